@@ -0,0 +1,33 @@
+use std::fmt::Display;
+
+/// Range of a cartesian coordinate in angstrom, for the models without lattice vectors.
+#[derive(Debug, Clone, Copy)]
+pub struct CartesianCoordRange(f64, f64);
+
+impl Display for CartesianCoordRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {})", self.0, self.1)
+    }
+}
+
+impl CartesianCoordRange {
+    /// # Panics
+    /// Panics if `lower > higher`
+    pub fn new(lower: f64, higher: f64) -> Self {
+        assert!(lower <= higher);
+        Self(lower, higher)
+    }
+    /// The whole axis.
+    pub fn unbounded() -> Self {
+        Self(f64::NEG_INFINITY, f64::INFINITY)
+    }
+    pub fn is_in_range(&self, value: f64) -> bool {
+        self.0 <= value && self.1 >= value
+    }
+    pub fn min(&self) -> f64 {
+        self.0
+    }
+    pub fn max(&self) -> f64 {
+        self.1
+    }
+}
@@ -1,3 +1,8 @@
+mod cartesian_coord_range;
 mod fractional_coord_range;
+mod region;
 
+pub use cartesian_coord_range::CartesianCoordRange;
 pub use fractional_coord_range::FractionalCoordRange;
+pub use region::Region;
+pub(crate) use region::ModelFrame;
@@ -0,0 +1,283 @@
+//! Regions of interest in a model, to restrict the atoms to scan.
+//! The basic shapes are combined by `and`, `or` and `complement`.
+//!
+//! With lattice vectors, the distances to a point or an atom are measured to the nearest periodic
+//! image. The cylinder and the half-space are taken in cartesian space as given.
+//! Without lattice vectors, the fractional coordinates are taken in the bounding box of the atoms.
+use nalgebra::{Matrix3, Point3, Vector3};
+
+use crate::data::BasicLatticeModel;
+
+#[derive(Debug, Clone)]
+pub enum Region {
+    /// Ball of `radius` around `center`, in angstrom
+    Sphere { center: Point3<f64>, radius: f64 },
+    /// Ball of `radius` around the atom at `atom_id` of the model
+    AroundAtom { atom_id: usize, radius: f64 },
+    /// Infinite cylinder of `radius` around the line through `origin` along `axis`
+    Cylinder {
+        origin: Point3<f64>,
+        axis: Vector3<f64>,
+        radius: f64,
+    },
+    /// The side of the plane through `point` that `normal` points to, the plane included
+    HalfSpace {
+        point: Point3<f64>,
+        normal: Vector3<f64>,
+    },
+    /// Fractional coordinate along `axis` (0, 1, 2 for a, b, c) from `lower` to `upper`,
+    /// wrapped into `[0, 1)`. The interval wraps around the cell when `lower > upper`,
+    /// e.g. `0.9` to `0.1`. An `upper` on the cell boundary stays `1.0`, and an interval
+    /// of one cell or longer covers the whole axis.
+    FractionalInterval { axis: usize, lower: f64, upper: f64 },
+    /// Inside all of the regions
+    All(Vec<Region>),
+    /// Inside any of the regions
+    Any(Vec<Region>),
+    /// Outside the region
+    Complement(Box<Region>),
+}
+
+impl Region {
+    pub fn sphere(center: Point3<f64>, radius: f64) -> Self {
+        Self::Sphere { center, radius }
+    }
+    pub fn around_atom(atom_id: usize, radius: f64) -> Self {
+        Self::AroundAtom { atom_id, radius }
+    }
+    pub fn cylinder(origin: Point3<f64>, axis: Vector3<f64>, radius: f64) -> Self {
+        Self::Cylinder {
+            origin,
+            axis,
+            radius,
+        }
+    }
+    pub fn half_space(point: Point3<f64>, normal: Vector3<f64>) -> Self {
+        Self::HalfSpace { point, normal }
+    }
+    /// # Panics
+    /// Panics if `axis` is not 0, 1 or 2.
+    pub fn fractional_interval(axis: usize, lower: f64, upper: f64) -> Self {
+        assert!(axis < 3, "Invalid fractional axis: {axis}");
+        if upper - lower >= 1.0 {
+            return Self::FractionalInterval {
+                axis,
+                lower: 0.0,
+                upper: 1.0,
+            };
+        }
+        let (lower, wrapped_upper) = (lower.rem_euclid(1.0), upper.rem_euclid(1.0));
+        // `upper` on the cell boundary ends the interval there, instead of wrapping to `0.0`
+        let upper = if wrapped_upper == 0.0 && upper > lower {
+            1.0
+        } else {
+            wrapped_upper
+        };
+        Self::FractionalInterval { axis, lower, upper }
+    }
+    /// Inside both regions.
+    pub fn and(self, other: Region) -> Self {
+        match self {
+            Self::All(mut regions) => {
+                regions.push(other);
+                Self::All(regions)
+            }
+            region => Self::All(vec![region, other]),
+        }
+    }
+    /// Inside either region.
+    pub fn or(self, other: Region) -> Self {
+        match self {
+            Self::Any(mut regions) => {
+                regions.push(other);
+                Self::Any(regions)
+            }
+            region => Self::Any(vec![region, other]),
+        }
+    }
+    /// Outside this region.
+    pub fn complement(self) -> Self {
+        match self {
+            Self::Complement(region) => *region,
+            region => Self::Complement(Box::new(region)),
+        }
+    }
+    /// Whether the cartesian position is inside the region of the model.
+    pub fn contains(&self, position: &Point3<f64>, lattice_model: &BasicLatticeModel) -> bool {
+        self.contains_in(position, &ModelFrame::new(lattice_model))
+    }
+    pub(crate) fn contains_in(&self, position: &Point3<f64>, frame: &ModelFrame) -> bool {
+        match self {
+            Region::Sphere { center, radius } => {
+                frame.shortest(position - center).norm() <= *radius
+            }
+            Region::AroundAtom { atom_id, radius } => frame
+                .lattice_model
+                .atoms()
+                .get(*atom_id)
+                .is_some_and(|atom| {
+                    frame.shortest(position - atom.cartesian_coord()).norm() <= *radius
+                }),
+            Region::Cylinder {
+                origin,
+                axis,
+                radius,
+            } => {
+                let d = position - origin;
+                let along = d.dot(axis) / axis.norm_squared();
+                (d - axis * along).norm() <= *radius
+            }
+            Region::HalfSpace { point, normal } => (position - point).dot(normal) >= 0.0,
+            Region::FractionalInterval { axis, lower, upper } => {
+                let frac = frame.fractional_coord(position)[*axis].rem_euclid(1.0);
+                if lower <= upper {
+                    *lower <= frac && frac <= *upper
+                } else {
+                    frac >= *lower || frac <= *upper
+                }
+            }
+            Region::All(regions) => regions.iter().all(|r| r.contains_in(position, frame)),
+            Region::Any(regions) => regions.iter().any(|r| r.contains_in(position, frame)),
+            Region::Complement(region) => !region.contains_in(position, frame),
+        }
+    }
+}
+
+/// The conversions to the fractional coordinates of a model, prepared once for many positions.
+pub(crate) struct ModelFrame<'a> {
+    lattice_model: &'a BasicLatticeModel,
+    /// Lattice vectors as columns and the inverse, if periodic
+    lattice: Option<(Matrix3<f64>, Matrix3<f64>)>,
+    /// Lower corner and extent of the atoms, if not periodic
+    bounding_box: Option<(Point3<f64>, Vector3<f64>)>,
+}
+
+impl<'a> ModelFrame<'a> {
+    pub(crate) fn new(lattice_model: &'a BasicLatticeModel) -> Self {
+        let lattice = lattice_model
+            .lattice_vectors()
+            .map(|lattice_vectors| (*lattice_vectors.data(), lattice_vectors.mat_cart_to_frac()));
+        let bounding_box = match lattice {
+            Some(_) => None,
+            None => lattice_model
+                .bounding_box()
+                .map(|(lower, upper)| (lower, upper - lower)),
+        };
+        Self {
+            lattice_model,
+            lattice,
+            bounding_box,
+        }
+    }
+    /// The fractional coordinates, in the bounding box of the atoms if not periodic.
+    /// A flat extent maps to 0.0.
+    pub(crate) fn fractional_coord(&self, position: &Point3<f64>) -> Vector3<f64> {
+        match (self.lattice, self.bounding_box) {
+            (Some((_, to_frac)), _) => to_frac * position.coords,
+            (None, Some((lower, extent))) => {
+                (position - lower).zip_map(
+                    &extent,
+                    |d, extent| if extent > 0.0 { d / extent } else { 0.0 },
+                )
+            }
+            (None, None) => Vector3::zeros(),
+        }
+    }
+    /// The vector to the nearest periodic image, by rounding the fractional components.
+    fn shortest(&self, vector: Vector3<f64>) -> Vector3<f64> {
+        match self.lattice {
+            Some((to_cart, to_frac)) => {
+                let frac = to_frac * vector;
+                to_cart * frac.map(|x| x - x.round())
+            }
+            None => vector,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::{Matrix3, Point3, Vector3};
+
+    use crate::data::{Atom, BasicLatticeModel, LatticeVectors};
+
+    use super::Region;
+
+    #[test]
+    fn composed_regions() {
+        // A row of atoms along a in a 10 Å cubic cell, at x = 0.5, 1.5, ..., 9.5
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal_element(10.0));
+        let atoms: Vec<Atom> = (0..10)
+            .map(|i| {
+                Atom::new_builder()
+                    .with_symbol("Cu")
+                    .with_atomic_number(29)
+                    .with_index(i)
+                    .with_coord(&Point3::new(0.5 + i as f64, 5.0, 5.0 + 0.1 * i as f64))
+                    .ready()
+                    .build()
+            })
+            .collect();
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        let ids = |region: &Region| -> Vec<usize> {
+            model
+                .region_filter(region)
+                .iter()
+                .map(|atom| atom.index())
+                .collect()
+        };
+        // Across the cell boundary
+        assert_eq!(ids(&Region::fractional_interval(0, 0.9, 0.1)), vec![0, 9]);
+        assert_eq!(ids(&Region::around_atom(0, 1.4)), vec![0, 1, 9]);
+        assert_eq!(
+            ids(&Region::sphere(Point3::new(0.0, 5.0, 5.0), 1.1)),
+            vec![0, 9]
+        );
+        let above = Region::half_space(Point3::new(0.0, 0.0, 5.75), Vector3::z());
+        assert_eq!(ids(&above), vec![8, 9]);
+        let column = Region::cylinder(Point3::new(3.0, 5.0, 0.0), Vector3::z(), 1.0);
+        assert_eq!(ids(&column), vec![2, 3]);
+        assert_eq!(ids(&column.clone().or(above.clone())), vec![2, 3, 8, 9]);
+        assert_eq!(ids(&above.clone().and(column.clone())), Vec::<usize>::new());
+        assert_eq!(ids(&column.complement()).len(), 8);
+    }
+
+    #[test]
+    fn intervals_to_cell_boundary() {
+        // Atoms at frac 0.0, 0.5 and 0.75 along a
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal_element(10.0));
+        let atoms: Vec<Atom> = [0.0, 5.0, 7.5]
+            .iter()
+            .enumerate()
+            .map(|(i, &x)| {
+                Atom::new_builder()
+                    .with_symbol("Cu")
+                    .with_atomic_number(29)
+                    .with_index(i)
+                    .with_coord(&Point3::new(x, 5.0, 5.0))
+                    .ready()
+                    .build()
+            })
+            .collect();
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        let ids = |region: &Region| -> Vec<usize> {
+            model
+                .region_filter(region)
+                .iter()
+                .map(|atom| atom.index())
+                .collect()
+        };
+        // The whole axis
+        assert_eq!(
+            ids(&Region::fractional_interval(0, 0.0, 1.0)),
+            vec![0, 1, 2]
+        );
+        assert_eq!(
+            ids(&Region::fractional_interval(0, -0.5, 0.5)),
+            vec![0, 1, 2]
+        );
+        // Up to the boundary, without wrapping to the atom at 0.0
+        assert_eq!(ids(&Region::fractional_interval(0, 0.5, 1.0)), vec![1, 2]);
+        assert_eq!(ids(&Region::fractional_interval(0, 0.6, 1.0)), vec![2]);
+    }
+}
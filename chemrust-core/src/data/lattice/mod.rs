@@ -1,12 +1,16 @@
 use std::{collections::HashSet, fmt::Display};
 
-use super::custom_data_type::FractionalCoordRange;
+use nalgebra::{Matrix3, Point3, Vector3};
+
+use super::custom_data_type::{CartesianCoordRange, FractionalCoordRange, ModelFrame, Region};
 use super::Atom;
 
 mod lattice_vectors;
 mod reciprocal_space;
+mod surface;
 
 pub use lattice_vectors::LatticeVectors;
+pub use surface::{SurfaceDetector, SurfaceSide};
 
 #[derive(Debug, Clone)]
 pub struct BasicLatticeModel {
@@ -69,18 +73,19 @@ impl BasicLatticeModel {
             .map(|(_, symbol)| symbol.to_string())
             .collect()
     }
-    /// Select atoms by the given ranges of x, y, z in fractional coordinates
+    /// Select atoms by the given ranges of x, y, z in fractional coordinates.
+    /// Without lattice vectors, the fractional coordinates are taken in the bounding box of the atoms.
     pub fn xyz_range_filter(
         &self,
         x_range: FractionalCoordRange,
         y_range: FractionalCoordRange,
         z_range: FractionalCoordRange,
     ) -> Vec<Atom> {
+        let frame = ModelFrame::new(self);
         self.atoms
             .iter()
             .filter(|&atom| {
-                let frac_coord = self.lattice_vectors.as_ref().unwrap().mat_cart_to_frac()
-                    * atom.cartesian_coord();
+                let frac_coord = frame.fractional_coord(&atom.cartesian_coord());
                 x_range.is_in_range(frac_coord.x)
                     && y_range.is_in_range(frac_coord.y)
                     && z_range.is_in_range(frac_coord.z)
@@ -88,6 +93,86 @@ impl BasicLatticeModel {
             .cloned()
             .collect::<Vec<Atom>>()
     }
+    /// Select atoms by the given ranges of x, y, z in cartesian coordinates.
+    pub fn cartesian_range_filter(
+        &self,
+        x_range: CartesianCoordRange,
+        y_range: CartesianCoordRange,
+        z_range: CartesianCoordRange,
+    ) -> Vec<Atom> {
+        self.atoms
+            .iter()
+            .filter(|&atom| {
+                let coord = atom.cartesian_coord();
+                x_range.is_in_range(coord.x)
+                    && y_range.is_in_range(coord.y)
+                    && z_range.is_in_range(coord.z)
+            })
+            .cloned()
+            .collect::<Vec<Atom>>()
+    }
+    /// Select atoms inside the region.
+    pub fn region_filter(&self, region: &Region) -> Vec<Atom> {
+        let frame = ModelFrame::new(self);
+        self.atoms
+            .iter()
+            .filter(|&atom| region.contains_in(&atom.cartesian_coord(), &frame))
+            .cloned()
+            .collect::<Vec<Atom>>()
+    }
+    /// The lower and upper corners of the cartesian coordinates, `None` if there is no atom.
+    pub fn bounding_box(&self) -> Option<(Point3<f64>, Point3<f64>)> {
+        let first = self.atoms.first()?.cartesian_coord();
+        Some(
+            self.atoms
+                .iter()
+                .fold((first, first), |(lower, upper), atom| {
+                    let coord = atom.cartesian_coord();
+                    (lower.inf(&coord), upper.sup(&coord))
+                }),
+        )
+    }
+    /// The orthorhombic box around the atoms with `vacuum` (in angstrom) on each side,
+    /// and the translation moving the atoms into the box.
+    /// The models of the same molecule with new atoms attached can share this box,
+    /// as long as the new atoms stay within the vacuum.
+    pub fn vacuum_box(&self, vacuum: f64) -> (LatticeVectors, Vector3<f64>) {
+        let (lower, upper) = self
+            .bounding_box()
+            .unwrap_or((Point3::origin(), Point3::origin()));
+        let lengths = (upper - lower).add_scalar(2.0 * vacuum);
+        let lattice_vectors = LatticeVectors::new(Matrix3::from_diagonal(&lengths));
+        (lattice_vectors, Vector3::repeat(vacuum) - lower.coords)
+    }
+    /// Put the model without lattice vectors into its `vacuum_box`, for the periodic codes
+    /// like CASTEP. The model with lattice vectors is returned unchanged.
+    pub fn boxed_in_vacuum(&self, vacuum: f64) -> Self {
+        if self.lattice_vectors.is_some() {
+            return self.clone();
+        }
+        let (lattice_vectors, translation) = self.vacuum_box(vacuum);
+        self.placed_in_box(lattice_vectors, &translation)
+    }
+    /// Set the lattice vectors and translate all atoms, e.g. by the `vacuum_box` of another model.
+    pub fn placed_in_box(
+        &self,
+        lattice_vectors: LatticeVectors,
+        translation: &Vector3<f64>,
+    ) -> Self {
+        let atoms = self
+            .atoms
+            .iter()
+            .map(|atom| {
+                let mut atom = atom.clone();
+                atom.set_cartesian_coord(atom.cartesian_coord() + translation);
+                atom
+            })
+            .collect();
+        Self {
+            lattice_vectors: Some(lattice_vectors),
+            atoms,
+        }
+    }
 }
 
 impl Display for BasicLatticeModel {
@@ -106,3 +191,49 @@ impl Display for BasicLatticeModel {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use nalgebra::Point3;
+
+    use crate::data::{
+        custom_data_type::{CartesianCoordRange, FractionalCoordRange},
+        Atom,
+    };
+
+    use super::BasicLatticeModel;
+
+    #[test]
+    fn molecule_in_box() {
+        // A linear CO2 along x, without lattice vectors
+        let atoms: Vec<Atom> = [("O", 8, -1.16), ("C", 6, 0.0), ("O", 8, 1.16)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(symbol, atomic_number, x))| {
+                Atom::new_builder()
+                    .with_symbol(symbol)
+                    .with_atomic_number(atomic_number)
+                    .with_index(i)
+                    .with_coord(&Point3::new(x, 0.0, 0.0))
+                    .ready()
+                    .build()
+            })
+            .collect();
+        let model = BasicLatticeModel::new(&None, &atoms);
+        let full = FractionalCoordRange::new(0.0, 1.0);
+        let upper_half = model.xyz_range_filter(FractionalCoordRange::new(0.5, 1.0), full, full);
+        assert_eq!(upper_half.len(), 2);
+        let center = model.cartesian_range_filter(
+            CartesianCoordRange::new(-0.5, 0.5),
+            CartesianCoordRange::unbounded(),
+            CartesianCoordRange::unbounded(),
+        );
+        assert_eq!(center.len(), 1);
+        assert_eq!(center[0].symbol(), "C");
+        let boxed = model.boxed_in_vacuum(10.0);
+        let lengths = boxed.lattice_vectors().unwrap().data().diagonal();
+        assert!((lengths.x - 22.32).abs() < 1e-9);
+        assert!((lengths.y - 20.0).abs() < 1e-9);
+        assert!((boxed.atoms()[0].cartesian_coord() - Point3::new(10.0, 10.0, 10.0)).norm() < 1e-9);
+    }
+}
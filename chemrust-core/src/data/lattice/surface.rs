@@ -0,0 +1,255 @@
+//! Detect the atoms accessible from the vacuum of a slab model, by a rolling probe.
+//! The surface normal is taken along `a x b`, so the vacuum layer is expected along the `c` axis.
+//! 1. A probe sphere is dropped along the normal at the points of a grid over the `ab` plane.
+//! 2. At each grid point, the probe rests on the atom it touches first, considering the
+//!    periodic images in the `ab` plane.
+//! 3. Every atom touched by the probe is accessible from the vacuum.
+//!
+//! Without lattice vectors, the normal is `+z` and the grid covers the xy extent of the atoms.
+use std::collections::BTreeSet;
+
+use castep_periodic_table::{data::ELEMENT_TABLE, element::LookupElement};
+use nalgebra::{Matrix2, Point3, Vector2, Vector3};
+
+use super::BasicLatticeModel;
+use crate::data::Atom;
+
+/// Which side of the slab to look at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SurfaceSide {
+    /// Facing the `+normal` direction
+    Top,
+    /// Facing the `-normal` direction
+    Bottom,
+    Both,
+}
+
+#[derive(Debug, Clone)]
+pub struct SurfaceDetector<'a> {
+    lattice_model: &'a BasicLatticeModel,
+    /// Radius of the probe sphere, in angstrom.
+    probe_radius: f64,
+    /// Spacing of the grid points to drop the probe, in angstrom.
+    grid_spacing: f64,
+}
+
+/// The atom positions decomposed into the height along the surface normal
+/// and the in-plane coordinates.
+struct SlabFrame {
+    normal: Vector3<f64>,
+    /// In-plane basis; `a` and `b` for a lattice, `x` and `y` otherwise.
+    plane_basis: [Vector3<f64>; 2],
+    periodic: bool,
+}
+
+impl SlabFrame {
+    fn new(lattice_model: &BasicLatticeModel) -> Self {
+        match lattice_model.lattice_vectors() {
+            Some(lattice) => {
+                let a: Vector3<f64> = lattice.data().column(0).into();
+                let b: Vector3<f64> = lattice.data().column(1).into();
+                Self {
+                    normal: a.cross(&b).normalize(),
+                    plane_basis: [a, b],
+                    periodic: true,
+                }
+            }
+            None => Self {
+                normal: Vector3::z(),
+                plane_basis: [Vector3::x(), Vector3::y()],
+                periodic: false,
+            },
+        }
+    }
+    fn height(&self, point: &Point3<f64>) -> f64 {
+        point.coords.dot(&self.normal)
+    }
+    /// Coordinates of the projection of the point on the plane, in the in-plane basis.
+    fn plane_coord(&self, point: &Point3<f64>) -> Vector2<f64> {
+        let [u, v] = self.plane_basis;
+        let gram = Matrix2::new(u.dot(&u), u.dot(&v), v.dot(&u), v.dot(&v));
+        let rhs = Vector2::new(point.coords.dot(&u), point.coords.dot(&v));
+        gram.try_inverse().unwrap() * rhs
+    }
+    fn plane_vector(&self, plane_coord: &Vector2<f64>) -> Vector3<f64> {
+        self.plane_basis[0] * plane_coord.x + self.plane_basis[1] * plane_coord.y
+    }
+}
+
+impl<'a> SurfaceDetector<'a> {
+    pub fn new(lattice_model: &'a BasicLatticeModel) -> Self {
+        Self {
+            lattice_model,
+            probe_radius: 1.0,
+            grid_spacing: 0.25,
+        }
+    }
+    pub fn with_probe_radius(self, probe_radius: f64) -> Self {
+        Self {
+            probe_radius,
+            ..self
+        }
+    }
+    pub fn with_grid_spacing(self, grid_spacing: f64) -> Self {
+        Self {
+            grid_spacing,
+            ..self
+        }
+    }
+    /// Indices of the surface atoms in `atoms()` of the model, in ascending order.
+    pub fn surface_atom_ids(&self, side: SurfaceSide) -> Vec<usize> {
+        match side {
+            SurfaceSide::Top => self.probe_side(1.0).into_iter().collect(),
+            SurfaceSide::Bottom => self.probe_side(-1.0).into_iter().collect(),
+            SurfaceSide::Both => {
+                let mut ids = self.probe_side(1.0);
+                ids.extend(self.probe_side(-1.0));
+                ids.into_iter().collect()
+            }
+        }
+    }
+    pub fn surface_atoms(&self, side: SurfaceSide) -> Vec<Atom> {
+        let atoms = self.lattice_model.atoms();
+        self.surface_atom_ids(side)
+            .into_iter()
+            .map(|i| atoms[i].clone())
+            .collect()
+    }
+    /// Drop the probe from the side of `sign * normal`, returns the ids of the touched atoms.
+    fn probe_side(&self, sign: f64) -> BTreeSet<usize> {
+        let atoms = self.lattice_model.atoms();
+        let frame = SlabFrame::new(self.lattice_model);
+        let radii: Vec<f64> = atoms
+            .iter()
+            .map(|atom| {
+                ELEMENT_TABLE
+                    .get_by_atomic_number(atom.atomic_number())
+                    .and_then(|element| element.covalent_radius())
+                    .unwrap_or(1.0)
+            })
+            .collect();
+        let heights: Vec<f64> = atoms
+            .iter()
+            .map(|atom| sign * frame.height(&atom.cartesian_coord()))
+            .collect();
+        let plane_coords: Vec<Vector2<f64>> = atoms
+            .iter()
+            .map(|atom| frame.plane_coord(&atom.cartesian_coord()))
+            .collect();
+        let mut touched: BTreeSet<usize> = BTreeSet::new();
+        self.grid_points(&frame, &plane_coords, &radii)
+            .iter()
+            .for_each(|grid_point| {
+                let mut resting: Option<(usize, f64)> = None;
+                atoms.iter().enumerate().for_each(|(i, _)| {
+                    let contact_radius = radii[i] + self.probe_radius;
+                    let lateral = lateral_distance(&frame, &(plane_coords[i] - grid_point));
+                    if lateral >= contact_radius {
+                        return;
+                    }
+                    let contact_height =
+                        heights[i] + (contact_radius.powi(2) - lateral.powi(2)).sqrt();
+                    let is_higher = match resting {
+                        Some((_, h)) => contact_height > h,
+                        None => true,
+                    };
+                    if is_higher {
+                        resting = Some((i, contact_height));
+                    }
+                });
+                if let Some((i, _)) = resting {
+                    touched.insert(i);
+                }
+            });
+        touched
+    }
+    /// The grid over the cell in the `ab` plane, or over the extent of the atoms when non-periodic.
+    fn grid_points(
+        &self,
+        frame: &SlabFrame,
+        plane_coords: &[Vector2<f64>],
+        radii: &[f64],
+    ) -> Vec<Vector2<f64>> {
+        let (lower, upper) = if frame.periodic {
+            (Vector2::zeros(), Vector2::new(1.0, 1.0))
+        } else {
+            let margin = radii.iter().cloned().fold(0.0, f64::max) + self.probe_radius;
+            let lower = plane_coords
+                .iter()
+                .fold(Vector2::repeat(f64::INFINITY), |acc, p| acc.inf(p))
+                .add_scalar(-margin);
+            let upper = plane_coords
+                .iter()
+                .fold(Vector2::repeat(f64::NEG_INFINITY), |acc, p| acc.sup(p))
+                .add_scalar(margin);
+            (lower, upper)
+        };
+        let steps: [usize; 2] = [0, 1].map(|i| {
+            let length = frame.plane_basis[i].norm() * (upper[i] - lower[i]);
+            ((length / self.grid_spacing).ceil() as usize).max(1)
+        });
+        (0..steps[0])
+            .flat_map(|i| {
+                (0..steps[1]).map(move |j| {
+                    Vector2::new(
+                        lower.x + (upper.x - lower.x) * i as f64 / steps[0] as f64,
+                        lower.y + (upper.y - lower.y) * j as f64 / steps[1] as f64,
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+/// In-plane distance of the displacement, by the minimum image when periodic.
+fn lateral_distance(frame: &SlabFrame, plane_diff: &Vector2<f64>) -> f64 {
+    if !frame.periodic {
+        return frame.plane_vector(plane_diff).norm();
+    }
+    let wrapped = plane_diff.map(|v| v - v.round());
+    // Rounding is not always the minimum image for oblique cells, check the neighbouring images.
+    (-1..=1)
+        .flat_map(|i| (-1..=1).map(move |j| Vector2::new(i as f64, j as f64)))
+        .map(|shift| frame.plane_vector(&(wrapped + shift)).norm())
+        .fold(f64::INFINITY, f64::min)
+}
+
+impl BasicLatticeModel {
+    /// Select the atoms accessible from the vacuum on the given side of the slab,
+    /// by a rolling probe of 1.0 angstrom radius. See `SurfaceDetector` for other settings.
+    pub fn surface_filter(&self, side: SurfaceSide) -> Vec<Atom> {
+        SurfaceDetector::new(self).surface_atoms(side)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::{Matrix3, Point3};
+
+    use crate::data::{Atom, BasicLatticeModel, LatticeVectors};
+
+    use super::{SurfaceDetector, SurfaceSide};
+
+    #[test]
+    fn slab_surfaces() {
+        // A simple cubic Cu slab of three layers in a 2.5 x 2.5 x 20 cell
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal(&nalgebra::Vector3::new(
+            2.5, 2.5, 20.0,
+        )));
+        let atoms: Vec<Atom> = (0..3)
+            .map(|i| {
+                Atom::new_builder()
+                    .with_symbol("Cu")
+                    .with_index(i)
+                    .with_coord(&Point3::new(0.0, 0.0, 5.0 + 2.5 * i as f64))
+                    .ready()
+                    .build()
+            })
+            .collect();
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        let detector = SurfaceDetector::new(&model);
+        assert_eq!(detector.surface_atom_ids(SurfaceSide::Top), vec![2]);
+        assert_eq!(detector.surface_atom_ids(SurfaceSide::Bottom), vec![0]);
+        assert_eq!(detector.surface_atom_ids(SurfaceSide::Both), vec![0, 2]);
+    }
+}
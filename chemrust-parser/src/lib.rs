@@ -2,5 +2,5 @@
 mod model_file;
 mod parser_combos;
 
-pub use model_file::CellParser;
+pub use model_file::{CellParser, XyzParseError, XyzParser};
 pub use parser_combos::*;
@@ -1,5 +1,7 @@
 mod cell_file_parser;
+mod xyz_file_parser;
 // mod msi_file_parser;
 
 // pub use msi_file_parser::MsiParser;
 pub use cell_file_parser::CellParser;
+pub use xyz_file_parser::{XyzParseError, XyzParser};
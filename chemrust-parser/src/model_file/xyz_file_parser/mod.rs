@@ -0,0 +1,122 @@
+//! Parse the `.xyz` format: the number of atoms, a comment line, then one `symbol x y z` line
+//! per atom in cartesian coordinates (angstrom).
+use std::fmt::Display;
+
+use castep_periodic_table::{data::ELEMENT_TABLE, element::LookupElement};
+use chemrust_core::data::Atom;
+use nalgebra::Point3;
+use nom::{
+    branch::alt,
+    character::complete::{alpha1, multispace0, one_of},
+    combinator::{opt, recognize},
+    sequence::{preceded, tuple},
+    IResult,
+};
+
+use crate::{decimal, float};
+
+#[derive(Debug)]
+pub struct XyzParseError(String);
+
+impl Display for XyzParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid xyz file: {}", self.0)
+    }
+}
+
+impl std::error::Error for XyzParseError {}
+
+#[derive(Debug)]
+pub struct XyzParser<'a> {
+    input: &'a str,
+}
+
+impl<'a> XyzParser<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self { input }
+    }
+    /// A coordinate written as a float or a plain integer, e.g. `0.757`, `0`, `-1e-3`.
+    fn coordinate(input: &str) -> IResult<&str, &str> {
+        recognize(preceded(opt(one_of("+-")), alt((float, decimal))))(input)
+    }
+    fn atom_line(input: &str) -> IResult<&str, (&str, &str, &str, &str)> {
+        tuple((
+            preceded(multispace0, alpha1),
+            preceded(multispace0, Self::coordinate),
+            preceded(multispace0, Self::coordinate),
+            preceded(multispace0, Self::coordinate),
+        ))(input)
+    }
+    /// Atoms in the order of the file, indexed from 0.
+    pub fn parse_atoms(&self) -> Result<Vec<Atom>, XyzParseError> {
+        let mut lines = self.input.lines();
+        let number_of_atoms = lines
+            .next()
+            .and_then(|line| line.trim().parse::<usize>().ok())
+            .ok_or(XyzParseError("missing number of atoms".into()))?;
+        // Comment line
+        lines.next();
+        let atoms = lines
+            .filter(|line| !line.trim().is_empty())
+            .take(number_of_atoms)
+            .enumerate()
+            .map(|(i, line)| {
+                let (_, (symbol, x, y, z)) = Self::atom_line(line)
+                    .map_err(|_| XyzParseError(format!("cannot parse line: {line}")))?;
+                let atomic_number = ELEMENT_TABLE
+                    .get_by_symbol(symbol)
+                    .ok_or(XyzParseError(format!("unknown element: {symbol}")))?
+                    .atomic_number();
+                let coord = [x, y, z]
+                    .iter()
+                    .map(|v| {
+                        v.parse::<f64>().map_err(|_| {
+                            XyzParseError(format!("invalid coordinate {v} in line: {line}"))
+                        })
+                    })
+                    .collect::<Result<Vec<f64>, XyzParseError>>()?;
+                Ok(Atom::new_builder()
+                    .with_symbol(symbol)
+                    .with_atomic_number(atomic_number)
+                    .with_coord(&Point3::from_slice(&coord))
+                    .with_index(i)
+                    .ready()
+                    .build())
+            })
+            .collect::<Result<Vec<Atom>, XyzParseError>>()?;
+        if atoms.len() != number_of_atoms {
+            return Err(XyzParseError(format!(
+                "expected {number_of_atoms} atoms, found {}",
+                atoms.len()
+            )));
+        }
+        Ok(atoms)
+    }
+}
+
+#[cfg(test)]
+mod xyz_test {
+    use super::XyzParser;
+
+    #[test]
+    fn xyz_parser() {
+        let text = "3\nwater\nO 0.0 0.0 0.0\nH 0.757 0.586 0.0\nH -0.757 0.586 0.0\n";
+        let atoms = XyzParser::new(text).parse_atoms().unwrap();
+        assert_eq!(atoms.len(), 3);
+        assert_eq!(atoms[1].symbol(), "H");
+        assert!((atoms[2].cartesian_coord().x + 0.757).abs() < 1e-12);
+        assert!(XyzParser::new("2\n\nO 0.0 0.0 0.0\n")
+            .parse_atoms()
+            .is_err());
+        // Plain integers and exponents without a decimal point
+        let atoms = XyzParser::new("2\n\nO 0 0 0\nH -1e-3 1E2 +2\n")
+            .parse_atoms()
+            .unwrap();
+        assert_eq!(atoms[0].cartesian_coord(), nalgebra::Point3::origin());
+        assert!((atoms[1].cartesian_coord().x + 1e-3).abs() < 1e-12);
+        assert!((atoms[1].cartesian_coord().y - 100.0).abs() < 1e-12);
+        assert!((atoms[1].cartesian_coord().z - 2.0).abs() < 1e-12);
+        // Malformed numbers are errors, not panics
+        assert!(XyzParser::new("1\n\nH 1.e 0 0\n").parse_atoms().is_err());
+    }
+}
@@ -0,0 +1,289 @@
+//! Molecular adsorbates to mount at the found sites, e.g., CO, OH, H2O, NH3 or O2.
+//! The molecule is placed with its anchor atom at the site, and oriented so that the axis
+//! from the anchor to the centroid of the other atoms points along the outward normal of the site.
+//! Rotations about the normal can be enumerated, and the orientations clashing with the host are rejected.
+use std::f64::consts::PI;
+
+use chemrust_core::data::{Atom, BasicLatticeModel};
+use chemrust_parser::{CellParser, XyzParseError, XyzParser};
+use nalgebra::{Point3, Rotation3, Unit, UnitVector3, Vector3};
+
+use super::algorithm::{
+    ideal_bondlength, BondingArc, BondingCircle, BondingSphere, CoordinationPoint,
+};
+
+/// A site where the anchor atom of the adsorbate can be placed.
+pub trait AdsorptionSite {
+    /// Position of the anchor atom.
+    fn anchor_position(&self) -> Point3<f64>;
+    /// Direction pointing away from the host at the site.
+    fn outward_normal(&self, lattice_model: &BasicLatticeModel) -> UnitVector3<f64>;
+}
+
+impl AdsorptionSite for BondingSphere {
+    fn anchor_position(&self) -> Point3<f64> {
+        self.mounting_point()
+    }
+    fn outward_normal(&self, _lattice_model: &BasicLatticeModel) -> UnitVector3<f64> {
+        self.mounting_direction()
+    }
+}
+
+impl AdsorptionSite for BondingCircle {
+    fn anchor_position(&self) -> Point3<f64> {
+        self.circle().topmost_point()
+    }
+    fn outward_normal(&self, _lattice_model: &BasicLatticeModel) -> UnitVector3<f64> {
+        Unit::try_new(self.anchor_position() - self.circle().center, 1e-6)
+            .unwrap_or(self.circle().normal)
+    }
+}
+
+impl AdsorptionSite for BondingArc {
+    fn anchor_position(&self) -> Point3<f64> {
+        self.representative()
+    }
+    fn outward_normal(&self, _lattice_model: &BasicLatticeModel) -> UnitVector3<f64> {
+        Unit::try_new(self.representative() - self.arc().center(), 1e-6)
+            .unwrap_or(self.arc().normal())
+    }
+}
+
+impl AdsorptionSite for CoordinationPoint {
+    fn anchor_position(&self) -> Point3<f64> {
+        self.coord()
+    }
+    /// From the centroid of the connecting atoms to the point, or `+z` if the point is at the centroid.
+    fn outward_normal(&self, lattice_model: &BasicLatticeModel) -> UnitVector3<f64> {
+        let atoms = lattice_model.atoms();
+        let images = self.connecting_atoms();
+        if images.is_empty() {
+            return Vector3::z_axis();
+        }
+        let centroid = images
+            .iter()
+            .map(|image| {
+                let offset = image.offset().map(|v| v as f64);
+                let translation = lattice_model
+                    .lattice_vectors()
+                    .map(|lattice| lattice.data() * Vector3::from(offset))
+                    .unwrap_or_else(Vector3::zeros);
+                atoms[image.atom_id()].cartesian_coord().coords + translation
+            })
+            .sum::<Vector3<f64>>()
+            / images.len() as f64;
+        Unit::try_new(self.coord().coords - centroid, 1e-6).unwrap_or(Vector3::z_axis())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MolecularAdsorbate {
+    atoms: Vec<Atom>,
+    /// Index of the atom bonding to the site
+    anchor: usize,
+    /// Number of evenly spaced rotations about the outward normal
+    rotations: usize,
+    /// Other atoms closer to a host atom than `clash_factor * ideal_bondlength` are clashes.
+    clash_factor: f64,
+}
+
+impl MolecularAdsorbate {
+    /// # Panics
+    /// Panics if `anchor` is out of the range of `atoms`.
+    pub fn new(atoms: &[Atom], anchor: usize) -> Self {
+        assert!(
+            anchor < atoms.len(),
+            "Anchor atom {anchor} out of {} atoms",
+            atoms.len()
+        );
+        Self {
+            atoms: atoms.to_vec(),
+            anchor,
+            rotations: 1,
+            clash_factor: 0.8,
+        }
+    }
+    /// A single atom, the same as mounting by element.
+    pub fn single_atom(element_symbol: &str) -> Self {
+        let atom = Atom::new_builder()
+            .with_index(0)
+            .with_symbol(element_symbol)
+            .with_coord(&Point3::origin())
+            .ready()
+            .build();
+        Self::new(&[atom], 0)
+    }
+    pub fn from_xyz(xyz_text: &str, anchor: usize) -> Result<Self, XyzParseError> {
+        let atoms = XyzParser::new(xyz_text).parse_atoms()?;
+        Ok(Self::new(&atoms, anchor))
+    }
+    /// Use the atoms of a `.cell` fragment; the lattice vectors are ignored.
+    pub fn from_cell(cell_text: &str, anchor: usize) -> Self {
+        let fragment = CellParser::new(cell_text)
+            .to_lattice_cart()
+            .to_positions()
+            .build_lattice();
+        Self::new(fragment.atoms(), anchor)
+    }
+    /// Enumerate `rotations` evenly spaced orientations about the outward normal.
+    pub fn with_rotations(self, rotations: usize) -> Self {
+        Self {
+            rotations: rotations.max(1),
+            ..self
+        }
+    }
+    pub fn with_clash_factor(self, clash_factor: f64) -> Self {
+        Self {
+            clash_factor,
+            ..self
+        }
+    }
+
+    pub fn atoms(&self) -> &[Atom] {
+        self.atoms.as_ref()
+    }
+
+    pub fn anchor(&self) -> &Atom {
+        &self.atoms[self.anchor]
+    }
+
+    pub fn rotations(&self) -> usize {
+        self.rotations
+    }
+
+    pub fn is_single_atom(&self) -> bool {
+        self.atoms.len() == 1
+    }
+    /// Direction from the anchor to the centroid of the other atoms, `+z` if not defined.
+    fn molecular_axis(&self) -> UnitVector3<f64> {
+        let anchor = self.anchor().cartesian_coord();
+        let others: Vec<Vector3<f64>> = self
+            .atoms
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != self.anchor)
+            .map(|(_, atom)| atom.cartesian_coord() - anchor)
+            .collect();
+        if others.is_empty() {
+            return Vector3::z_axis();
+        }
+        let centroid = others.iter().sum::<Vector3<f64>>() / others.len() as f64;
+        Unit::try_new(centroid, 1e-6).unwrap_or(Vector3::z_axis())
+    }
+    /// The clash-free orientations at the site, with the index of the rotation about the normal.
+    /// The new atoms are indexed after the atoms of the model.
+    pub fn orientations<T: AdsorptionSite>(
+        &self,
+        site: &T,
+        lattice_model: &BasicLatticeModel,
+    ) -> Vec<(usize, Vec<Atom>)> {
+        self.orientations_at(
+            &site.anchor_position(),
+            &site.outward_normal(lattice_model),
+            lattice_model,
+        )
+    }
+    /// The clash-free orientations with the anchor at `position` and the axis along `normal`.
+    pub fn orientations_at(
+        &self,
+        position: &Point3<f64>,
+        normal: &UnitVector3<f64>,
+        lattice_model: &BasicLatticeModel,
+    ) -> Vec<(usize, Vec<Atom>)> {
+        let axis = self.molecular_axis();
+        // `rotation_between` is undefined for antiparallel vectors, turn over about a perpendicular axis
+        let align = Rotation3::rotation_between(&axis, normal).unwrap_or_else(|| {
+            let perpendicular = axis.cross(&Vector3::x());
+            let perpendicular = if perpendicular.norm() < 1e-6 {
+                axis.cross(&Vector3::y())
+            } else {
+                perpendicular
+            };
+            Rotation3::from_axis_angle(&Unit::new_normalize(perpendicular), PI)
+        });
+        let anchor = self.anchor().cartesian_coord();
+        let start_index = lattice_model.number_of_atoms();
+        (0..self.rotations)
+            .map(|k| {
+                let spin =
+                    Rotation3::from_axis_angle(normal, 2.0 * PI * k as f64 / self.rotations as f64);
+                let atoms: Vec<Atom> = self
+                    .atoms
+                    .iter()
+                    .enumerate()
+                    .map(|(i, atom)| {
+                        let mut new_atom = atom.clone();
+                        let coord = position + spin * align * (atom.cartesian_coord() - anchor);
+                        new_atom.set_cartesian_coord(coord);
+                        new_atom.set_index(start_index + i);
+                        new_atom
+                    })
+                    .collect();
+                (k, atoms)
+            })
+            .filter(|(_, atoms)| !self.is_clashed(atoms, lattice_model))
+            .collect()
+    }
+    /// Whether any atom other than the anchor is too close to the host atoms,
+    /// by the minimum image when periodic.
+    fn is_clashed(&self, placed_atoms: &[Atom], lattice_model: &BasicLatticeModel) -> bool {
+        placed_atoms
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != self.anchor)
+            .any(|(_, atom)| {
+                lattice_model.atoms().iter().any(|host| {
+                    let diff = atom.cartesian_coord() - host.cartesian_coord();
+                    let distance = match lattice_model.lattice_vectors() {
+                        Some(lattice) => {
+                            let frac = lattice.mat_cart_to_frac() * diff;
+                            (lattice.data() * frac.map(|v| v - v.round())).norm()
+                        }
+                        None => diff.norm(),
+                    };
+                    distance
+                        < self.clash_factor
+                            * ideal_bondlength(host.atomic_number(), atom.atomic_number())
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chemrust_core::data::{Atom, BasicLatticeModel};
+    use nalgebra::{Point3, Vector3};
+
+    use super::MolecularAdsorbate;
+
+    #[test]
+    fn orient_molecule() {
+        // Water with the O anchor, the Hs pointing to +y in the fragment
+        let water = MolecularAdsorbate::from_xyz(
+            "3\nwater\nO 0.0 0.0 0.0\nH 0.757 0.586 0.0\nH -0.757 0.586 0.0\n",
+            0,
+        )
+        .unwrap()
+        .with_rotations(4);
+        let host = Atom::new_builder()
+            .with_symbol("Pt")
+            .with_index(0)
+            .with_coord(&Point3::origin())
+            .ready()
+            .build();
+        let model = BasicLatticeModel::new(&None, &[host]);
+        // On top of the Pt, the Hs point up for every rotation
+        let site = Point3::new(0.0, 0.0, 2.2);
+        let orientations = water.orientations_at(&site, &Vector3::z_axis(), &model);
+        assert_eq!(orientations.len(), 4);
+        orientations.iter().for_each(|(_, atoms)| {
+            assert_eq!(atoms[0].cartesian_coord(), site);
+            assert_eq!(atoms[0].index(), 1);
+            assert!((atoms[1].cartesian_coord().z - 2.786).abs() < 1e-6);
+        });
+        // Pointing down to the Pt closely, the Hs clash with the host
+        let site = Point3::new(0.0, 0.0, 1.0);
+        let orientations = water.orientations_at(&site, &(-Vector3::z_axis()), &model);
+        assert!(orientations.is_empty());
+    }
+}
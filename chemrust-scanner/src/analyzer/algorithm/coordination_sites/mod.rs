@@ -1,15 +1,19 @@
-use nalgebra::Point3;
+use nalgebra::{Point3, UnitVector3, Vector3};
 
 use crate::analyzer::geometry::{Arc, Circle, Sphere};
 
+use super::AtomImage;
+
 mod visualize;
 pub use visualize::Visualize;
 
-/// Sphere with location atom information
+/// Sphere with location atom information.
+/// The new atom is mounted along the `mounting_direction`, which defaults to `+z`.
 #[derive(Debug, Clone, Copy)]
 pub struct BondingSphere {
     sphere: Sphere,
     locating_atom_id: usize,
+    mounting_direction: UnitVector3<f64>,
 }
 
 impl BondingSphere {
@@ -17,9 +21,25 @@ impl BondingSphere {
         Self {
             sphere,
             locating_atom_id,
+            mounting_direction: Vector3::z_axis(),
+        }
+    }
+
+    pub fn with_mounting_direction(self, mounting_direction: UnitVector3<f64>) -> Self {
+        Self {
+            mounting_direction,
+            ..self
         }
     }
 
+    pub fn mounting_direction(&self) -> UnitVector3<f64> {
+        self.mounting_direction
+    }
+    /// The position of the new atom on the sphere.
+    pub fn mounting_point(&self) -> Point3<f64> {
+        self.sphere.point_at_surface(&self.mounting_direction)
+    }
+
     pub fn sphere(&self) -> Sphere {
         self.sphere
     }
@@ -33,11 +53,11 @@ impl BondingSphere {
 #[derive(Debug, Clone, Copy)]
 pub struct BondingCircle {
     circle: Circle,
-    connecting_atom_ids: [usize; 2],
+    connecting_atom_ids: [AtomImage; 2],
 }
 
 impl BondingCircle {
-    pub fn new(circle: Circle, connecting_atoms: [usize; 2]) -> Self {
+    pub fn new(circle: Circle, connecting_atoms: [AtomImage; 2]) -> Self {
         Self {
             circle,
             connecting_atom_ids: connecting_atoms,
@@ -48,7 +68,7 @@ impl BondingCircle {
         self.circle
     }
 
-    pub fn connecting_atoms(&self) -> [usize; 2] {
+    pub fn connecting_atoms(&self) -> [AtomImage; 2] {
         self.connecting_atom_ids
     }
 }
@@ -57,31 +77,84 @@ impl BondingCircle {
 #[derive(Debug, Clone)]
 pub struct BondingArc {
     arc: Arc,
-    connecting_atom_ids: [usize; 2],
+    connecting_atom_ids: [AtomImage; 2],
+    representative: Point3<f64>,
 }
 
 impl BondingArc {
-    pub fn new(arc: Arc, connecting_atom_ids: [usize; 2]) -> Self {
+    /// The representative position defaults to the middle of the arc.
+    pub fn new(arc: Arc, connecting_atom_ids: [AtomImage; 2]) -> Self {
         Self {
             arc,
             connecting_atom_ids,
+            representative: arc.point_at(arc.theta() / 2.0),
         }
     }
+
+    pub fn with_representative(self, representative: Point3<f64>) -> Self {
+        Self {
+            representative,
+            ..self
+        }
+    }
+
+    pub fn arc(&self) -> Arc {
+        self.arc
+    }
+
+    pub fn connecting_atoms(&self) -> [AtomImage; 2] {
+        self.connecting_atom_ids
+    }
+    /// The chosen position on the arc to place the new atom.
+    pub fn representative(&self) -> Point3<f64> {
+        self.representative
+    }
+    /// `n` evenly spaced positions on the arc.
+    pub fn samples(&self, n: usize) -> Vec<Point3<f64>> {
+        self.arc.sample_points(n)
+    }
+}
+
+/// How to choose the representative position on a `BondingArc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArcRepresentative {
+    /// The middle point of the arc
+    Middle,
+    /// The point with the largest `z`, i.e., farthest from the surface of a slab
+    Topmost,
+    /// The point farthest from all other atoms around
+    #[default]
+    FarthestFromAtoms,
 }
 
-/// Point with bonding atom information
+/// Point with bonding atom information.
+/// The connecting atoms are the periodic images seen from the point.
+/// The measured distance to each connecting atom is stored in the same order,
+/// once the point has been checked in the `PointStage`.
 #[derive(Debug, Clone)]
 pub struct CoordinationPoint {
     coord: Point3<f64>,
-    connecting_atom_ids: Vec<usize>,
+    connecting_atom_ids: Vec<AtomImage>,
+    bond_distances: Vec<f64>,
     cn: u32,
+    /// Kind of the site known from its origin, e.g., `fcc_hollow`, used in the exported name
+    label: Option<String>,
 }
 impl CoordinationPoint {
-    pub fn new(coord: Point3<f64>, connecting_atom_ids: Vec<usize>, cn: u32) -> Self {
+    pub fn new(coord: Point3<f64>, connecting_atom_ids: Vec<AtomImage>, cn: u32) -> Self {
         Self {
             coord,
             connecting_atom_ids,
+            bond_distances: Vec::new(),
             cn,
+            label: None,
+        }
+    }
+    /// Name the kind of the site, e.g., `top` or `fcc_hollow`.
+    pub fn with_label(self, label: &str) -> Self {
+        Self {
+            label: Some(label.into()),
+            ..self
         }
     }
 
@@ -92,19 +165,51 @@ impl CoordinationPoint {
             new_connecting_atom_ids.sort();
             new_connecting_atom_ids.dedup();
             let new_cn = new_connecting_atom_ids.len();
+            // The distances have to be measured again for the merged atoms.
             Some(CoordinationPoint {
                 coord: self.coord,
                 connecting_atom_ids: new_connecting_atom_ids,
+                bond_distances: Vec::new(),
                 cn: new_cn as u32,
+                label: self.label,
             })
         } else {
             None
         }
     }
 
-    pub fn connecting_atom_ids(&self) -> &[usize] {
+    /// Indices of the connecting atoms in the model, regardless of the periodic images.
+    pub fn connecting_atom_ids(&self) -> Vec<usize> {
+        self.connecting_atom_ids
+            .iter()
+            .map(|image| image.atom_id())
+            .collect()
+    }
+
+    pub fn connecting_atoms(&self) -> &[AtomImage] {
         self.connecting_atom_ids.as_ref()
     }
+    /// Distances to the connecting atoms, in the same order of `connecting_atoms()`.
+    /// Empty if the distances have not been measured.
+    pub fn bond_distances(&self) -> &[f64] {
+        self.bond_distances.as_ref()
+    }
+    /// Distance to the given connecting atom image, if measured.
+    pub fn bond_distance_to(&self, atom: &AtomImage) -> Option<f64> {
+        self.connecting_atom_ids
+            .iter()
+            .position(|image| image == atom)
+            .and_then(|i| self.bond_distances.get(i).copied())
+    }
+    /// Set the connecting atoms together with the measured distances.
+    /// # Panics
+    /// Panics if the numbers of atoms and distances are different.
+    pub fn set_bonds(&mut self, connecting_atoms: Vec<AtomImage>, bond_distances: Vec<f64>) {
+        assert_eq!(connecting_atoms.len(), bond_distances.len());
+        self.cn = connecting_atoms.len() as u32;
+        self.connecting_atom_ids = connecting_atoms;
+        self.bond_distances = bond_distances;
+    }
 
     pub fn cn(&self) -> u32 {
         self.cn
@@ -117,4 +222,8 @@ impl CoordinationPoint {
     pub fn set_coord(&mut self, coord: Point3<f64>) {
         self.coord = coord;
     }
+
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
 }
@@ -1,7 +1,6 @@
 use chemrust_core::data::Atom;
-use nalgebra::Vector3;
 
-use super::{BondingCircle, BondingSphere, CoordinationPoint};
+use super::{BondingArc, BondingCircle, BondingSphere, CoordinationPoint};
 pub trait Visualize {
     type Output;
     fn draw_with_atoms(&self) -> Self::Output;
@@ -11,10 +10,7 @@ pub trait Visualize {
 impl Visualize for BondingSphere {
     type Output = Vec<Atom>;
     fn draw_with_atoms(&self) -> Self::Output {
-        let center = self.sphere.center;
-        let radius = self.sphere.radius;
-        let z_shift = Vector3::z_axis().scale(radius);
-        let report_coord = center + z_shift;
+        let report_coord = self.mounting_point();
         vec![Atom::new_builder()
             .with_index(0)
             .with_symbol("He")
@@ -23,10 +19,7 @@ impl Visualize for BondingSphere {
             .build()]
     }
     fn draw_with_element(&self, element_symbol: &str) -> Self::Output {
-        let center = self.sphere.center;
-        let radius = self.sphere.radius;
-        let z_shift = Vector3::z_axis().scale(radius);
-        let report_coord = center + z_shift;
+        let report_coord = self.mounting_point();
         vec![Atom::new_builder()
             .with_index(0)
             .with_symbol(element_symbol)
@@ -39,10 +32,7 @@ impl Visualize for BondingSphere {
 impl Visualize for BondingCircle {
     type Output = Vec<Atom>;
     fn draw_with_atoms(&self) -> Self::Output {
-        let center = self.circle().center;
-        let z_axis = Vector3::z_axis();
-        let z_shift = self.circle().radius;
-        let repr_coord = center + z_axis.scale(z_shift);
+        let repr_coord = self.circle().topmost_point();
         vec![Atom::new_builder()
             .with_index(0)
             .with_symbol("Ne")
@@ -51,10 +41,7 @@ impl Visualize for BondingCircle {
             .build()]
     }
     fn draw_with_element(&self, element_symbol: &str) -> Self::Output {
-        let center = self.circle().center;
-        let z_axis = Vector3::z_axis();
-        let z_shift = self.circle().radius;
-        let repr_coord = center + z_axis.scale(z_shift);
+        let repr_coord = self.circle().topmost_point();
         vec![Atom::new_builder()
             .with_index(0)
             .with_symbol(element_symbol)
@@ -64,6 +51,26 @@ impl Visualize for BondingCircle {
     }
 }
 
+impl Visualize for BondingArc {
+    type Output = Vec<Atom>;
+    fn draw_with_atoms(&self) -> Self::Output {
+        vec![Atom::new_builder()
+            .with_index(0)
+            .with_symbol("Ar")
+            .with_coord(&self.representative())
+            .ready()
+            .build()]
+    }
+    fn draw_with_element(&self, element_symbol: &str) -> Self::Output {
+        vec![Atom::new_builder()
+            .with_index(0)
+            .with_symbol(element_symbol)
+            .with_coord(&self.representative())
+            .ready()
+            .build()]
+    }
+}
+
 impl Visualize for CoordinationPoint {
     type Output = Vec<Atom>;
     fn draw_with_atoms(&self) -> Self::Output {
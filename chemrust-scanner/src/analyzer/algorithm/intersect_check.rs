@@ -1,32 +1,69 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, f64::consts::PI};
 
-use crate::analyzer::geometry::{
-    CircleIntersectChecker, CircleIntersectResult, Intersect, SphereIntersectResult,
+use crate::analyzer::{
+    geometry::{
+        Arc, CircleIntersectChecker, CircleIntersectResult, Intersect, Sphere,
+        SphereIntersectResult, Tolerance,
+    },
+    mounting_analyze::{LOWER_FAC, UPPER_FAC},
 };
+use chemrust_core::data::LatticeVectors;
 use itertools::Itertools;
-use kd_tree::KdIndexTree;
-use nalgebra::{distance, Point3};
+use kd_tree::KdMap;
+use nalgebra::{distance, Point3, UnitVector3, Vector3};
+use rayon::prelude::*;
 
 use super::{
-    BondingCircle, BondingSphere, CheckStage, CircleStage, CoordinationPoint, FinalReport,
+    ideal_bondlength, is_bonded, ArcRepresentative, AtomImage, BondingArc, BondingCircle,
+    BondingSphere, CheckStage, CircleStage, CoordinationPoint, FinalReport, PeriodicImages,
     PointStage, Ready, SphereStage,
 };
 
+/// The sphere intersects found around a selected atom, and the pure sphere site if it intersects nothing.
+type SphereCheckResult = (
+    Vec<([AtomImage; 2], SphereIntersectResult)>,
+    Option<BondingSphere>,
+);
+
 pub struct IntersectChecker<'a, T: CheckStage> {
     coords: &'a [Point3<f64>],
     selected_coords: &'a [Point3<f64>],
-    coords_kdtree: KdIndexTree<'a, Point3<f64>>,
-    bondlength: f64,
+    lattice_vectors: Option<&'a LatticeVectors>,
+    /// The atoms and their periodic images around the cell
+    images: PeriodicImages,
+    /// Maps the coordinates of `images` to the image ids
+    coords_kdtree: KdMap<Point3<f64>, usize>,
+    /// Target bondlength to each atom, by atom id
+    bondlengths: Vec<f64>,
+    /// Accepted `(min, max)` bondlength to each atom, by atom id
+    bondlength_windows: Vec<(f64, f64)>,
+    /// How to place the new atom on the clash-free arcs
+    arc_representative: ArcRepresentative,
+    /// Atomic numbers by atom id, to determine the bonded neighbours of the host atoms
+    atomic_numbers: Vec<u8>,
+    /// Whether each atom can bond to the new atom, by atom id. Empty if all atoms can.
+    /// The other atoms are only steric blockers.
+    bonding_atoms: Vec<bool>,
+    /// Numerical tolerances of the geometric comparisons and the merging of the found sites
+    tolerance: Tolerance,
     state: T,
 }
 
 impl<'a> IntersectChecker<'a, Ready> {
     pub fn new(coords: &'a [Point3<f64>]) -> Self {
-        let coords_kdtree = KdIndexTree::build_by_ordered_float(coords);
+        let images = PeriodicImages::new(coords, None, 0.0);
+        let coords_kdtree = images.kdtree();
         IntersectChecker {
             coords,
+            lattice_vectors: None,
+            images,
             coords_kdtree,
-            bondlength: 0.0,
+            bondlengths: Vec::new(),
+            bondlength_windows: Vec::new(),
+            arc_representative: ArcRepresentative::default(),
+            atomic_numbers: Vec::new(),
+            bonding_atoms: Vec::new(),
+            tolerance: Tolerance::default(),
             state: Ready,
             selected_coords: coords,
         }
@@ -37,17 +74,156 @@ impl<'a> IntersectChecker<'a, Ready> {
             ..self
         }
     }
+    /// Search with periodic images across the cell boundary.
+    /// `None` for isolated molecules or clusters.
+    pub fn set_lattice_vectors(self, lattice_vectors: Option<&'a LatticeVectors>) -> Self {
+        IntersectChecker {
+            lattice_vectors,
+            ..self
+        }
+    }
+    /// Accept the sites whose distances to the connecting atoms fall within the `(min, max)` window,
+    /// given for each atom in the same order of the coords.
+    /// Without it, only the distances equal to the target bondlength are accepted.
+    pub fn set_bondlength_windows(self, windows: &[(f64, f64)]) -> Self {
+        IntersectChecker {
+            bondlength_windows: windows.to_vec(),
+            ..self
+        }
+    }
+    /// Choose the representative position on each clash-free arc of the circles.
+    pub fn set_arc_representative(self, arc_representative: ArcRepresentative) -> Self {
+        IntersectChecker {
+            arc_representative,
+            ..self
+        }
+    }
+    /// Atomic numbers of the atoms in the same order of the coords, to determine the bonded
+    /// neighbours of a host atom by `is_bonded`. Without them, the neighbours within
+    /// `UPPER_FAC` times the nearest distance are taken as bonded.
+    pub fn set_atomic_numbers(self, atomic_numbers: &[u8]) -> Self {
+        IntersectChecker {
+            atomic_numbers: atomic_numbers.to_vec(),
+            ..self
+        }
+    }
+    /// Whether each atom, in the same order of the coords, can bond to the new atom.
+    /// The atoms excluded from bonding still block the sites too close to them,
+    /// but never connect to a site. Without it, all atoms can bond.
+    pub fn set_bonding_atoms(self, bonding_atoms: &[bool]) -> Self {
+        IntersectChecker {
+            bonding_atoms: bonding_atoms.to_vec(),
+            ..self
+        }
+    }
+    pub fn set_tolerance(self, tolerance: Tolerance) -> Self {
+        IntersectChecker { tolerance, ..self }
+    }
+    /// Start with the same radius for every atom.
     pub fn start_with_radius(self, radius: f64) -> IntersectChecker<'a, SphereStage> {
+        let radii = vec![radius; self.coords.len()];
+        self.start_with_radii(&radii)
+    }
+    /// Start with the given radius for each atom, in the same order of the coords.
+    /// # Panics
+    /// Panics if the number of radii or bondlength windows does not match the number of coords.
+    pub fn start_with_radii(self, radii: &[f64]) -> IntersectChecker<'a, SphereStage> {
+        assert_eq!(radii.len(), self.coords.len());
+        let bondlength_windows: Vec<(f64, f64)> = if self.bondlength_windows.is_empty() {
+            radii.iter().map(|&r| (r, r)).collect()
+        } else {
+            assert_eq!(self.bondlength_windows.len(), self.coords.len());
+            self.bondlength_windows
+        };
+        let max_radius = bondlength_windows
+            .iter()
+            .map(|&(_, max)| max)
+            .fold(max_radius_of(radii), f64::max);
+        // The farthest lookup happens in checking the pure circles, which searches
+        // `circle radius + bondlength` around the circle centers, and the circle centers
+        // can be `radius` away from the home atom.
+        let images = PeriodicImages::new(self.coords, self.lattice_vectors, 3.0 * max_radius)
+            .with_tolerance(self.tolerance);
+        let coords_kdtree = images.kdtree();
+        let image_radii: Vec<f64> = images
+            .images()
+            .iter()
+            .map(|image| radii[image.atom_id()])
+            .collect();
+        let state = SphereStage::new(images.coords(), &image_radii);
         IntersectChecker {
             coords: self.coords,
-            coords_kdtree: self.coords_kdtree,
+            lattice_vectors: self.lattice_vectors,
+            images,
+            coords_kdtree,
             selected_coords: self.selected_coords,
-            bondlength: radius,
-            state: SphereStage::new(self.coords, radius),
+            bondlengths: radii.to_vec(),
+            bondlength_windows,
+            arc_representative: self.arc_representative,
+            atomic_numbers: self.atomic_numbers,
+            bonding_atoms: self.bonding_atoms,
+            tolerance: self.tolerance,
+            state,
         }
     }
 }
 
+impl<'a, T: CheckStage> IntersectChecker<'a, T> {
+    fn image_coord(&self, image: &AtomImage) -> Point3<f64> {
+        self.images.image_coord(image)
+    }
+    fn bondlength_of(&self, image: &AtomImage) -> f64 {
+        self.bondlengths[image.atom_id()]
+    }
+    fn max_bondlength(&self) -> f64 {
+        max_radius_of(&self.bondlengths)
+    }
+    fn is_bonding_atom(&self, image: &AtomImage) -> bool {
+        self.bonding_atoms.is_empty() || self.bonding_atoms[image.atom_id()]
+    }
+    fn bondlength_window_of(&self, image: &AtomImage) -> (f64, f64) {
+        self.bondlength_windows[image.atom_id()]
+    }
+    /// The largest accepted bondlength among the atoms.
+    fn max_window_bondlength(&self) -> f64 {
+        self.bondlength_windows
+            .iter()
+            .map(|&(_, max)| max)
+            .fold(self.max_bondlength(), f64::max)
+    }
+    /// The widest bondlength window among the atoms.
+    fn max_window_width(&self) -> f64 {
+        self.bondlength_windows
+            .iter()
+            .map(|&(min, max)| max - min)
+            .fold(0.0, f64::max)
+    }
+    /// Wrap the coordination point back into the cell, and update the image offsets of the connecting atoms
+    /// as seen from the wrapped point.
+    fn wrap_point(&self, point: &CoordinationPoint) -> CoordinationPoint {
+        let (wrapped, shift) = self.images.wrap(&point.coord());
+        let back_shift = [-shift[0], -shift[1], -shift[2]];
+        let connecting_atoms: Vec<AtomImage> = point
+            .connecting_atoms()
+            .iter()
+            .map(|image| image.translate(back_shift))
+            .collect();
+        CoordinationPoint::new(wrapped, connecting_atoms, point.cn())
+    }
+    /// Wrap the circle center back into the cell, and update the image offsets of the connecting atoms.
+    fn wrap_circle(&self, bonding_circle: &BondingCircle) -> BondingCircle {
+        let mut circle = bonding_circle.circle();
+        let (wrapped, shift) = self.images.wrap(&circle.center);
+        let back_shift = [-shift[0], -shift[1], -shift[2]];
+        circle.center = wrapped;
+        let [atom_a, atom_b] = bonding_circle.connecting_atoms();
+        BondingCircle::new(
+            circle,
+            [atom_a.translate(back_shift), atom_b.translate(back_shift)],
+        )
+    }
+}
+
 impl<'a> IntersectChecker<'a, SphereStage> {
     /// Returns the analyze sphere intersects of this [`IntersectChecker<SphereStage>`].
     /// The `SphereIntersectResult` is pattern-matched to corresponding `MountingSite` types.
@@ -55,46 +231,160 @@ impl<'a> IntersectChecker<'a, SphereStage> {
     /// - `SphereIntersectResult::Zero` => No sphere intersects, this sphere represent a single coordination site around the atom.
     /// - `SphereIntersectResult::SinglePoint` => Two spheres cut at one point. The `C.N.` of a point is at least two, considering later check of repetitions from other possibilities, e.g. many circles crossing at one point.
     /// - `SphereIntersectResult::Circle` => The two spheres intersect as a circle, every point on this circle satisfy bonding two atoms at the same time.
+    /// - The neighbours are searched among the periodic images. A pair across the cell boundary is kept
+    ///   in the frame of each selected atom, so the circles sharing the same atom stay in the same frame
+    ///   to be intersected. The translated copies are merged after wrapping in the later stages.
     fn analyze_sphere_intersects(&self) -> CircleStage {
         let mut circles: Vec<BondingCircle> = Vec::new();
         let mut spheres: Vec<BondingSphere> = Vec::new();
         let mut points_only_sites: Vec<CoordinationPoint> = Vec::new();
-        let radius = self.state.radius();
-        let mut checked_pairs: HashSet<[usize; 2]> = HashSet::new();
-        self.selected_coords.iter().enumerate().for_each(|(_, p)| {
-            let found = self.coords_kdtree.within_radius(p, 2.0 * radius);
-            let original_id = self.coords.iter().position(|&op| *p == op).unwrap();
-            let this_sphere = self.state.get_sphere(original_id).unwrap();
-            found
-                .iter()
-                .filter(|found_id| -> bool {
-                    if original_id == ***found_id {
-                        return false;
-                    }
-                    let mut pair: [usize; 2] = [original_id, ***found_id];
+        let max_radius = self.state.max_radius();
+        // The atoms are checked in parallel, and the results are collected in the order of the selected atoms.
+        let results: Vec<SphereCheckResult> = self
+            .selected_coords
+            .par_iter()
+            .map(|p| {
+                let original_id = self.coords.iter().position(|&op| *p == op).unwrap();
+                let this_atom = AtomImage::home(original_id);
+                if !self.is_bonding_atom(&this_atom) {
+                    return (Vec::new(), None);
+                }
+                // The home images are placed first, the image id equals to the atom id.
+                let this_sphere = self.state.get_sphere(original_id).unwrap();
+                let mut found = self
+                    .coords_kdtree
+                    .within_radius(p, this_sphere.radius + max_radius);
+                found.sort_by_key(|(_, image_id)| *image_id);
+                let intersects: Vec<([AtomImage; 2], SphereIntersectResult)> = found
+                    .iter()
+                    .map(|(_, image_id)| *image_id)
+                    // The atoms excluded from bonding do not form circles or points with this atom,
+                    // but still block the sphere in `sphere_mounting_direction`.
+                    .filter(|&image_id| {
+                        image_id != original_id
+                            && self.is_bonding_atom(&self.images.images()[image_id])
+                    })
+                    .map(|image_id| {
+                        let found_atom = self.images.images()[image_id];
+                        let found_sphere = self.state.get_sphere(image_id).unwrap();
+                        (
+                            [this_atom, found_atom],
+                            this_sphere.intersects_within(found_sphere, &self.tolerance),
+                        )
+                    })
+                    .collect();
+                let has_intersection = intersects
+                    .iter()
+                    .any(|(_, res)| !matches!(res, SphereIntersectResult::Zero));
+                let sphere = if has_intersection {
+                    None
+                } else {
+                    let bonding_sphere = BondingSphere::new(*this_sphere, original_id);
+                    self.sphere_mounting_direction(&bonding_sphere)
+                        .map(|direction| bonding_sphere.with_mounting_direction(direction))
+                };
+                (intersects, sphere)
+            })
+            .collect();
+        let mut checked_pairs: HashSet<[AtomImage; 2]> = HashSet::new();
+        results.into_iter().for_each(|(intersects, sphere)| {
+            intersects
+                .into_iter()
+                .for_each(|(atoms, intersect_result)| {
+                    let mut pair = atoms;
                     pair.sort();
-                    checked_pairs.insert(pair)
-                })
-                // If the pair atoms of found id and current id has been documented, the `insert` will return false, so the checked atom pairs will be skipped
-                .for_each(|new_id| {
-                    // the remain ids are new
-                    let found_sphere = self.state.get_sphere(**new_id).unwrap();
-                    let intersect_result = this_sphere.intersects(found_sphere);
+                    // If the pair atoms of found id and current id has been documented, the `insert` will return false, so the checked atom pairs will be skipped
+                    if !checked_pairs.insert(pair) {
+                        return;
+                    }
                     match intersect_result {
-                        SphereIntersectResult::Zero => {
-                            spheres.push(BondingSphere::new(*this_sphere, original_id))
+                        SphereIntersectResult::SinglePoint(p) => {
+                            points_only_sites.push(CoordinationPoint::new(p, atoms.to_vec(), 2))
                         }
-                        SphereIntersectResult::SinglePoint(p) => points_only_sites
-                            .push(CoordinationPoint::new(p, vec![original_id, **new_id], 2)),
                         SphereIntersectResult::Circle(c) => {
-                            circles.push(BondingCircle::new(c, [original_id, **new_id]))
+                            circles.push(BondingCircle::new(c, atoms))
                         }
                         _ => (),
                     }
-                })
+                });
+            if let Some(bonding_sphere) = sphere {
+                spheres.push(bonding_sphere)
+            }
         });
         CircleStage::new(spheres, points_only_sites, circles)
     }
+    /// The outward direction of the host atom, opposite to the sum of the unit bond vectors
+    /// to its bonded neighbours. Falls back to `+z` when the bonds cancel out within the distance
+    /// tolerance, or there is no bonded neighbour.
+    /// # Notes:
+    /// The neighbours are looked up among the nearest 12 atoms, including the periodic images.
+    fn outward_direction(&self, atom_id: usize) -> UnitVector3<f64> {
+        let center = self.coords[atom_id];
+        let found = self.coords_kdtree.nearests(&center, 13);
+        let neighbours: Vec<(Point3<f64>, usize, f64)> = found
+            .iter()
+            .filter(|item| item.item.1 != atom_id)
+            .map(|item| {
+                let (coord, image_id) = item.item;
+                let neighbour_id = self.images.images()[*image_id].atom_id();
+                (*coord, neighbour_id, item.squared_distance.sqrt())
+            })
+            .collect();
+        let nearest_distance = neighbours
+            .iter()
+            .map(|&(_, _, d)| d)
+            .fold(f64::INFINITY, f64::min);
+        let bond_sum: Vector3<f64> = neighbours
+            .iter()
+            .filter(|&&(_, neighbour_id, d)| {
+                if self.atomic_numbers.is_empty() {
+                    d <= UPPER_FAC * nearest_distance
+                } else {
+                    let ideal_bondlength = ideal_bondlength(
+                        self.atomic_numbers[atom_id],
+                        self.atomic_numbers[neighbour_id],
+                    );
+                    is_bonded(d, ideal_bondlength, LOWER_FAC, UPPER_FAC)
+                }
+            })
+            .map(|(coord, _, _)| (coord - center).normalize())
+            .sum();
+        if self.tolerance.eq_length(bond_sum.norm(), 0.0) {
+            Vector3::z_axis()
+        } else {
+            UnitVector3::new_normalize(-bond_sum)
+        }
+    }
+    /// Mount along the outward direction if the position does not clash with any other atom.
+    /// Otherwise, take the clash-free direction on the sphere closest to the outward direction.
+    /// Returns `None` if the whole sphere clashes.
+    fn sphere_mounting_direction(
+        &self,
+        bonding_sphere: &BondingSphere,
+    ) -> Option<UnitVector3<f64>> {
+        let atom_id = bonding_sphere.locating_atom_id();
+        let sphere = bonding_sphere.sphere();
+        let is_clashed = |direction: &UnitVector3<f64>| {
+            let point = sphere.point_at_surface(direction);
+            self.coords_kdtree
+                .within_radius(&point, self.max_window_bondlength())
+                .into_iter()
+                .any(|(coord, image_id)| {
+                    let image = self.images.images()[*image_id];
+                    let (min_bondlength, _) = self.bondlength_window_of(&image);
+                    *image_id != atom_id
+                        && min_bondlength - distance(&point, coord) > self.tolerance.distance()
+                })
+        };
+        let outward = self.outward_direction(atom_id);
+        if !is_clashed(&outward) {
+            return Some(outward);
+        }
+        Sphere::fibonacci_directions(200)
+            .into_iter()
+            .filter(|direction| !is_clashed(direction))
+            .max_by(|a, b| a.dot(&outward).total_cmp(&b.dot(&outward)))
+    }
     /// Transition to `CircleStage` by collecting pure spheres, single points,
     /// and circles from current stage intersect results.
     /// # Caution: This function consumes self, copy data to create a new `IntersectChecker`
@@ -103,15 +393,29 @@ impl<'a> IntersectChecker<'a, SphereStage> {
         let Self {
             coords,
             selected_coords,
+            lattice_vectors,
+            images,
             coords_kdtree,
-            bondlength,
+            bondlengths,
+            bondlength_windows,
+            arc_representative,
+            atomic_numbers,
+            bonding_atoms,
+            tolerance,
             state: _,
         } = self;
         IntersectChecker {
             coords,
+            lattice_vectors,
+            images,
             coords_kdtree,
             selected_coords,
-            bondlength,
+            bondlengths,
+            bondlength_windows,
+            arc_representative,
+            atomic_numbers,
+            bonding_atoms,
+            tolerance,
             state: circle_stage,
         }
     }
@@ -119,7 +423,6 @@ impl<'a> IntersectChecker<'a, SphereStage> {
 
 impl<'a> IntersectChecker<'a, CircleStage> {
     /// Returns the analyze circle intersects of this [`IntersectChecker<CircleStage>`].
-    /// Bug when c2 contains c1
     fn join_circle_points(
         &self,
         point: &Point3<f64>,
@@ -132,96 +435,146 @@ impl<'a> IntersectChecker<'a, CircleStage> {
         let mut connecting_atoms = connecting_atoms.concat();
         connecting_atoms.sort();
         connecting_atoms.dedup();
-        let real_connecting_atoms: Vec<usize> = connecting_atoms
+        let real_connecting_atoms: Vec<AtomImage> = connecting_atoms
             .into_iter()
-            .filter(|&atom_id| {
-                let distance = distance(point, self.coords.get(atom_id).unwrap());
-                (distance - self.bondlength).abs() <= 1e-6
+            .filter(|atom| {
+                let distance = distance(point, &self.image_coord(atom));
+                let (min, max) = self.bondlength_window_of(atom);
+                distance >= min - self.tolerance.distance()
+                    && distance <= max + self.tolerance.distance()
             })
             .collect();
         let coordination_number = real_connecting_atoms.len() as u32;
         CoordinationPoint::new(*point, real_connecting_atoms, coordination_number)
     }
+    /// # Notes:
+    /// The circle centers are indexed by a kd-tree. Two circles can only intersect if their centers
+    /// are closer than the sum of the radii, so each circle is only checked against the circles found
+    /// within its radius plus the largest radius. Each pair is checked once, from the circle with the smaller index.
+    /// The circles are checked in parallel, and the results are collected in the order of the circles.
     pub fn analyze_circle_intersects(self) -> IntersectChecker<'a, PointStage> {
-        let mut pure_circles = Vec::new();
-        let mut points_only_sites: Vec<CoordinationPoint> = Vec::new();
-        let mut checked_pairs: HashSet<[usize; 2]> = HashSet::new();
-        self.state
-            .circles
+        let circles = &self.state.circles;
+        let max_circle_radius = circles
             .iter()
+            .map(|bc| bc.circle().radius)
+            .fold(0.0, f64::max);
+        let centers_kdtree: KdMap<Point3<f64>, usize> = KdMap::build_by_ordered_float(
+            circles
+                .iter()
+                .enumerate()
+                .map(|(i, bc)| (bc.circle().center, i))
+                .collect(),
+        );
+        let results: Vec<(bool, Vec<CoordinationPoint>)> = circles
+            .par_iter()
             .enumerate()
-            .for_each(|(id_main, now_bond_circle)| {
-                let mut zero_intersect_count: usize = 0;
-                let mut to_check: usize = 0;
-                self.state
-                    .circles
-                    .iter()
-                    .enumerate()
-                    .filter(|&(id_sub, _)| match id_main == id_sub {
-                        true => false,
-                        false => {
-                            let mut pair = [id_main, id_sub];
-                            pair.sort();
-                            checked_pairs.insert(pair)
+            .map(|(id_main, now_bond_circle)| {
+                let mut candidates: Vec<usize> = centers_kdtree
+                    .within_radius(
+                        &now_bond_circle.circle().center,
+                        now_bond_circle.circle().radius
+                            + max_circle_radius
+                            + self.tolerance.distance(),
+                    )
+                    .into_iter()
+                    .map(|(_, id_sub)| *id_sub)
+                    .filter(|&id_sub| id_sub > id_main)
+                    .collect();
+                candidates.sort();
+                let mut is_pure = true;
+                let mut points: Vec<CoordinationPoint> = Vec::new();
+                candidates.into_iter().for_each(|id_sub| {
+                    let bonding_circle = &circles[id_sub];
+                    let res = CircleIntersectChecker::new(
+                        &now_bond_circle.circle(),
+                        &bonding_circle.circle(),
+                    )
+                    .with_tolerance(self.tolerance)
+                    .check();
+                    match res {
+                        CircleIntersectResult::CoplanarZero
+                        | CircleIntersectResult::NonCoplanarZero => (),
+                        CircleIntersectResult::Single(p) => {
+                            is_pure = false;
+                            points.push(self.join_circle_points(
+                                &p,
+                                now_bond_circle,
+                                bonding_circle,
+                            ));
                         }
-                    })
-                    .for_each(|(_, bonding_circle)| {
-                        to_check += 1;
-                        let res = CircleIntersectChecker::new(
-                            &now_bond_circle.circle(),
-                            &bonding_circle.circle(),
-                        )
-                        .check();
-                        match res {
-                            CircleIntersectResult::CoplanarZero => {
-                                zero_intersect_count += 1;
-                            }
-                            CircleIntersectResult::NonCoplanarZero => {
-                                zero_intersect_count += 1;
-                            }
-                            CircleIntersectResult::Single(p) => {
-                                let point =
-                                    self.join_circle_points(&p, now_bond_circle, bonding_circle);
-                                points_only_sites.push(point);
-                            }
-                            CircleIntersectResult::Double(points) => {
-                                let point_1 = self.join_circle_points(
-                                    &points.0,
-                                    now_bond_circle,
-                                    bonding_circle,
-                                );
-                                let point_2 = self.join_circle_points(
-                                    &points.1,
-                                    now_bond_circle,
-                                    bonding_circle,
-                                );
-                                points_only_sites.push(point_1);
-                                points_only_sites.push(point_2);
-                            }
-                            _ => (),
+                        CircleIntersectResult::Double(double) => {
+                            is_pure = false;
+                            points.push(self.join_circle_points(
+                                &double.0,
+                                now_bond_circle,
+                                bonding_circle,
+                            ));
+                            points.push(self.join_circle_points(
+                                &double.1,
+                                now_bond_circle,
+                                bonding_circle,
+                            ));
                         }
-                    });
-                if zero_intersect_count == to_check {
-                    pure_circles.push(*now_bond_circle)
+                        _ => is_pure = false,
+                    }
+                });
+                (is_pure, points)
+            })
+            .collect();
+        let mut pure_circles = Vec::new();
+        let mut points_only_sites: Vec<CoordinationPoint> = Vec::new();
+        results
+            .into_iter()
+            .zip(circles.iter())
+            .for_each(|((is_pure, points), bonding_circle)| {
+                if is_pure {
+                    pure_circles.push(*bonding_circle)
+                }
+                points_only_sites.extend(points);
+            });
+        let analyzed_circles = self.analyze_pure_circles(&pure_circles);
+        // The same circle across the cell boundary is found from both atoms.
+        // Wrap them into the cell to remove the translated copies. Every wrapped circle is
+        // recorded before the cut, so the copies of a circle cut into arcs are removed too.
+        let mut wrapped_centers: Vec<Point3<f64>> = Vec::new();
+        let mut wrapped_circles: Vec<BondingCircle> = Vec::new();
+        let mut arcs: Vec<BondingArc> = Vec::new();
+        analyzed_circles
+            .iter()
+            .map(|bc| self.wrap_circle(bc))
+            .for_each(|bc| {
+                let is_copy = wrapped_centers
+                    .iter()
+                    .any(|center| self.tolerance.same_point(center, &bc.circle().center));
+                if is_copy {
+                    return;
+                }
+                wrapped_centers.push(bc.circle().center);
+                // Circles partially clashed with other atoms are cut into arcs.
+                match self.clash_free_arcs(&bc) {
+                    None => wrapped_circles.push(bc),
+                    Some(free_arcs) => arcs.extend(free_arcs),
                 }
             });
-        pure_circles.dedup_by(|a, b| {
-            (a.circle().center.x - b.circle().center.x).abs() < 1e-6
-                && (a.circle().center.y - b.circle().center.y).abs() < 1e-6
-                && (a.circle().center.z - b.circle().center.z).abs() < 1e-6
-        });
-        let analzyed_circles = self.analyze_pure_circles(&pure_circles);
         let point_stage = PointStage::new(
             self.state.sphere_sites,
-            analzyed_circles,
+            wrapped_circles,
+            arcs,
             self.state.sphere_cut_points,
             points_only_sites,
         );
         IntersectChecker {
             coords: self.coords,
+            lattice_vectors: self.lattice_vectors,
+            images: self.images,
             coords_kdtree: self.coords_kdtree,
             selected_coords: self.selected_coords,
-            bondlength: self.bondlength,
+            bondlengths: self.bondlengths,
+            bondlength_windows: self.bondlength_windows,
+            arc_representative: self.arc_representative,
+            atomic_numbers: self.atomic_numbers,
+            bonding_atoms: self.bonding_atoms,
+            tolerance: self.tolerance,
             state: point_stage,
         }
     }
@@ -229,7 +582,10 @@ impl<'a> IntersectChecker<'a, CircleStage> {
     /// 2. Determine the longest possible distance from each atom to the circle
     /// 3. If the longest to circle distance of an atom is shorter than the bondlength, reject
     /// 4. How far should we iterate?
+    ///
+    /// The bondlength is the minimum accepted bondlength to each found atom.
     fn analyze_pure_circles(&self, circles: &[BondingCircle]) -> Vec<BondingCircle> {
+        let max_bondlength = self.max_window_bondlength();
         circles
             .iter()
             .filter(|bc| {
@@ -237,15 +593,12 @@ impl<'a> IntersectChecker<'a, CircleStage> {
 
                 let atoms_found = self
                     .coords_kdtree
-                    .within_radius(&center, bc.circle().radius + self.bondlength);
-                for &atom_id in atoms_found {
-                    let atom_coord = self.coords.get(atom_id).unwrap();
-                    let (min_distance, max_distance) =
-                        bc.circle().point_to_circle_distances(atom_coord);
-                    if self.bondlength - max_distance > 1e-6 {
-                        if bc.connecting_atoms().contains(&atom_id) {
-                            println!("{atom_id}, {min_distance}, {max_distance}")
-                        }
+                    .within_radius(&center, bc.circle().radius + max_bondlength);
+                for (atom_coord, image_id) in atoms_found {
+                    let atom_image = self.images.images()[*image_id];
+                    let (_, max_distance) = bc.circle().point_to_circle_distances(atom_coord);
+                    let (min_bondlength, _) = self.bondlength_window_of(&atom_image);
+                    if min_bondlength - max_distance > self.tolerance.distance() {
                         return false;
                     }
                 }
@@ -254,6 +607,135 @@ impl<'a> IntersectChecker<'a, CircleStage> {
             .cloned()
             .collect()
     }
+    /// Cut the circle into the arcs free of clashes with the other atoms around.
+    /// Returns `None` if the whole circle is free of clashes, and an empty `Vec`
+    /// if the whole circle clashes.
+    /// # Notes:
+    /// A point `X(t)` on the circle is `t` radians from the `u` of `Circle::basis()`.
+    /// The squared distance to an atom `P` is `|C - P|^2 + r^2 + 2r * R * cos(t - t0)`,
+    /// where `R` and `t0` are the length and angle of `C - P` projected on the circle plane.
+    /// So the clash, where the distance is shorter than the minimum bondlength,
+    /// is an interval of `t` centered at `t0 + PI`.
+    fn clash_free_arcs(&self, bonding_circle: &BondingCircle) -> Option<Vec<BondingArc>> {
+        let circle = bonding_circle.circle();
+        let (u, v) = circle.basis();
+        let mut clashes: Vec<(f64, f64)> = Vec::new();
+        for (atom_coord, image_id) in self
+            .coords_kdtree
+            .within_radius(&circle.center, circle.radius + self.max_window_bondlength())
+        {
+            let atom_image = self.images.images()[*image_id];
+            if bonding_circle.connecting_atoms().contains(&atom_image) {
+                continue;
+            }
+            let (min_bondlength, _) = self.bondlength_window_of(&atom_image);
+            let (min_distance, max_distance) = circle.point_to_circle_distances(atom_coord);
+            if min_bondlength - min_distance <= self.tolerance.distance() {
+                continue;
+            }
+            if min_bondlength - max_distance > self.tolerance.distance() {
+                return Some(Vec::new());
+            }
+            let center_to_atom = circle.center - atom_coord;
+            let (a, b) = (center_to_atom.dot(&u), center_to_atom.dot(&v));
+            let amplitude = (a * a + b * b).sqrt();
+            let t0 = b.atan2(a);
+            let k =
+                (min_bondlength.powi(2) - center_to_atom.norm_squared() - circle.radius.powi(2))
+                    / (2.0 * circle.radius * amplitude);
+            // Clash where cos(t - t0) < k
+            let half_width = PI - k.clamp(-1.0, 1.0).acos();
+            clashes.push((t0 + PI - half_width, 2.0 * half_width));
+        }
+        if clashes.is_empty() {
+            return None;
+        }
+        let arcs = free_intervals(&clashes, self.tolerance.angle())
+            .into_iter()
+            .map(|(start, theta)| {
+                let arc = Arc::from_circle(&circle, start, theta);
+                let bonding_arc = BondingArc::new(arc, bonding_circle.connecting_atoms());
+                let representative = self.arc_representative_point(&bonding_arc);
+                bonding_arc.with_representative(representative)
+            })
+            .collect();
+        Some(arcs)
+    }
+    fn arc_representative_point(&self, bonding_arc: &BondingArc) -> Point3<f64> {
+        let arc = bonding_arc.arc();
+        match self.arc_representative {
+            ArcRepresentative::Middle => arc.point_at(arc.theta() / 2.0),
+            ArcRepresentative::Topmost => arc.topmost_point(),
+            ArcRepresentative::FarthestFromAtoms => {
+                let neighbours: Vec<Point3<f64>> = self
+                    .coords_kdtree
+                    .within_radius(&arc.center(), arc.radius() + 2.0 * self.max_bondlength())
+                    .into_iter()
+                    .filter(|(_, image_id)| {
+                        let image = self.images.images()[*image_id];
+                        !bonding_arc.connecting_atoms().contains(&image)
+                    })
+                    .map(|(coord, _)| *coord)
+                    .collect();
+                let nearest_distance = |p: &Point3<f64>| {
+                    neighbours
+                        .iter()
+                        .map(|atom| distance(p, atom))
+                        .fold(f64::INFINITY, f64::min)
+                };
+                // Sample the arc by about one degree
+                let samples_num = (arc.theta().to_degrees().ceil() as usize).max(1);
+                arc.sample_points(samples_num)
+                    .into_iter()
+                    .max_by(|a, b| nearest_distance(a).total_cmp(&nearest_distance(b)))
+                    .unwrap()
+            }
+        }
+    }
+}
+
+/// The complement of the clashed intervals on `[0, 2PI)`.
+/// Each interval is given and returned as `(start, length)` in radians.
+/// The free intervals not longer than `min_length` are dropped.
+fn free_intervals(clashes: &[(f64, f64)], min_length: f64) -> Vec<(f64, f64)> {
+    let full = 2.0 * PI;
+    if clashes.iter().any(|&(_, length)| length >= full) {
+        return Vec::new();
+    }
+    // Split the intervals crossing `2PI` to work on `[0, 2PI)`
+    let mut segments: Vec<(f64, f64)> = Vec::new();
+    clashes.iter().for_each(|&(start, length)| {
+        let start = start.rem_euclid(full);
+        let end = start + length;
+        if end > full {
+            segments.push((start, full));
+            segments.push((0.0, end - full));
+        } else {
+            segments.push((start, end));
+        }
+    });
+    segments.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut free: Vec<(f64, f64)> = Vec::new();
+    let mut cursor = 0.0;
+    segments.iter().for_each(|&(start, end)| {
+        if start > cursor {
+            free.push((cursor, start));
+        }
+        cursor = cursor.max(end);
+    });
+    if cursor < full {
+        free.push((cursor, full));
+    }
+    // Join the free intervals touching at `0` and `2PI` into one arc.
+    if free.len() > 1 && free[0].0 == 0.0 && free[free.len() - 1].1 == full {
+        let (_, first_end) = free.remove(0);
+        let last = free.len() - 1;
+        free[last].1 = full + first_end;
+    }
+    free.into_iter()
+        .map(|(start, end)| (start, end - start))
+        .filter(|&(_, length)| length > min_length)
+        .collect()
 }
 
 impl<'a> IntersectChecker<'a, PointStage> {
@@ -264,27 +746,59 @@ impl<'a> IntersectChecker<'a, PointStage> {
         } else {
             points
         };
+        let mut cut_points: Vec<CoordinationPoint> = Vec::new();
+        let measured_cut_points: Vec<CoordinationPoint> = self
+            .state
+            .cut_points()
+            .par_iter()
+            .map(|cp| {
+                let mut wrapped = self.wrap_point(cp);
+                let connecting_atoms = wrapped.connecting_atoms().to_vec();
+                let distances = connecting_atoms
+                    .iter()
+                    .map(|atom| distance(&wrapped.coord(), &self.image_coord(atom)))
+                    .collect();
+                wrapped.set_bonds(connecting_atoms, distances);
+                wrapped
+            })
+            .collect();
+        measured_cut_points.into_iter().for_each(|cp| {
+            let is_copy = cut_points
+                .iter()
+                .any(|existed| self.tolerance.same_site(&existed.coord(), &cp.coord()));
+            if !is_copy {
+                cut_points.push(cp)
+            }
+        });
         let final_stage = FinalReport::new(
             self.state.sphere_sites,
             self.state.circles,
-            self.state.cut_points,
+            self.state.arcs,
+            cut_points,
             dedup_point_only,
         );
         IntersectChecker {
             coords: self.coords,
+            lattice_vectors: self.lattice_vectors,
+            images: self.images,
             coords_kdtree: self.coords_kdtree,
             selected_coords: self.selected_coords,
-            bondlength: self.bondlength,
+            bondlengths: self.bondlengths,
+            bondlength_windows: self.bondlength_windows,
+            arc_representative: self.arc_representative,
+            atomic_numbers: self.atomic_numbers,
+            bonding_atoms: self.bonding_atoms,
+            tolerance: self.tolerance,
             state: final_stage,
         }
     }
     fn merge_points(&self, points: &mut [CoordinationPoint]) -> Vec<CoordinationPoint> {
+        // Points found across the cell boundary are wrapped back, so the translated copies overlap.
         // Floor to clear meaningless digits in f64 for the ease of sort and deduplicate
         points.iter_mut().for_each(|p| {
-            let floor_x = (p.coord().x * 1e5).floor() / 1e5;
-            let floor_y = (p.coord().y * 1e5).floor() / 1e5;
-            let floor_z = (p.coord().z * 1e5).floor() / 1e5;
-            p.set_coord(Point3::new(floor_x, floor_y, floor_z))
+            let mut wrapped = self.wrap_point(p);
+            wrapped.set_coord(self.tolerance.snap(&wrapped.coord()));
+            *p = wrapped;
         });
         points.sort_by(|a, b| {
             a.coord()
@@ -294,29 +808,30 @@ impl<'a> IntersectChecker<'a, PointStage> {
                 .then(a.coord().y.partial_cmp(&b.coord().y).unwrap())
                 .then(a.coord().z.partial_cmp(&b.coord().z).unwrap())
         });
-        let point_xyzs: Vec<Point3<f64>> = points.iter().map(|cp| cp.coord()).collect();
+        let point_xyzs: Vec<(Point3<f64>, usize)> = points
+            .iter()
+            .enumerate()
+            .map(|(i, cp)| (cp.coord(), i))
+            .collect();
         let dedup_points: Vec<(usize, &CoordinationPoint)> = points
             .iter()
-            .dedup_by_with_count(|a, b| {
-                (a.coord().x - b.coord().x).abs() < 1e-5
-                    && (a.coord().y - b.coord().y).abs() < 1e-5
-                    && (a.coord().z - b.coord().z).abs() < 1e-5
-            })
+            .dedup_by_with_count(|a, b| self.tolerance.same_site(&a.coord(), &b.coord()))
             .collect();
-        let point_kdtree = KdIndexTree::build_by_ordered_float(&point_xyzs);
+        let point_kdtree = KdMap::build_by_ordered_float(point_xyzs);
         let res: Vec<CoordinationPoint> = dedup_points
-            .into_iter()
+            .into_par_iter()
             .map(|(_, p)| {
                 let this_coord = p.coord();
-                let found = point_kdtree.within_radius(&this_coord, 0.00001);
+                let found =
+                    point_kdtree.within_radius(&this_coord, self.tolerance.merge_distance());
                 if found.len() == 1 {
                     p.clone()
                 } else {
                     // There are more than one result due to the floating point inaccuracy.
                     // Merge them into one coordination point result
-                    let total_connected_atoms_vec: Vec<Vec<usize>> = found
+                    let total_connected_atoms_vec: Vec<Vec<AtomImage>> = found
                         .iter()
-                        .map(|&&i| points[i].connecting_atom_ids().to_vec())
+                        .map(|(_, i)| points[*i].connecting_atoms().to_vec())
                         .collect();
                     let mut total_atoms = total_connected_atoms_vec.concat();
                     total_atoms.sort();
@@ -326,27 +841,84 @@ impl<'a> IntersectChecker<'a, PointStage> {
                 }
             })
             // New: conditional check to rule out situations that the found point actually has closer connections to atoms in the original lattice model,
-            // besides the previously reported connected atoms
-            .filter(|cp| {
-                let this_coord = cp.coord();
-                let found = self
-                    .coords_kdtree
-                    .within_radius(&this_coord, self.bondlength + 0.00000001);
-                let mut found_cn: Vec<usize> = found.iter().map(|id| **id).collect();
-                found_cn.sort();
-                let mut cn: Vec<usize> = cp.connecting_atom_ids().to_vec();
-                cn.sort();
-                if found_cn == cn {
-                    println!("found {:?}", found_cn);
-                    println!("cn {:?}", cn);
-                }
-                // 0.0001 is the tolerance of floating point comparison.
-                // After adding this, no more cases of `found.len() < cp.cn()` is reported
-                found.len() == cp.cn() as usize && found_cn == cn
-            })
+            // besides the previously reported connected atoms.
+            // The connecting atoms are updated to all atoms within the bondlength windows, with the measured distances.
+            .filter_map(|cp| self.measure_bonds(cp))
             .collect();
-        res
+        self.merge_window_duplicates(res)
+    }
+    /// Measure the distances from the point to the surrounding atoms.
+    /// Returns `None` if any atom is closer than its minimum accepted bondlength,
+    /// or the point does not connect to all the previously reported atoms.
+    /// The atoms excluded from bonding are checked for the clashes, but not recorded as bonds.
+    /// # Notes:
+    /// The tolerance, 10 times the merge distance, covers the flooring of the coordinates in `merge_points`.
+    fn measure_bonds(&self, cp: CoordinationPoint) -> Option<CoordinationPoint> {
+        let this_coord = cp.coord();
+        let bond_tolerance = 10.0 * self.tolerance.merge_distance();
+        let mut bonds: Vec<(AtomImage, f64)> = Vec::new();
+        for (coord, image_id) in self
+            .coords_kdtree
+            .within_radius(&this_coord, self.max_window_bondlength() + bond_tolerance)
+        {
+            let image = self.images.images()[*image_id];
+            let distance = distance(&this_coord, coord);
+            let (min, max) = self.bondlength_window_of(&image);
+            if distance < min - bond_tolerance {
+                return None;
+            }
+            if distance <= max + bond_tolerance && self.is_bonding_atom(&image) {
+                bonds.push((image, distance));
+            }
+        }
+        bonds.sort_by_key(|bond| bond.0);
+        let is_connected = cp
+            .connecting_atoms()
+            .iter()
+            .all(|atom| bonds.iter().any(|(image, _)| image == atom));
+        if !is_connected {
+            return None;
+        }
+        let (connecting_atoms, distances): (Vec<AtomImage>, Vec<f64>) = bonds.into_iter().unzip();
+        let mut measured = cp;
+        measured.set_bonds(connecting_atoms, distances);
+        Some(measured)
     }
+    /// Within the bondlength windows, the intersections of different circle pairs around the same site
+    /// are scattered points connecting the same atoms. Keep the one closest to the target bondlengths.
+    fn merge_window_duplicates(&self, points: Vec<CoordinationPoint>) -> Vec<CoordinationPoint> {
+        let merge_radius = self.max_window_width();
+        if merge_radius == 0.0 {
+            return points;
+        }
+        let deviation = |cp: &CoordinationPoint| -> f64 {
+            cp.connecting_atoms()
+                .iter()
+                .zip(cp.bond_distances())
+                .map(|(atom, d)| (d - self.bondlength_of(atom)).powi(2))
+                .sum()
+        };
+        let mut merged: Vec<CoordinationPoint> = Vec::new();
+        points.into_iter().for_each(|cp| {
+            let duplicate = merged.iter().position(|existed| {
+                existed.connecting_atoms() == cp.connecting_atoms()
+                    && distance(&existed.coord(), &cp.coord()) < merge_radius
+            });
+            match duplicate {
+                Some(i) => {
+                    if deviation(&cp) < deviation(&merged[i]) {
+                        merged[i] = cp
+                    }
+                }
+                None => merged.push(cp),
+            }
+        });
+        merged
+    }
+}
+
+fn max_radius_of(radii: &[f64]) -> f64 {
+    radii.iter().cloned().fold(0.0, f64::max)
 }
 
 impl<'a> IntersectChecker<'a, FinalReport> {
@@ -354,3 +926,252 @@ impl<'a> IntersectChecker<'a, FinalReport> {
         &self.state
     }
 }
+
+#[cfg(test)]
+mod test {
+    use chemrust_core::data::LatticeVectors;
+    use nalgebra::{distance, Matrix3, Point3, Vector3};
+
+    use super::IntersectChecker;
+    use crate::analyzer::algorithm::{AtomImage, Ready};
+
+    #[test]
+    fn clash_free_arcs() {
+        // Within the bondlength window, the circle between the first two atoms
+        // is partially blocked by the third one below.
+        let coords = vec![
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(-1.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -0.3),
+        ];
+        let windows = vec![(1.3, 1.7); 3];
+        let checker = IntersectChecker::<Ready>::new(&coords)
+            .set_check_atoms(&coords[0..2])
+            .set_bondlength_windows(&windows)
+            .start_with_radius(1.5)
+            .check_spheres()
+            .analyze_circle_intersects()
+            .analyze_points();
+        let report = checker.report();
+        let pair = [AtomImage::home(0), AtomImage::home(1)];
+        assert!(report
+            .circles()
+            .iter()
+            .all(|c| c.connecting_atoms() != pair));
+        let arcs: Vec<_> = report
+            .arcs()
+            .iter()
+            .filter(|a| a.connecting_atoms() == pair)
+            .collect();
+        assert_eq!(arcs.len(), 1);
+        assert!(arcs[0].arc().theta() < std::f64::consts::PI * 2.0);
+        assert!(arcs[0].representative().z > 1.0);
+        arcs[0]
+            .samples(10)
+            .iter()
+            .for_each(|p| assert!(distance(p, &coords[2]) > 1.3 - 1e-6));
+    }
+
+    #[test]
+    fn clash_free_arcs_across_boundary() {
+        // The same geometry as above, with the pair bonded across the `a` boundary:
+        // both atoms find the cut circle, through the image of the other one.
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal(&Vector3::new(6.0, 10.0, 10.0)));
+        let coords = vec![
+            Point3::new(1.0, 5.0, 5.0),
+            Point3::new(5.0, 5.0, 5.0),
+            Point3::new(0.0, 5.0, 4.7),
+        ];
+        let windows = vec![(1.3, 1.7); 3];
+        let checker = IntersectChecker::<Ready>::new(&coords)
+            .set_check_atoms(&coords[0..2])
+            .set_lattice_vectors(Some(&lattice))
+            .set_bondlength_windows(&windows)
+            .start_with_radius(1.5)
+            .check_spheres()
+            .analyze_circle_intersects()
+            .analyze_points();
+        let report = checker.report();
+        let is_pair = |atoms: &[AtomImage; 2]| {
+            let mut ids = [atoms[0].atom_id(), atoms[1].atom_id()];
+            ids.sort();
+            ids == [0, 1]
+        };
+        assert!(report
+            .circles()
+            .iter()
+            .all(|c| !is_pair(&c.connecting_atoms())));
+        let arcs: Vec<_> = report
+            .arcs()
+            .iter()
+            .filter(|a| is_pair(&a.connecting_atoms()))
+            .collect();
+        assert_eq!(arcs.len(), 1);
+        assert!(arcs[0].arc().center().x.abs() < 1e-6);
+        assert!(arcs[0].representative().z > 6.0);
+    }
+
+    #[test]
+    fn hollow_site_across_boundary() {
+        // A square slab with one atom per cell: the hollow sites sit at the cell corner,
+        // bonded to four images of the same atom.
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal(&Vector3::new(3.0, 3.0, 15.0)));
+        let coords = vec![Point3::new(1.5, 1.5, 5.0)];
+        let checker = IntersectChecker::<Ready>::new(&coords)
+            .set_lattice_vectors(Some(&lattice))
+            .start_with_radius(2.5)
+            .check_spheres()
+            .analyze_circle_intersects()
+            .analyze_points();
+        let hollow_sites: Vec<_> = checker
+            .report()
+            .multi_cn_points()
+            .iter()
+            .filter(|p| p.cn() == 4)
+            .collect();
+        // One above and one below the slab, each reported once
+        assert_eq!(hollow_sites.len(), 2);
+        let on_boundary = |x: f64| x.min(3.0 - x) < 1e-4;
+        hollow_sites.iter().for_each(|p| {
+            let coord = p.coord();
+            assert!((0.0..3.0).contains(&coord.x) && (0.0..3.0).contains(&coord.y));
+            assert!(on_boundary(coord.x) && on_boundary(coord.y));
+            assert!(((coord.z - 5.0).abs() - (2.5_f64.powi(2) - 4.5).sqrt()).abs() < 1e-4);
+            let mut offsets: Vec<[i32; 3]> = p
+                .connecting_atoms()
+                .iter()
+                .map(|image| {
+                    assert_eq!(image.atom_id(), 0);
+                    image.offset()
+                })
+                .collect();
+            // The images around the wrapped corner
+            offsets.iter().for_each(|offset| {
+                let image_coord = coords[0]
+                    + Vector3::new(
+                        3.0 * offset[0] as f64,
+                        3.0 * offset[1] as f64,
+                        15.0 * offset[2] as f64,
+                    );
+                assert!((distance(&coord, &image_coord) - 2.5).abs() < 1e-4);
+            });
+            offsets.sort();
+            offsets.dedup();
+            assert_eq!(offsets.len(), 4);
+        });
+    }
+
+    #[test]
+    fn outward_sphere_site() {
+        // A bottom-layer atom bonded to the atoms above: the adatom goes downwards.
+        let coords = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.2, 0.0, 1.6),
+            Point3::new(-0.6, 1.04, 1.6),
+            Point3::new(-0.6, -1.04, 1.6),
+        ];
+        let checker = IntersectChecker::<Ready>::new(&coords)
+            .set_check_atoms(&coords[0..1])
+            .start_with_radius(0.9)
+            .check_spheres()
+            .analyze_circle_intersects()
+            .analyze_points();
+        let spheres = checker.report().sphere_sites();
+        assert_eq!(spheres.len(), 1);
+        let direction = spheres[0].mounting_direction();
+        assert!((direction.z + 1.0).abs() < 1e-6);
+        assert!((spheres[0].mounting_point().z + 0.9).abs() < 1e-6);
+    }
+
+    #[test]
+    fn bondlength_window() {
+        // A rhombus hollow: no point is exactly 2.5 Å to all four atoms.
+        let coords = vec![
+            Point3::new(1.5, 0.0, 0.0),
+            Point3::new(-1.5, 0.0, 0.0),
+            Point3::new(0.0, 1.6, 0.0),
+            Point3::new(0.0, -1.6, 0.0),
+        ];
+        let exact_checker = IntersectChecker::<Ready>::new(&coords)
+            .start_with_radius(2.5)
+            .check_spheres()
+            .analyze_circle_intersects()
+            .analyze_points();
+        assert!(exact_checker
+            .report()
+            .multi_cn_points()
+            .iter()
+            .all(|p| p.cn() < 4));
+        let windows = vec![(2.35, 2.65); 4];
+        let window_checker = IntersectChecker::<Ready>::new(&coords)
+            .set_bondlength_windows(&windows)
+            .start_with_radius(2.5)
+            .check_spheres()
+            .analyze_circle_intersects()
+            .analyze_points();
+        let hollow_sites: Vec<_> = window_checker
+            .report()
+            .multi_cn_points()
+            .iter()
+            .filter(|p| p.cn() == 4)
+            .collect();
+        // One above and one below the plane of the atoms
+        assert_eq!(hollow_sites.len(), 2);
+        hollow_sites.iter().for_each(|p| {
+            assert_eq!(p.bond_distances().len(), 4);
+            assert!(p
+                .bond_distances()
+                .iter()
+                .all(|&d| (2.35 - 1e-4..=2.65 + 1e-4).contains(&d)));
+        });
+    }
+    #[test]
+    fn deterministic_order() {
+        // A square of atoms: the circles between the neighbours cross above and below the hollow
+        let coords = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(2.8, 0.0, 0.0),
+            Point3::new(0.0, 2.8, 0.0),
+            Point3::new(2.8, 2.8, 0.0),
+            Point3::new(1.4, 1.4, 2.0),
+        ];
+        let run = || {
+            let checker = IntersectChecker::<Ready>::new(&coords)
+                .start_with_radius(2.2)
+                .check_spheres()
+                .analyze_circle_intersects()
+                .analyze_points();
+            checker
+                .report()
+                .multi_cn_points()
+                .iter()
+                .map(|p| (p.coord(), p.connecting_atoms().to_vec()))
+                .collect::<Vec<_>>()
+        };
+        let first = run();
+        assert!(!first.is_empty());
+        (0..5).for_each(|_| assert_eq!(run(), first));
+    }
+    #[test]
+    fn non_bonding_atoms() {
+        let coords = vec![Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 0.0, 0.0)];
+        let checker = IntersectChecker::<Ready>::new(&coords)
+            .start_with_radius(1.5)
+            .check_spheres()
+            .analyze_circle_intersects()
+            .analyze_points();
+        assert_eq!(checker.report().circles().len(), 1);
+        // The second atom never bonds, but still keeps the new atom away
+        let checker = IntersectChecker::<Ready>::new(&coords)
+            .set_bonding_atoms(&[true, false])
+            .start_with_radius(1.5)
+            .check_spheres()
+            .analyze_circle_intersects()
+            .analyze_points();
+        let report = checker.report();
+        assert!(report.circles().is_empty());
+        assert_eq!(report.sphere_sites().len(), 1);
+        assert_eq!(report.sphere_sites()[0].locating_atom_id(), 0);
+        assert!(distance(&report.sphere_sites()[0].mounting_point(), &coords[1]) > 1.5 - 1e-6);
+    }
+}
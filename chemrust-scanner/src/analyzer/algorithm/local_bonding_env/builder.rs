@@ -18,12 +18,15 @@ use kd_tree::KdMap;
 use nalgebra::Point3;
 
 use crate::analyzer::algorithm::local_bonding_env::bonding_scheme::{ideal_bondlength, is_bonded};
+use crate::analyzer::algorithm::{AtomImage, PeriodicImages};
 use crate::analyzer::mounting_analyze::{LOWER_FAC, UPPER_FAC};
 
 use super::LocalBondingEnv;
 /// Struct to build a `LocalBondingEnv`
 pub struct LocalBondingEnvBuilder<'a> {
     atoms: &'a [Atom],
+    /// The atoms and their periodic images, so the bonds across the cell boundary are found.
+    images: PeriodicImages,
     coord_kdtree: KdMap<Point3<f64>, CheckAtom>,
 }
 
@@ -31,15 +34,15 @@ pub struct LocalBondingEnvBuilder<'a> {
 #[derive(Debug, Clone, Copy)]
 struct CheckAtom {
     atomic_number: u8,
-    atom_index: usize,
+    image: AtomImage,
     coord: Point3<f64>,
 }
 
 impl CheckAtom {
-    pub fn new(atomic_number: u8, atom_index: usize, coord: Point3<f64>) -> Self {
+    pub fn new(atomic_number: u8, image: AtomImage, coord: Point3<f64>) -> Self {
         Self {
             atomic_number,
-            atom_index,
+            image,
             coord,
         }
     }
@@ -49,7 +52,11 @@ impl CheckAtom {
     }
 
     pub fn atom_index(&self) -> usize {
-        self.atom_index
+        self.image.atom_id()
+    }
+
+    pub fn image(&self) -> AtomImage {
+        self.image
     }
 
     pub fn coord(&self) -> Point3<f64> {
@@ -59,92 +66,102 @@ impl CheckAtom {
 
 impl<'a> LocalBondingEnvBuilder<'a> {
     /// Initiate a builder instance. The lifetime is tied to that of the input `LatticeModel`.
+    /// # Notes:
+    /// The atoms are identified by their positions in `lattice_model.atoms()`. The periodic images
+    /// are generated within the longest possible bond, `UPPER_FAC` times the largest ideal bondlength.
     pub fn new(lattice_model: &'a BasicLatticeModel) -> Self {
-        let items = lattice_model
-            .atoms()
+        let atoms = lattice_model.atoms();
+        let coords: Vec<Point3<f64>> = atoms.iter().map(|atom| atom.cartesian_coord()).collect();
+        let cutoff = atoms
             .iter()
-            .map(|atom| -> (Point3<f64>, CheckAtom) {
-                (
-                    atom.cartesian_coord(),
-                    CheckAtom::new(atom.atomic_number(), atom.index(), atom.cartesian_coord()),
-                )
+            .map(|atom| UPPER_FAC * ideal_bondlength(atom.atomic_number(), atom.atomic_number()))
+            .fold(0.0, f64::max);
+        let images = PeriodicImages::new(&coords, lattice_model.lattice_vectors(), cutoff);
+        let items = images
+            .images()
+            .iter()
+            .zip(images.coords())
+            .map(|(image, coord)| -> (Point3<f64>, CheckAtom) {
+                let atomic_number = atoms[image.atom_id()].atomic_number();
+                (*coord, CheckAtom::new(atomic_number, *image, *coord))
             })
             .collect();
         let coord_kdtree = KdMap::build_by_ordered_float(items);
         Self {
-            atoms: lattice_model.atoms(),
+            atoms,
+            images,
             coord_kdtree,
         }
     }
-    /// Returns number of bonded atoms around the given atom at given number of neighbors
-    /// # Args:
-    /// - atom: `&Atom` - Reference of the inspected atom.
-    /// - initial_neighbor_num: `usize` - Default will be 5 (means 4 neighbors excluding itself),
-    fn local_bonding_env_bonded_num(&self, atom: &Atom, initial_neighbor_num: usize) -> usize {
+    /// Returns the bonded atoms among the given number of nearest neighbors, including the atom itself.
+    fn bonded_neighbors(&self, atom_id: usize, neighbor_num: usize) -> Vec<CheckAtom> {
+        let atom = &self.atoms[atom_id];
         let coord = atom.cartesian_coord();
-        let found = self.coord_kdtree.nearests(&coord, initial_neighbor_num); // 5 because includes itself
+        let found = self.coord_kdtree.nearests(&coord, neighbor_num);
         found
             .iter()
-            .skip(1)
+            .filter(|item_dist| item_dist.item.1.image() != AtomImage::home(atom_id))
             .filter(|item_dist| {
-                let atomic_number_found = item_dist.item.1.atomic_number;
+                let atomic_number_found = item_dist.item.1.atomic_number();
                 let atomic_number_this = atom.atomic_number();
                 let ideal_bondlength = ideal_bondlength(atomic_number_this, atomic_number_found);
                 is_bonded(
-                    item_dist.squared_distance,
+                    item_dist.squared_distance.sqrt(),
                     ideal_bondlength,
                     LOWER_FAC,
                     UPPER_FAC,
                 )
             })
-            .count()
+            .map(|item_dist| item_dist.item.1)
+            .collect()
+    }
+    /// Returns number of bonded atoms around the given atom at given number of neighbors
+    /// # Args:
+    /// - atom_id: `usize` - Index of the inspected atom.
+    /// - initial_neighbor_num: `usize` - Default will be 5 (means 4 neighbors excluding itself),
+    fn local_bonding_env_bonded_num(&self, atom_id: usize, initial_neighbor_num: usize) -> usize {
+        self.bonded_neighbors(atom_id, initial_neighbor_num).len()
     }
-    /// Determine the bonding neighbors by definition in `super::bonding_scheme`.
+    /// Determine the number of nearest neighbors to look up, which covers all the bonding neighbors
+    /// by definition in `super::bonding_scheme`.
     /// # Args:
     /// - &self
-    /// - atom: &Atom
-    /// # Returns: the final actual bonding neighbors.
+    /// - atom_id: usize
+    /// # Returns: the number of nearest neighbors to look up, including the atom itself.
     /// # Notes:
     /// - Starts by checking number of atoms in model. If the model has less than 5 atoms, adjust to fit the case.
-    /// - If all neighbors are bonded, increment the `initial_neighbor_num` by 2 per round, stops when `bonding_num` < `initial_neighbor_num`
-    fn determine_bonding_neighbors_num(&self, atom: &Atom) -> usize {
-        let mut initial_neighbor_num = if self.atoms.len() < 5 {
-            self.atoms.len()
-        } else {
-            5
-        };
-        let mut bonded_num = self.local_bonding_env_bonded_num(atom, initial_neighbor_num);
-        while bonded_num == initial_neighbor_num {
-            initial_neighbor_num += 2;
-            bonded_num = self.local_bonding_env_bonded_num(atom, initial_neighbor_num);
+    /// - If all neighbors are bonded, increment the `initial_neighbor_num` by 2 per round, stops when `bonding_num` < `initial_neighbor_num - 1`
+    fn determine_bonding_neighbors_num(&self, atom_id: usize) -> usize {
+        let total_num = self.images.coords().len();
+        let mut initial_neighbor_num = total_num.min(5);
+        let mut bonded_num = self.local_bonding_env_bonded_num(atom_id, initial_neighbor_num);
+        while bonded_num + 1 == initial_neighbor_num && initial_neighbor_num < total_num {
+            initial_neighbor_num = total_num.min(initial_neighbor_num + 2);
+            bonded_num = self.local_bonding_env_bonded_num(atom_id, initial_neighbor_num);
         }
-        bonded_num
+        initial_neighbor_num
     }
     /// Returns the `LocalBondingEnv` of the atom.
-    fn get_local_bonding_env(&'a self, atom: &'a Atom) -> LocalBondingEnv {
-        let bonding_neighbors_num = self.determine_bonding_neighbors_num(atom);
-        let found = self
-            .coord_kdtree
-            .nearests(&atom.cartesian_coord(), bonding_neighbors_num);
-        let atoms: Vec<&Atom> = found
+    fn get_local_bonding_env(&self, atom_id: usize) -> LocalBondingEnv<'a> {
+        let neighbor_num = self.determine_bonding_neighbors_num(atom_id);
+        let bonded = self.bonded_neighbors(atom_id, neighbor_num);
+        let atoms: Vec<&Atom> = bonded
             .iter()
-            .skip(1)
-            .map(|res| {
-                let index = res.item.1.atom_index();
-                self.atoms.get(index).unwrap()
-            })
+            .map(|check_atom| &self.atoms[check_atom.atom_index()])
             .collect();
         LocalBondingEnv {
-            center_atom: atom,
-            number_of_bonding_atoms: bonding_neighbors_num,
+            center_atom: &self.atoms[atom_id],
+            center_id: atom_id,
+            number_of_bonding_atoms: bonded.len(),
             atoms,
+            images: bonded.iter().map(|check_atom| check_atom.image()).collect(),
+            coords: bonded.iter().map(|check_atom| check_atom.coord()).collect(),
         }
     }
-    /// Build `LocalBondingEnv` for all `Atom` in `LatticeMoel`
-    pub fn build_local_bonding_envs(&self) -> Vec<LocalBondingEnv> {
-        self.atoms
-            .iter()
-            .map(|atom| -> LocalBondingEnv { self.get_local_bonding_env(atom) })
+    /// Build `LocalBondingEnv` for all `Atom` in `LatticeMoel`, in the same order of the atoms.
+    pub fn build_local_bonding_envs(&self) -> Vec<LocalBondingEnv<'a>> {
+        (0..self.atoms.len())
+            .map(|atom_id| -> LocalBondingEnv { self.get_local_bonding_env(atom_id) })
             .collect()
     }
 }
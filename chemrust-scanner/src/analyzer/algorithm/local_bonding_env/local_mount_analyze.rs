@@ -5,41 +5,394 @@
 //! When examining a pair of atoms, their atom ids are sorted to return a pair, (id_smaller, id_larger),
 //! then the global table is consulted. If this pair exists, skip the mounting scanning, else perform
 //! the scanning and add the pair to the table.
+//!
+//! In each LBE, the new atom is searched only around the center atom and its bonded neighbours:
+//! - The spheres of the center atom and each bonded neighbour intersect as a circle, which is
+//!   looked up in the pair table first. The pair is kept as periodic images translated to put the
+//!   smaller atom in the home cell, so the same bond seen from both ends shares one entry.
+//! - The circles of the same LBE intersect at the points bonding the center and two neighbours.
+//! - An atom without any circle gives a sphere site along its outward direction.
+//!
+//! A site is dropped if it clashes with any atom, or if any of its new bonds is closer than
+//! `min_bond_angle` to an existing bond of the same host atom, i.e., the new atom would sit on top
+//! of the existing bond. A circle is reported as a whole only if all of its points are acceptable;
+//! the partially acceptable circles are not cut into arcs, but their intersections are still checked.
 
-use std::collections::{HashMap, HashSet};
-
-use castep_periodic_table::{
-    data::ELEMENT_TABLE,
-    element::{Element, LookupElement},
+use std::{
+    collections::HashMap,
+    f64::consts::{FRAC_PI_6, PI},
 };
-use chemrust_core::data::Atom;
 
-use crate::analyzer::algorithm::BondingCircle;
+use chemrust_core::data::BasicLatticeModel;
+use itertools::Itertools;
+use kd_tree::KdMap;
+use nalgebra::{distance, Point3, UnitVector3, Vector3};
 
-use super::{
-    bonding_scheme::{is_bonded, LOWER_FAC, UPPER_FAC},
-    LocalBondingEnv,
+use crate::analyzer::{
+    algorithm::{
+        AtomImage, BondingCircle, BondingSphere, CoordinationPoint, FinalReport, PeriodicImages,
+    },
+    geometry::{
+        CircleIntersectChecker, CircleIntersectResult, Intersect, Sphere, SphereIntersectResult,
+        Tolerance,
+    },
 };
 
+use super::{LocalBondingEnv, LocalBondingEnvBuilder};
+
 pub struct LBEMountingChecker<'a> {
     local_bonding_envs: Vec<LocalBondingEnv<'a>>,
-    target_bondlength: f64,
-    target_atom_covalent_radius: f64,
-    checked_spheres_result: HashMap<[usize; 2], Option<BondingCircle>>,
+    /// The atoms and their periodic images, to check the clashes around the sites
+    images: PeriodicImages,
+    /// Maps the coordinates of `images` to the image ids
+    coords_kdtree: KdMap<Point3<f64>, usize>,
+    /// Target bondlength to each atom, by atom id
+    bondlengths: Vec<f64>,
+    /// Whether each atom can bond to the new atom, by atom id. Empty if all atoms can.
+    bonding_atoms: Vec<bool>,
+    /// The smallest accepted angle between a new bond and an existing bond of the same host atom
+    min_bond_angle: f64,
+    tolerance: Tolerance,
+    /// The circle of each checked atom pair, `None` if the two spheres do not intersect as a circle
+    checked_spheres_result: HashMap<[AtomImage; 2], Option<BondingCircle>>,
 }
 
 impl<'a> LBEMountingChecker<'a> {
-    pub fn new(
-        local_bonding_envs: Vec<LocalBondingEnv<'a>>,
-        target_bondlength: f64,
-        target_atom_covalent_radius: f64,
-    ) -> Self {
-        let checked_spheres_result = HashMap::new();
+    /// Build the LBEs of all atoms in the model.
+    /// The target bondlength to each atom is given in the same order of the atoms.
+    /// # Panics
+    /// Panics if the number of bondlengths does not match the number of atoms.
+    pub fn new(lattice_model: &'a BasicLatticeModel, bondlengths: &[f64]) -> Self {
+        assert_eq!(bondlengths.len(), lattice_model.atoms().len());
+        let local_bonding_envs =
+            LocalBondingEnvBuilder::new(lattice_model).build_local_bonding_envs();
+        let coords: Vec<Point3<f64>> = lattice_model
+            .atoms()
+            .iter()
+            .map(|atom| atom.cartesian_coord())
+            .collect();
+        let max_bondlength = bondlengths.iter().cloned().fold(0.0, f64::max);
+        // The circles are sampled up to `2 * bondlength` away from the home atoms,
+        // and the clashes are searched within another `bondlength`.
+        let images = PeriodicImages::new(
+            &coords,
+            lattice_model.lattice_vectors(),
+            3.0 * max_bondlength,
+        );
+        let coords_kdtree = images.kdtree();
         Self {
             local_bonding_envs,
-            target_bondlength,
-            target_atom_covalent_radius,
-            checked_spheres_result,
+            images,
+            coords_kdtree,
+            bondlengths: bondlengths.to_vec(),
+            bonding_atoms: Vec::new(),
+            min_bond_angle: FRAC_PI_6,
+            tolerance: Tolerance::default(),
+            checked_spheres_result: HashMap::new(),
+        }
+    }
+    /// Whether each atom, in the same order of the atoms, can bond to the new atom.
+    /// The atoms excluded from bonding still block the sites too close to them.
+    pub fn with_bonding_atoms(self, bonding_atoms: &[bool]) -> Self {
+        Self {
+            bonding_atoms: bonding_atoms.to_vec(),
+            ..self
         }
     }
+    /// The smallest accepted angle, in radians, between a new bond and an existing bond
+    /// of the same host atom. Defaults to 30 degrees.
+    pub fn with_min_bond_angle(self, min_bond_angle: f64) -> Self {
+        Self {
+            min_bond_angle,
+            ..self
+        }
+    }
+    pub fn with_tolerance(self, tolerance: Tolerance) -> Self {
+        Self {
+            images: self.images.with_tolerance(tolerance),
+            tolerance,
+            ..self
+        }
+    }
+
+    pub fn local_bonding_envs(&self) -> &[LocalBondingEnv<'a>] {
+        self.local_bonding_envs.as_ref()
+    }
+    /// Search the sites in the LBEs of the given atoms, by their indices in the model.
+    /// The checked pairs are kept in the table for the following searches.
+    pub fn mount_search(&mut self, to_check_ids: &[usize]) -> FinalReport {
+        let mut spheres: Vec<BondingSphere> = Vec::new();
+        let mut circles: Vec<BondingCircle> = Vec::new();
+        let mut points: Vec<CoordinationPoint> = Vec::new();
+        for &center_id in to_check_ids {
+            if !self.is_bonding_atom(center_id) {
+                continue;
+            }
+            let neighbours: Vec<AtomImage> = self.local_bonding_envs[center_id]
+                .images()
+                .iter()
+                .filter(|image| self.is_bonding_atom(image.atom_id()))
+                .copied()
+                .collect();
+            let mut lbe_circles: Vec<BondingCircle> = Vec::new();
+            neighbours.iter().for_each(|neighbour| {
+                let (bonding_circle, is_new) =
+                    self.pair_circle([AtomImage::home(center_id), *neighbour]);
+                if let Some(bonding_circle) = bonding_circle {
+                    if is_new && self.is_acceptable_circle(&bonding_circle) {
+                        circles.push(self.wrap_circle(&bonding_circle))
+                    }
+                    lbe_circles.push(bonding_circle)
+                }
+            });
+            if lbe_circles.is_empty() {
+                if let Some(sphere) = self.sphere_site(center_id) {
+                    spheres.push(sphere)
+                }
+                continue;
+            }
+            lbe_circles
+                .iter()
+                .tuple_combinations()
+                .for_each(|(circle_a, circle_b)| {
+                    let found =
+                        match CircleIntersectChecker::new(&circle_a.circle(), &circle_b.circle())
+                            .with_tolerance(self.tolerance)
+                            .check()
+                        {
+                            CircleIntersectResult::Single(p) => vec![p],
+                            CircleIntersectResult::Double((p, q)) => vec![p, q],
+                            _ => Vec::new(),
+                        };
+                    let mut connecting: Vec<AtomImage> =
+                        [circle_a.connecting_atoms(), circle_b.connecting_atoms()].concat();
+                    connecting.sort();
+                    connecting.dedup();
+                    found
+                        .into_iter()
+                        .filter_map(|p| self.measure_point(&p, &connecting))
+                        .for_each(|cp| {
+                            let is_copy = points.iter().any(|existed| {
+                                self.tolerance.same_site(&existed.coord(), &cp.coord())
+                            });
+                            if !is_copy {
+                                points.push(cp)
+                            }
+                        });
+                });
+        }
+        let (cut_points, multi_cn_points): (Vec<CoordinationPoint>, Vec<CoordinationPoint>) =
+            points.into_iter().partition(|cp| cp.cn() < 3);
+        FinalReport::new(spheres, circles, Vec::new(), cut_points, multi_cn_points)
+    }
+    fn is_bonding_atom(&self, atom_id: usize) -> bool {
+        self.bonding_atoms.is_empty() || self.bonding_atoms[atom_id]
+    }
+    fn image_coord(&self, image: &AtomImage) -> Point3<f64> {
+        self.images.image_coord(image)
+    }
+    fn max_bondlength(&self) -> f64 {
+        self.bondlengths.iter().cloned().fold(0.0, f64::max)
+    }
+    /// The circle of the atom pair, in the frame of the given images, and whether it is newly checked.
+    /// # Notes:
+    /// The table keeps the pair sorted and translated to put the first atom in the home cell.
+    fn pair_circle(&mut self, pair: [AtomImage; 2]) -> (Option<BondingCircle>, bool) {
+        let mut sorted = pair;
+        sorted.sort();
+        let shift = sorted[0].offset();
+        let back_shift = [-shift[0], -shift[1], -shift[2]];
+        let key = [
+            sorted[0].translate(back_shift),
+            sorted[1].translate(back_shift),
+        ];
+        let is_new = !self.checked_spheres_result.contains_key(&key);
+        if is_new {
+            let result = self.intersect_pair(key);
+            self.checked_spheres_result.insert(key, result);
+        }
+        let bonding_circle = self.checked_spheres_result[&key].map(|bonding_circle| {
+            let mut circle = bonding_circle.circle();
+            circle.center += self.images.translation(shift);
+            let [atom_a, atom_b] = bonding_circle.connecting_atoms();
+            BondingCircle::new(circle, [atom_a.translate(shift), atom_b.translate(shift)])
+        });
+        (bonding_circle, is_new)
+    }
+    fn intersect_pair(&self, pair: [AtomImage; 2]) -> Option<BondingCircle> {
+        let [atom_a, atom_b] = pair;
+        let sphere_a = Sphere::new(
+            self.image_coord(&atom_a),
+            self.bondlengths[atom_a.atom_id()],
+        );
+        let sphere_b = Sphere::new(
+            self.image_coord(&atom_b),
+            self.bondlengths[atom_b.atom_id()],
+        );
+        match sphere_a.intersects_within(&sphere_b, &self.tolerance) {
+            SphereIntersectResult::Circle(circle) => Some(BondingCircle::new(circle, pair)),
+            _ => None,
+        }
+    }
+    /// Whether any atom is closer to the point than its target bondlength.
+    fn is_clashed(&self, point: &Point3<f64>) -> bool {
+        let bond_tolerance = 10.0 * self.tolerance.merge_distance();
+        self.coords_kdtree
+            .within_radius(point, self.max_bondlength())
+            .into_iter()
+            .any(|(coord, image_id)| {
+                let atom_id = self.images.images()[*image_id].atom_id();
+                distance(point, coord) < self.bondlengths[atom_id] - bond_tolerance
+            })
+    }
+    /// Whether the bonds from the connecting atoms to the point stay away from their existing bonds
+    /// by at least `min_bond_angle`.
+    fn respects_bond_directions(&self, point: &Point3<f64>, connecting: &[AtomImage]) -> bool {
+        connecting.iter().all(|image| {
+            let new_bond = point - self.image_coord(image);
+            self.local_bonding_envs[image.atom_id()]
+                .bond_vectors()
+                .iter()
+                .all(|bond| new_bond.angle(bond) >= self.min_bond_angle)
+        })
+    }
+    /// Sample the circle by about one degree, every point has to be free of clashes
+    /// and respect the existing bond directions.
+    fn is_acceptable_circle(&self, bonding_circle: &BondingCircle) -> bool {
+        let circle = bonding_circle.circle();
+        (0..360).all(|i| {
+            let point = circle.point_at(i as f64 * PI / 180.0);
+            !self.is_clashed(&point)
+                && self.respects_bond_directions(&point, &bonding_circle.connecting_atoms())
+        })
+    }
+    /// The sphere site of an atom without any circle, along the direction opposite to its existing bonds.
+    /// Returns `None` if the position clashes, or the atom is bonded all around.
+    fn sphere_site(&self, atom_id: usize) -> Option<BondingSphere> {
+        let local_bonding_env = &self.local_bonding_envs[atom_id];
+        let bond_sum: Vector3<f64> = local_bonding_env
+            .bond_vectors()
+            .iter()
+            .map(|bond| bond.normalize())
+            .sum();
+        let outward = if bond_sum.norm() < 1e-3 {
+            Vector3::z_axis()
+        } else {
+            UnitVector3::new_normalize(-bond_sum)
+        };
+        let sphere = Sphere::new(
+            local_bonding_env.center_atom().cartesian_coord(),
+            self.bondlengths[atom_id],
+        );
+        let point = sphere.point_at_surface(&outward);
+        if self.is_clashed(&point)
+            || !self.respects_bond_directions(&point, &[AtomImage::home(atom_id)])
+        {
+            return None;
+        }
+        Some(BondingSphere::new(sphere, atom_id).with_mounting_direction(outward))
+    }
+    /// Measure the distances from the point to the surrounding atoms, and wrap it back into the cell.
+    /// Returns `None` if any atom is closer than its target bondlength, the point does not connect
+    /// to all the `connecting` atoms, or any new bond sits on top of an existing bond.
+    /// The atoms excluded from bonding are checked for the clashes, but not recorded as bonds.
+    fn measure_point(
+        &self,
+        point: &Point3<f64>,
+        connecting: &[AtomImage],
+    ) -> Option<CoordinationPoint> {
+        let bond_tolerance = 10.0 * self.tolerance.merge_distance();
+        let mut bonds: Vec<(AtomImage, f64)> = Vec::new();
+        for (coord, image_id) in self
+            .coords_kdtree
+            .within_radius(point, self.max_bondlength() + bond_tolerance)
+        {
+            let image = self.images.images()[*image_id];
+            let distance = distance(point, coord);
+            let bondlength = self.bondlengths[image.atom_id()];
+            if distance < bondlength - bond_tolerance {
+                return None;
+            }
+            if distance <= bondlength + bond_tolerance && self.is_bonding_atom(image.atom_id()) {
+                bonds.push((image, distance));
+            }
+        }
+        bonds.sort_by_key(|bond| bond.0);
+        let is_connected = connecting
+            .iter()
+            .all(|atom| bonds.iter().any(|(image, _)| image == atom));
+        let (connecting_atoms, distances): (Vec<AtomImage>, Vec<f64>) = bonds.into_iter().unzip();
+        if !is_connected || !self.respects_bond_directions(point, &connecting_atoms) {
+            return None;
+        }
+        let (wrapped, shift) = self.images.wrap(point);
+        let back_shift = [-shift[0], -shift[1], -shift[2]];
+        let connecting_atoms: Vec<AtomImage> = connecting_atoms
+            .iter()
+            .map(|image| image.translate(back_shift))
+            .collect();
+        let mut measured = CoordinationPoint::new(
+            wrapped,
+            connecting_atoms.clone(),
+            connecting_atoms.len() as u32,
+        );
+        measured.set_bonds(connecting_atoms, distances);
+        Some(measured)
+    }
+    /// Wrap the circle center back into the cell, and update the image offsets of the connecting atoms.
+    fn wrap_circle(&self, bonding_circle: &BondingCircle) -> BondingCircle {
+        let mut circle = bonding_circle.circle();
+        let (wrapped, shift) = self.images.wrap(&circle.center);
+        let back_shift = [-shift[0], -shift[1], -shift[2]];
+        circle.center = wrapped;
+        let [atom_a, atom_b] = bonding_circle.connecting_atoms();
+        BondingCircle::new(
+            circle,
+            [atom_a.translate(back_shift), atom_b.translate(back_shift)],
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chemrust_core::data::{Atom, BasicLatticeModel, LatticeVectors};
+    use nalgebra::{Matrix3, Point3, Vector3};
+
+    use crate::analyzer::algorithm::AtomImage;
+
+    use super::LBEMountingChecker;
+
+    #[test]
+    fn bond_across_boundary() {
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal(&Vector3::new(6.0, 10.0, 10.0)));
+        // The two carbons are 1.4 Å apart across the `a` boundary
+        let atoms: Vec<Atom> = [0.2, 4.8]
+            .iter()
+            .enumerate()
+            .map(|(i, &x)| {
+                Atom::new_builder()
+                    .with_symbol("C")
+                    .with_atomic_number(6)
+                    .with_index(i)
+                    .with_coord(&Point3::new(x, 5.0, 5.0))
+                    .ready()
+                    .build()
+            })
+            .collect();
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        let mut checker = LBEMountingChecker::new(&model, &[1.5, 1.5]);
+        let envs = checker.local_bonding_envs();
+        assert_eq!(envs[0].number_of_bonding_atoms(), 1);
+        assert_eq!(envs[0].images(), [AtomImage::new(1, [-1, 0, 0])]);
+        let report = checker.mount_search(&[0, 1]);
+        // The same bond seen from both atoms is checked once
+        assert_eq!(checker.checked_spheres_result.len(), 1);
+        assert_eq!(report.circles().len(), 1);
+        assert!(report.sphere_sites().is_empty());
+        // With a short bondlength, the new atom sits almost on the C-C bond
+        let mut checker = LBEMountingChecker::new(&model, &[0.75, 0.75]);
+        assert!(checker.mount_search(&[0, 1]).circles().is_empty());
+        let mut checker = LBEMountingChecker::new(&model, &[0.75, 0.75]).with_min_bond_angle(0.1);
+        assert_eq!(checker.mount_search(&[0, 1]).circles().len(), 1);
+    }
 }
@@ -13,19 +13,60 @@
 //! 4. Returns an array of local bonding environments. The new bonding site search will be conducted in each LBE.
 
 use chemrust_core::data::Atom;
+use nalgebra::{Point3, Vector3};
+
+use super::AtomImage;
 
 mod bonding_scheme;
 mod builder;
-// mod local_mount_analyze;
+mod local_mount_analyze;
 
 pub use bonding_scheme::{ideal_bondlength, is_bonded};
-
+pub use builder::LocalBondingEnvBuilder;
+pub use local_mount_analyze::LBEMountingChecker;
 
 #[derive(Debug, Clone)]
 /// The local bonding environment around each atom.
 /// The lifetime ties to the `Atom` of `LatticeModel`
 pub struct LocalBondingEnv<'a> {
     center_atom: &'a Atom,
+    /// Index of the center atom in the model
+    center_id: usize,
     number_of_bonding_atoms: usize,
     atoms: Vec<&'a Atom>,
+    /// The bonded atoms as the periodic images seen from the center atom, in the same order of `atoms`
+    images: Vec<AtomImage>,
+    /// Cartesian coordinates of the bonded images
+    coords: Vec<Point3<f64>>,
+}
+
+impl<'a> LocalBondingEnv<'a> {
+    pub fn center_atom(&self) -> &Atom {
+        self.center_atom
+    }
+
+    pub fn center_id(&self) -> usize {
+        self.center_id
+    }
+
+    pub fn number_of_bonding_atoms(&self) -> usize {
+        self.number_of_bonding_atoms
+    }
+
+    pub fn atoms(&self) -> &[&'a Atom] {
+        self.atoms.as_ref()
+    }
+
+    pub fn images(&self) -> &[AtomImage] {
+        self.images.as_ref()
+    }
+
+    pub fn coords(&self) -> &[Point3<f64>] {
+        self.coords.as_ref()
+    }
+    /// The vectors from the center atom to the bonded atoms.
+    pub fn bond_vectors(&self) -> Vec<Vector3<f64>> {
+        let center = self.center_atom.cartesian_coord();
+        self.coords.iter().map(|coord| coord - center).collect()
+    }
 }
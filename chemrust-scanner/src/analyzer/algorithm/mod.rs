@@ -1,9 +1,13 @@
 mod coordination_sites;
 mod intersect_check;
 mod local_bonding_env;
+mod periodic_images;
 mod stages;
 
 pub use coordination_sites::*;
 pub use intersect_check::IntersectChecker;
-pub use local_bonding_env::{ideal_bondlength, is_bonded};
+pub use local_bonding_env::{
+    ideal_bondlength, is_bonded, LBEMountingChecker, LocalBondingEnvBuilder,
+};
+pub use periodic_images::{AtomImage, PeriodicImages};
 pub use stages::*;
@@ -0,0 +1,222 @@
+//! Periodic images of the atoms in a lattice model.
+//! The intersection search works on an expanded set of coordinates: every atom in the model
+//! (the "home" images, offset `[0, 0, 0]`), plus the translated copies of the atoms which fall
+//! within a cutoff distance to the cell. With these images, the sphere, circle and point stages
+//! see the minimum-image neighbours across the cell boundary.
+//! - The home images are placed first, so the image id of an atom in the home cell is its atom id.
+//! - Without lattice vectors (isolated molecules), only the home images are generated.
+use chemrust_core::data::LatticeVectors;
+use kd_tree::KdMap;
+use nalgebra::{Matrix3, Point3, Vector3};
+
+use crate::analyzer::geometry::Tolerance;
+
+/// An atom of the model identified by its index and the lattice translation
+/// of the periodic image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AtomImage {
+    atom_id: usize,
+    offset: [i32; 3],
+}
+
+impl AtomImage {
+    pub fn new(atom_id: usize, offset: [i32; 3]) -> Self {
+        Self { atom_id, offset }
+    }
+    /// The atom itself in the home cell.
+    pub fn home(atom_id: usize) -> Self {
+        Self {
+            atom_id,
+            offset: [0, 0, 0],
+        }
+    }
+
+    pub fn atom_id(&self) -> usize {
+        self.atom_id
+    }
+
+    pub fn offset(&self) -> [i32; 3] {
+        self.offset
+    }
+    /// Returns the same atom image with the offset shifted by the given lattice translation.
+    pub fn translate(&self, shift: [i32; 3]) -> Self {
+        Self {
+            atom_id: self.atom_id,
+            offset: [
+                self.offset[0] + shift[0],
+                self.offset[1] + shift[1],
+                self.offset[2] + shift[2],
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PeriodicImages {
+    images: Vec<AtomImage>,
+    coords: Vec<Point3<f64>>,
+    lattice_matrix: Option<Matrix3<f64>>,
+    frac_matrix: Option<Matrix3<f64>>,
+    tolerance: Tolerance,
+}
+
+impl PeriodicImages {
+    /// Generate the images of `home_coords` within `cutoff` (in angstrom) to the cell.
+    pub fn new(
+        home_coords: &[Point3<f64>],
+        lattice_vectors: Option<&LatticeVectors>,
+        cutoff: f64,
+    ) -> Self {
+        let mut images: Vec<AtomImage> = (0..home_coords.len()).map(AtomImage::home).collect();
+        let mut coords: Vec<Point3<f64>> = home_coords.to_vec();
+        let lattice_matrix = lattice_vectors.map(|lat| *lat.data());
+        let frac_matrix = lattice_vectors.map(|lat| lat.mat_cart_to_frac());
+        if let (Some(lattice), Some(to_frac)) = (lattice_matrix, frac_matrix) {
+            let margins = frac_margins(&lattice, cutoff);
+            let fracs: Vec<Point3<f64>> = home_coords.iter().map(|p| to_frac * p).collect();
+            // The searched space must cover the cell and all the atoms given in the model,
+            // in case the coordinates are not wrapped inside the cell.
+            let (lower, upper): (Vec<f64>, Vec<f64>) = (0..3)
+                .map(|i| {
+                    let min = fracs.iter().map(|f| f[i]).fold(0.0_f64, f64::min);
+                    let max = fracs.iter().map(|f| f[i]).fold(1.0_f64, f64::max);
+                    (min - margins[i], max + margins[i])
+                })
+                .unzip();
+            fracs.iter().enumerate().for_each(|(atom_id, frac)| {
+                let ranges: Vec<(i32, i32)> = (0..3)
+                    .map(|i| {
+                        (
+                            (lower[i] - frac[i]).ceil() as i32,
+                            (upper[i] - frac[i]).floor() as i32,
+                        )
+                    })
+                    .collect();
+                for a in ranges[0].0..=ranges[0].1 {
+                    for b in ranges[1].0..=ranges[1].1 {
+                        for c in ranges[2].0..=ranges[2].1 {
+                            if [a, b, c] == [0, 0, 0] {
+                                continue;
+                            }
+                            let translation = lattice * Vector3::new(a as f64, b as f64, c as f64);
+                            images.push(AtomImage::new(atom_id, [a, b, c]));
+                            coords.push(home_coords[atom_id] + translation);
+                        }
+                    }
+                }
+            });
+        }
+        Self {
+            images,
+            coords,
+            lattice_matrix,
+            frac_matrix,
+            tolerance: Tolerance::default(),
+        }
+    }
+    /// The tolerance to keep the points on the cell boundary together in `wrap`.
+    pub fn with_tolerance(self, tolerance: Tolerance) -> Self {
+        Self { tolerance, ..self }
+    }
+
+    pub fn images(&self) -> &[AtomImage] {
+        &self.images
+    }
+
+    pub fn coords(&self) -> &[Point3<f64>] {
+        &self.coords
+    }
+
+    /// The kd-tree of the image coordinates, with the image id as value.
+    pub fn kdtree(&self) -> KdMap<Point3<f64>, usize> {
+        let items: Vec<(Point3<f64>, usize)> = self
+            .coords
+            .iter()
+            .enumerate()
+            .map(|(image_id, &coord)| (coord, image_id))
+            .collect();
+        KdMap::build_by_ordered_float(items)
+    }
+
+    pub fn is_periodic(&self) -> bool {
+        self.lattice_matrix.is_some()
+    }
+    /// Cartesian translation of the given lattice offset.
+    pub fn translation(&self, offset: [i32; 3]) -> Vector3<f64> {
+        match self.lattice_matrix {
+            Some(lattice) => {
+                lattice * Vector3::new(offset[0] as f64, offset[1] as f64, offset[2] as f64)
+            }
+            None => Vector3::zeros(),
+        }
+    }
+    /// Cartesian coordinate of an atom image.
+    pub fn image_coord(&self, image: &AtomImage) -> Point3<f64> {
+        self.coords[image.atom_id()] + self.translation(image.offset())
+    }
+    /// Wrap the point back into the cell.
+    /// Returns the wrapped point and the lattice translation that has been removed.
+    /// # Notes:
+    /// The points within the distance tolerance below the upper face of the cell are wrapped
+    /// to the lower face, so copies of a point lying on the cell boundary are not sent to
+    /// opposite sides by floating point noise.
+    pub fn wrap(&self, point: &Point3<f64>) -> (Point3<f64>, [i32; 3]) {
+        match (self.lattice_matrix, self.frac_matrix) {
+            (Some(lattice), Some(to_frac)) => {
+                let frac = to_frac * point;
+                let margins = frac_margins(&lattice, self.tolerance.distance());
+                let shift: [i32; 3] = [
+                    (frac.x + margins[0]).floor() as i32,
+                    (frac.y + margins[1]).floor() as i32,
+                    (frac.z + margins[2]).floor() as i32,
+                ];
+                (point - self.translation(shift), shift)
+            }
+            _ => (*point, [0, 0, 0]),
+        }
+    }
+}
+
+/// The cutoff distance expressed in fractional length along each axis,
+/// by the interplanar spacings of the cell.
+fn frac_margins(lattice: &Matrix3<f64>, cutoff: f64) -> [f64; 3] {
+    let a = lattice.column(0);
+    let b = lattice.column(1);
+    let c = lattice.column(2);
+    let volume = a.dot(&b.cross(&c)).abs();
+    [
+        cutoff * b.cross(&c).norm() / volume,
+        cutoff * c.cross(&a).norm() / volume,
+        cutoff * a.cross(&b).norm() / volume,
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use chemrust_core::data::LatticeVectors;
+    use nalgebra::{distance, Matrix3, Point3};
+
+    use super::{AtomImage, PeriodicImages};
+    use crate::analyzer::geometry::Tolerance;
+
+    #[test]
+    fn boundary_images() {
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal_element(5.0));
+        let coords = vec![Point3::new(0.2, 2.5, 2.5), Point3::new(4.8, 2.5, 2.5)];
+        let images = PeriodicImages::new(&coords, Some(&lattice), 1.0);
+        // Atom 1 seen across the `a` boundary is only 0.4 Å away from atom 0
+        let neighbour = AtomImage::new(1, [-1, 0, 0]);
+        assert!(images.images().contains(&neighbour));
+        assert!((distance(&coords[0], &images.image_coord(&neighbour)) - 0.4).abs() < 1e-6);
+        let (wrapped, shift) = images.wrap(&Point3::new(-0.1, 2.5, 7.5));
+        assert_eq!(shift, [-1, 0, 1]);
+        assert!(distance(&wrapped, &Point3::new(4.9, 2.5, 2.5)) < 1e-6);
+        // Within the distance tolerance below the upper face, the point goes to the lower face
+        let images = images.with_tolerance(Tolerance::default().with_distance(1e-3));
+        let (wrapped, shift) = images.wrap(&Point3::new(4.9995, 2.5, 2.5));
+        assert_eq!(shift, [1, 0, 0]);
+        assert!(wrapped.x.abs() < 1e-3);
+        let (_, shift) = images.wrap(&Point3::new(4.99, 2.5, 2.5));
+        assert_eq!(shift, [0, 0, 0]);
+    }
+}
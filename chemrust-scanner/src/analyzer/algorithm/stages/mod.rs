@@ -1,34 +1,44 @@
+use std::collections::HashMap;
+
 use chemrust_core::data::{Atom, BasicLatticeModel};
-use nalgebra::Point3;
+use nalgebra::{Point3, Unit, UnitVector3};
 
-use crate::analyzer::geometry::Sphere;
+use crate::analyzer::{
+    adsorbate::{AdsorptionSite, MolecularAdsorbate},
+    geometry::Sphere,
+};
 
-use super::{BondingCircle, BondingSphere, CoordinationPoint, Visualize};
+use super::{BondingArc, BondingCircle, BondingSphere, CoordinationPoint, Visualize};
 
 pub trait CheckStage {}
+
+/// A site to mount the adsorbate: the model name, the anchor position and the outward normal.
+pub type NamedSite = (String, Point3<f64>, UnitVector3<f64>);
 #[derive(Default)]
 pub struct Ready;
 pub struct SphereStage {
     spheres: Vec<Sphere>,
-    radius: f64,
+    max_radius: f64,
 }
 
 impl SphereStage {
-    pub fn new(coords: &[Point3<f64>], radius: f64) -> Self {
+    /// Build spheres at the coords, with the radius of each sphere given in `radii`.
+    pub fn new(coords: &[Point3<f64>], radii: &[f64]) -> Self {
         Self {
             spheres: coords
                 .iter()
-                .map(|&center| Sphere::new(center, radius))
+                .zip(radii.iter())
+                .map(|(&center, &radius)| Sphere::new(center, radius))
                 .collect(),
-            radius,
+            max_radius: radii.iter().cloned().fold(0.0, f64::max),
         }
     }
     pub fn get_sphere(&self, id: usize) -> Option<&Sphere> {
         self.spheres.get(id)
     }
-
-    pub fn radius(&self) -> f64 {
-        self.radius
+    /// The largest radius among the spheres, to bound the neighbour search.
+    pub fn max_radius(&self) -> f64 {
+        self.max_radius
     }
 }
 
@@ -60,6 +70,7 @@ impl CircleStage {
 pub struct PointStage {
     pub(crate) sphere_sites: Vec<BondingSphere>,
     pub(crate) circles: Vec<BondingCircle>,
+    pub(crate) arcs: Vec<BondingArc>,
     pub(crate) cut_points: Vec<CoordinationPoint>,
     pub(crate) multi_cn_points: Vec<CoordinationPoint>,
 }
@@ -68,12 +79,14 @@ impl PointStage {
     pub fn new(
         sphere_sites: Vec<BondingSphere>,
         circles: Vec<BondingCircle>,
+        arcs: Vec<BondingArc>,
         cut_points: Vec<CoordinationPoint>,
         multi_cn_points: Vec<CoordinationPoint>,
     ) -> Self {
         Self {
             sphere_sites,
             circles,
+            arcs,
             cut_points,
             multi_cn_points,
         }
@@ -87,6 +100,10 @@ impl PointStage {
         self.circles.as_ref()
     }
 
+    pub fn arcs(&self) -> &[BondingArc] {
+        self.arcs.as_ref()
+    }
+
     pub fn cut_points(&self) -> &[CoordinationPoint] {
         self.cut_points.as_ref()
     }
@@ -104,6 +121,7 @@ impl PointStage {
 pub struct FinalReport {
     pub(crate) sphere_sites: Vec<BondingSphere>,
     pub(crate) circles: Vec<BondingCircle>,
+    pub(crate) arcs: Vec<BondingArc>,
     pub(crate) cut_points: Vec<CoordinationPoint>,
     pub(crate) multi_cn_points: Vec<CoordinationPoint>,
 }
@@ -112,12 +130,14 @@ impl FinalReport {
     pub fn new(
         sphere_sites: Vec<BondingSphere>,
         circles: Vec<BondingCircle>,
+        arcs: Vec<BondingArc>,
         cut_points: Vec<CoordinationPoint>,
         multi_cn_points: Vec<CoordinationPoint>,
     ) -> Self {
         Self {
             sphere_sites,
             circles,
+            arcs,
             cut_points,
             multi_cn_points,
         }
@@ -131,6 +151,10 @@ impl FinalReport {
         self.circles.as_ref()
     }
 
+    pub fn arcs(&self) -> &[BondingArc] {
+        self.arcs.as_ref()
+    }
+
     pub fn cut_points(&self) -> &[CoordinationPoint] {
         self.cut_points.as_ref()
     }
@@ -139,6 +163,22 @@ impl FinalReport {
         self.multi_cn_points.as_ref()
     }
 
+    /// Keep the sites whose positions pass `keep`: the spheres at the mounting points, the arcs
+    /// at the representatives, and the points at their coordinates. A circle is kept only if
+    /// all of its points, sampled by about one degree, pass.
+    pub fn retain_sites<F>(&mut self, keep: F)
+    where
+        F: Fn(&Point3<f64>) -> bool,
+    {
+        self.sphere_sites
+            .retain(|sphere| keep(&sphere.mounting_point()));
+        self.circles
+            .retain(|bc| (0..360).all(|i| keep(&bc.circle().point_at((i as f64).to_radians()))));
+        self.arcs.retain(|arc| keep(&arc.representative()));
+        self.cut_points.retain(|cp| keep(&cp.coord()));
+        self.multi_cn_points.retain(|cp| keep(&cp.coord()));
+    }
+
     pub fn report_spheres(&self) -> String {
         let texts: Vec<String> = self
             .sphere_sites
@@ -155,130 +195,271 @@ impl FinalReport {
         texts.join("\n")
     }
 
-    pub fn generate_sphere_models(
-        &self,
+    /// Models with the adsorbate at each named site, one per clash-free orientation.
+    /// The names are suffixed by the rotation index when the rotations are enumerated.
+    fn adsorbate_models(
         lattice_model: &BasicLatticeModel,
-        new_element_symbol: &str,
+        adsorbate: &MolecularAdsorbate,
+        named_sites: Vec<NamedSite>,
     ) -> Option<Vec<(String, BasicLatticeModel)>> {
-        if !self.sphere_sites.is_empty() {
-            Some(
-                self.sphere_sites()
-                    .iter()
-                    .map(|sphere| {
-                        let location = sphere.locating_atom_id();
-                        let mut new_atoms = sphere.draw_with_element(new_element_symbol);
-                        let mut new_lattice = lattice_model.clone();
-                        let current_num = new_lattice.number_of_atoms();
-                        new_atoms
-                            .iter_mut()
-                            .for_each(|atom| atom.set_index(current_num + atom.index()));
-                        new_lattice.append_atom(&mut new_atoms);
-                        let new_name = format!("single_atom_{}", location);
-                        (new_name, new_lattice)
-                    })
-                    .collect(),
-            )
+        if named_sites.is_empty() {
+            return None;
+        }
+        Some(
+            named_sites
+                .into_iter()
+                .flat_map(|(name, position, normal)| {
+                    adsorbate
+                        .orientations_at(&position, &normal, lattice_model)
+                        .into_iter()
+                        .map(move |(k, mut new_atoms)| {
+                            let mut new_lattice = lattice_model.clone();
+                            new_lattice.append_atom(&mut new_atoms);
+                            (Self::model_name(&name, k, adsorbate), new_lattice)
+                        })
+                })
+                .collect(),
+        )
+    }
+
+    fn model_name(name: &str, k: usize, adsorbate: &MolecularAdsorbate) -> String {
+        if adsorbate.rotations() > 1 {
+            format!("{name}_rot_{k}")
         } else {
-            None
+            name.to_string()
+        }
+    }
+    /// Names of the models exported at each site by the `generate_*_models`, in the order of
+    /// `adsorption_sites`. A site has one model per clash-free orientation, or per sample of an arc
+    /// with `samples`, and none if the adsorbate clashes in all orientations.
+    pub fn exported_names(
+        &self,
+        lattice_model: &BasicLatticeModel,
+        adsorbate: &MolecularAdsorbate,
+        samples: Option<usize>,
+    ) -> Vec<Vec<String>> {
+        let single = |named_sites: Vec<NamedSite>| -> Vec<Vec<NamedSite>> {
+            named_sites.into_iter().map(|site| vec![site]).collect()
+        };
+        [
+            single(self.sphere_named_sites(lattice_model)),
+            single(self.circle_named_sites(lattice_model)),
+            self.arc_named_site_groups(samples),
+            single(Self::point_named_sites(self.cut_points(), lattice_model)),
+            single(Self::point_named_sites(
+                self.multi_cn_points(),
+                lattice_model,
+            )),
+        ]
+        .concat()
+        .into_iter()
+        .map(|named_sites| {
+            named_sites
+                .into_iter()
+                .flat_map(|(name, position, normal)| {
+                    adsorbate
+                        .orientations_at(&position, &normal, lattice_model)
+                        .into_iter()
+                        .map(move |(k, _)| Self::model_name(&name, k, adsorbate))
+                })
+                .collect()
+        })
+        .collect()
+    }
+
+    /// `{label}_atom_{ids}` for the labelled points, otherwise `cn_{n}_point_atom_{ids}`.
+    fn point_name(point: &CoordinationPoint) -> String {
+        let location = point
+            .connecting_atom_ids()
+            .iter()
+            .map(|i| format!("{i}"))
+            .collect::<Vec<String>>()
+            .join("_");
+        match point.label() {
+            Some(label) => format!("{label}_atom_{location}"),
+            None => format!(
+                "cn_{}_point_atom_{}",
+                point.connecting_atom_ids().len(),
+                location
+            ),
         }
     }
 
+    fn sphere_named_sites(&self, lattice_model: &BasicLatticeModel) -> Vec<NamedSite> {
+        with_unique_names(
+            self.sphere_sites()
+                .iter()
+                .map(|sphere| {
+                    (
+                        format!("single_atom_{}", sphere.locating_atom_id()),
+                        sphere.anchor_position(),
+                        sphere.outward_normal(lattice_model),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn circle_named_sites(&self, lattice_model: &BasicLatticeModel) -> Vec<NamedSite> {
+        with_unique_names(
+            self.circles()
+                .iter()
+                .map(|circle| {
+                    let location = circle.connecting_atoms();
+                    (
+                        format!(
+                            "double_atom_{}_{}",
+                            location[0].atom_id(),
+                            location[1].atom_id()
+                        ),
+                        circle.anchor_position(),
+                        circle.outward_normal(lattice_model),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    fn arc_named_sites(&self, samples: Option<usize>) -> Vec<NamedSite> {
+        self.arc_named_site_groups(samples).concat()
+    }
+    /// The named positions of each arc: the representative, or the samples.
+    fn arc_named_site_groups(&self, samples: Option<usize>) -> Vec<Vec<NamedSite>> {
+        let names = unique_names(
+            self.arcs()
+                .iter()
+                .map(|arc| {
+                    let location = arc.connecting_atoms();
+                    format!(
+                        "arc_atom_{}_{}",
+                        location[0].atom_id(),
+                        location[1].atom_id()
+                    )
+                })
+                .collect(),
+        );
+        self.arcs()
+            .iter()
+            .zip(names)
+            .map(|(arc, name)| {
+                let positions = match samples {
+                    Some(n) => arc
+                        .samples(n)
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, p)| (format!("{name}_sample_{i}"), p))
+                        .collect(),
+                    None => vec![(name, arc.representative())],
+                };
+                positions
+                    .into_iter()
+                    .map(|(new_name, position)| {
+                        let normal = Unit::try_new(position - arc.arc().center(), 1e-6)
+                            .unwrap_or(arc.arc().normal());
+                        (new_name, position, normal)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn point_named_sites(
+        points: &[CoordinationPoint],
+        lattice_model: &BasicLatticeModel,
+    ) -> Vec<NamedSite> {
+        with_unique_names(
+            points
+                .iter()
+                .map(|point| {
+                    (
+                        Self::point_name(point),
+                        point.anchor_position(),
+                        point.outward_normal(lattice_model),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    /// All the sites with their model names, anchor positions and outward normals,
+    /// in the order of spheres, circles, arcs (at the representatives), cut points and multi points.
+    pub fn adsorption_sites(&self, lattice_model: &BasicLatticeModel) -> Vec<NamedSite> {
+        [
+            self.sphere_named_sites(lattice_model),
+            self.circle_named_sites(lattice_model),
+            self.arc_named_sites(None),
+            Self::point_named_sites(self.cut_points(), lattice_model),
+            Self::point_named_sites(self.multi_cn_points(), lattice_model),
+        ]
+        .concat()
+    }
+
+    pub fn generate_sphere_models(
+        &self,
+        lattice_model: &BasicLatticeModel,
+        adsorbate: &MolecularAdsorbate,
+    ) -> Option<Vec<(String, BasicLatticeModel)>> {
+        Self::adsorbate_models(
+            lattice_model,
+            adsorbate,
+            self.sphere_named_sites(lattice_model),
+        )
+    }
+
     pub fn generate_circle_models(
         &self,
         lattice_model: &BasicLatticeModel,
-        new_element_symbol: &str,
+        adsorbate: &MolecularAdsorbate,
     ) -> Option<Vec<(String, BasicLatticeModel)>> {
-        if !self.circles.is_empty() {
-            Some(
-                self.circles()
-                    .iter()
-                    .map(|circle| {
-                        let location = circle.connecting_atoms();
-                        let mut new_atoms = circle.draw_with_element(new_element_symbol);
-                        let mut new_lattice = lattice_model.clone();
-                        new_lattice.append_atom(&mut new_atoms);
-                        let new_name = format!("double_atom_{}_{}", location[0], location[1]);
-                        (new_name, new_lattice)
-                    })
-                    .collect(),
-            )
-        } else {
-            None
-        }
+        Self::adsorbate_models(
+            lattice_model,
+            adsorbate,
+            self.circle_named_sites(lattice_model),
+        )
+    }
+
+    /// Models with the adsorbate at the representative position of each arc.
+    /// With `samples`, export `n` evenly spaced positions per arc instead.
+    pub fn generate_arc_models(
+        &self,
+        lattice_model: &BasicLatticeModel,
+        adsorbate: &MolecularAdsorbate,
+        samples: Option<usize>,
+    ) -> Option<Vec<(String, BasicLatticeModel)>> {
+        Self::adsorbate_models(lattice_model, adsorbate, self.arc_named_sites(samples))
     }
 
     pub fn generate_cut_point_models(
         &self,
         lattice_model: &BasicLatticeModel,
-        new_element_symbol: &str,
+        adsorbate: &MolecularAdsorbate,
     ) -> Option<Vec<(String, BasicLatticeModel)>> {
-        if !self.cut_points.is_empty() {
-            Some(
-                self.cut_points()
-                    .iter()
-                    .map(|cut_point| {
-                        let location = cut_point
-                            .connecting_atom_ids()
-                            .iter()
-                            .map(|i| format!("{i}"))
-                            .collect::<Vec<String>>()
-                            .join("_");
-                        let mut new_atoms = cut_point.draw_with_element(new_element_symbol);
-                        let mut new_lattice = lattice_model.clone();
-                        new_lattice.append_atom(&mut new_atoms);
-                        let new_name = format!(
-                            "cn_{}_point_atom_{}",
-                            cut_point.connecting_atom_ids().len(),
-                            location
-                        );
-                        (new_name, new_lattice)
-                    })
-                    .collect(),
-            )
-        } else {
-            None
-        }
+        Self::adsorbate_models(
+            lattice_model,
+            adsorbate,
+            Self::point_named_sites(self.cut_points(), lattice_model),
+        )
     }
 
     pub fn generate_multi_point_models(
         &self,
         lattice_model: &BasicLatticeModel,
-        new_element_symbol: &str,
+        adsorbate: &MolecularAdsorbate,
     ) -> Option<Vec<(String, BasicLatticeModel)>> {
-        if !self.multi_cn_points.is_empty() {
-            Some(
-                self.multi_cn_points()
-                    .iter()
-                    .map(|pt| {
-                        let location = pt
-                            .connecting_atom_ids()
-                            .iter()
-                            .map(|i| format!("{i}"))
-                            .collect::<Vec<String>>()
-                            .join("_");
-                        let mut new_atoms = pt.draw_with_element(new_element_symbol);
-                        let mut new_lattice = lattice_model.clone();
-                        new_lattice.append_atom(&mut new_atoms);
-                        let new_name = format!(
-                            "cn_{}_point_atom_{}",
-                            pt.connecting_atom_ids().len(),
-                            location
-                        );
-                        (new_name, new_lattice)
-                    })
-                    .collect(),
-            )
-        } else {
-            None
-        }
+        Self::adsorbate_models(
+            lattice_model,
+            adsorbate,
+            Self::point_named_sites(self.multi_cn_points(), lattice_model),
+        )
     }
 
     pub fn visualize_atoms(&self) -> Vec<Atom> {
         let spheres: Vec<Atom> = self.visualize_specific_sites(self.sphere_sites());
         let circles: Vec<Atom> = self.visualize_specific_sites(self.circles());
+        let arcs: Vec<Atom> = self.visualize_specific_sites(self.arcs());
         let points = [self.cut_points(), self.multi_cn_points()].concat();
         let all_points: Vec<Atom> = self.visualize_specific_sites(&points);
-        [spheres, circles, all_points].concat()
+        [spheres, circles, arcs, all_points].concat()
     }
 
     /// Export all possible sites of one kind at once.
@@ -295,6 +476,42 @@ impl FinalReport {
     }
 }
 
+/// Suffix the repeated names by `_site_{k}` in the order of appearance, e.g. for the points bonding
+/// to the same atoms through different periodic images, or on both sides of a layer.
+/// The exported seed folders are named after the sites, so the names must not collide.
+fn unique_names(names: Vec<String>) -> Vec<String> {
+    let mut totals: HashMap<String, usize> = HashMap::new();
+    names
+        .iter()
+        .for_each(|name| *totals.entry(name.clone()).or_default() += 1);
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    names
+        .into_iter()
+        .map(|name| {
+            if totals[&name] > 1 {
+                let k = seen.entry(name.clone()).or_default();
+                let unique_name = format!("{name}_site_{k}");
+                *k += 1;
+                unique_name
+            } else {
+                name
+            }
+        })
+        .collect()
+}
+
+fn with_unique_names(named_sites: Vec<NamedSite>) -> Vec<NamedSite> {
+    let (names, sites): (Vec<String>, Vec<_>) = named_sites
+        .into_iter()
+        .map(|(name, position, normal)| (name, (position, normal)))
+        .unzip();
+    unique_names(names)
+        .into_iter()
+        .zip(sites)
+        .map(|(name, (position, normal))| (name, position, normal))
+        .collect()
+}
+
 macro_rules! impl_check_stage {
     ($($x: ty),*) => {
         $(impl CheckStage for $x {})*
@@ -302,3 +519,71 @@ macro_rules! impl_check_stage {
 }
 
 impl_check_stage!(Ready, SphereStage, CircleStage, PointStage, FinalReport);
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use chemrust_core::data::{Atom, BasicLatticeModel, LatticeVectors};
+    use nalgebra::{Matrix3, Point3, Vector3};
+
+    use crate::analyzer::algorithm::{AtomImage, CoordinationPoint};
+
+    use super::FinalReport;
+
+    #[test]
+    fn unique_site_names() {
+        // Two atoms along `a` of a 3 Å cell, bridged inside the cell, across the cell boundary,
+        // and on both sides of the layer
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal(&Vector3::new(3.0, 3.0, 20.0)));
+        let atoms: Vec<Atom> = [0.0, 1.5]
+            .iter()
+            .enumerate()
+            .map(|(i, &x)| {
+                Atom::new_builder()
+                    .with_symbol("Cu")
+                    .with_atomic_number(29)
+                    .with_index(i)
+                    .with_coord(&Point3::new(x, 0.0, 5.0))
+                    .ready()
+                    .build()
+            })
+            .collect();
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        let bridge = |x: f64, z: f64, offset: [i32; 3]| {
+            CoordinationPoint::new(
+                Point3::new(x, 0.0, z),
+                vec![AtomImage::home(0), AtomImage::new(1, offset)],
+                2,
+            )
+        };
+        let top = CoordinationPoint::new(Point3::new(0.0, 0.0, 7.0), vec![AtomImage::home(0)], 1);
+        let report = FinalReport::new(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![
+                bridge(0.75, 6.0, [0, 0, 0]),
+                bridge(-0.75, 6.0, [-1, 0, 0]),
+                bridge(0.75, 4.0, [0, 0, 0]),
+                top,
+            ],
+            Vec::new(),
+        );
+        let names: Vec<String> = report
+            .adsorption_sites(&model)
+            .into_iter()
+            .map(|(name, _, _)| name)
+            .collect();
+        assert_eq!(names.iter().collect::<HashSet<_>>().len(), 4);
+        assert_eq!(
+            names,
+            vec![
+                "cn_2_point_atom_0_1_site_0",
+                "cn_2_point_atom_0_1_site_1",
+                "cn_2_point_atom_0_1_site_2",
+                "cn_1_point_atom_0",
+            ]
+        );
+    }
+}
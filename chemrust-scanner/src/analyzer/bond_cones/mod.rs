@@ -0,0 +1,164 @@
+//! Steric shielding of the existing host bonds.
+//! Every bond from the bonded neighbour list of the `LocalBondingEnv` gives a `Cone` from each end:
+//! the tip sits at the host atom, the axis points along the bond to the neighbour, and the cone
+//! reaches `reach` beyond the neighbour, with the given half-angle. The two cones of a bond cover
+//! the sites collinear with the bond, on top of it or behind either end.
+//! A candidate site inside any cone is discarded, with the cones of the periodic images considered.
+use chemrust_core::data::BasicLatticeModel;
+use kd_tree::KdMap;
+use nalgebra::{Point3, UnitVector3, Vector3};
+
+use super::{
+    algorithm::{AtomImage, FinalReport, LocalBondingEnvBuilder, PeriodicImages},
+    geometry::{Cone, Tolerance},
+};
+
+/// Discard the sites shielded by the existing host bonds.
+pub struct BondConeFilter {
+    /// The atoms and their periodic images within the longest cone
+    images: PeriodicImages,
+    /// Maps the coordinates of `images` to the image ids
+    coords_kdtree: KdMap<Point3<f64>, usize>,
+    /// The vectors to the bonded neighbours of each atom, by atom id
+    bond_vectors: Vec<Vec<Vector3<f64>>>,
+    /// Half of the apex angle of the cones, in radians
+    half_angle: f64,
+    /// How far the cones reach beyond the bonded neighbours, in angstrom
+    reach: f64,
+    /// Height of the longest cone
+    max_height: f64,
+    tolerance: Tolerance,
+}
+
+impl BondConeFilter {
+    /// Build the cones of all bonds in the model with the given half-angle, in radians.
+    /// The cones reach `reach` angstrom beyond the bonded neighbours, usually the target bondlength,
+    /// so the sites bonding to the neighbour from behind are covered.
+    pub fn new(lattice_model: &BasicLatticeModel, half_angle: f64, reach: f64) -> Self {
+        let bond_vectors: Vec<Vec<Vector3<f64>>> = LocalBondingEnvBuilder::new(lattice_model)
+            .build_local_bonding_envs()
+            .iter()
+            .map(|local_bonding_env| local_bonding_env.bond_vectors())
+            .collect();
+        let max_height = bond_vectors
+            .iter()
+            .flatten()
+            .map(|bond| bond.norm() + reach)
+            .fold(0.0, f64::max);
+        let coords: Vec<Point3<f64>> = lattice_model
+            .atoms()
+            .iter()
+            .map(|atom| atom.cartesian_coord())
+            .collect();
+        let images = PeriodicImages::new(&coords, lattice_model.lattice_vectors(), max_height);
+        let coords_kdtree = images.kdtree();
+        Self {
+            images,
+            coords_kdtree,
+            bond_vectors,
+            half_angle,
+            reach,
+            max_height,
+            tolerance: Tolerance::default(),
+        }
+    }
+    pub fn with_tolerance(self, tolerance: Tolerance) -> Self {
+        Self { tolerance, ..self }
+    }
+
+    pub fn half_angle(&self) -> f64 {
+        self.half_angle
+    }
+
+    pub fn reach(&self) -> f64 {
+        self.reach
+    }
+    /// The cones of the bonds from the given atom image.
+    pub fn cones_of(&self, image: &AtomImage) -> Vec<Cone> {
+        let tip = self.images.image_coord(image);
+        self.bond_vectors[image.atom_id()]
+            .iter()
+            .map(|bond| {
+                let h = bond.norm() + self.reach;
+                let r = h * self.half_angle.tan();
+                Cone::new(
+                    tip,
+                    UnitVector3::new_normalize(*bond),
+                    r,
+                    (h * h + r * r).sqrt(),
+                    h,
+                )
+            })
+            .collect()
+    }
+    /// Whether the point falls inside any cone of the bonds around.
+    pub fn is_shielded(&self, point: &Point3<f64>) -> bool {
+        self.coords_kdtree
+            .within_radius(point, self.max_height)
+            .into_iter()
+            .any(|(_, image_id)| {
+                let image = self.images.images()[*image_id];
+                self.cones_of(&image)
+                    .iter()
+                    .any(|cone| cone.point_in_cone(point, &self.tolerance))
+            })
+    }
+    /// Keep the sites out of the cones, see `FinalReport::retain_sites`.
+    pub fn filter(&self, report: &FinalReport) -> FinalReport {
+        let mut unshielded = report.clone();
+        unshielded.retain_sites(|point| !self.is_shielded(point));
+        unshielded
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chemrust_core::data::{Atom, BasicLatticeModel, LatticeVectors};
+    use nalgebra::{Matrix3, Point3, Vector3};
+
+    use crate::analyzer::algorithm::{AtomImage, CoordinationPoint, FinalReport};
+
+    use super::BondConeFilter;
+
+    #[test]
+    fn shielded_sites() {
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal(&Vector3::new(6.0, 10.0, 10.0)));
+        // The two carbons are 1.4 Å apart across the `a` boundary
+        let atoms: Vec<Atom> = [0.2, 4.8]
+            .iter()
+            .enumerate()
+            .map(|(i, &x)| {
+                Atom::new_builder()
+                    .with_symbol("C")
+                    .with_atomic_number(6)
+                    .with_index(i)
+                    .with_coord(&Point3::new(x, 5.0, 5.0))
+                    .ready()
+                    .build()
+            })
+            .collect();
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        let filter = BondConeFilter::new(&model, 20_f64.to_radians(), 1.5);
+        // Behind atom 1 along the bond from atom 0, collinear with the bond
+        let behind =
+            CoordinationPoint::new(Point3::new(3.5, 5.2, 5.0), vec![AtomImage::home(1)], 1);
+        // Above the middle of the bond
+        let bridge = CoordinationPoint::new(
+            Point3::new(5.5, 5.0, 6.2),
+            vec![AtomImage::new(0, [1, 0, 0]), AtomImage::home(1)],
+            2,
+        );
+        assert!(filter.is_shielded(&behind.coord()));
+        assert!(!filter.is_shielded(&bridge.coord()));
+        let report = FinalReport::new(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![behind, bridge],
+            Vec::new(),
+        );
+        let filtered = filter.filter(&report);
+        assert_eq!(filtered.cut_points().len(), 1);
+        assert_eq!(filtered.cut_points()[0].cn(), 2);
+    }
+}
@@ -0,0 +1,256 @@
+//! Enumerate the configurations of multiple adsorbates on the found sites, for coverage studies.
+//! 1. The sites are taken from `FinalReport::adsorption_sites`, and identified by the index in that order.
+//! 2. The combinations of 2 to `max_adsorbates` sites are searched depth-first in ascending site ids,
+//!    skipping the sites closer than `min_spacing` to a chosen site, by the minimum image under PBC.
+//! 3. A combination is dropped if a symmetry operation of the host maps it onto an accepted
+//!    combination of the same size.
+use chemrust_core::data::BasicLatticeModel;
+use nalgebra::{Matrix3, Matrix4, Point3};
+
+use super::{
+    adsorbate::MolecularAdsorbate,
+    algorithm::{FinalReport, NamedSite},
+    symmetry::{frac_distance, is_mapped_to, SymmetryFinder},
+};
+
+/// A combination of sites to mount the adsorbates at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverageConfiguration {
+    site_ids: Vec<usize>,
+}
+
+impl CoverageConfiguration {
+    /// Indices of the sites in `FinalReport::adsorption_sites`, in ascending order.
+    pub fn site_ids(&self) -> &[usize] {
+        self.site_ids.as_ref()
+    }
+    /// E.g., `coverage_3_site_0_4_9` for three adsorbates at the sites 0, 4 and 9.
+    pub fn name(&self) -> String {
+        let ids = self
+            .site_ids
+            .iter()
+            .map(|i| format!("{i}"))
+            .collect::<Vec<String>>()
+            .join("_");
+        format!("coverage_{}_site_{}", self.site_ids.len(), ids)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CoverageEnumerator<'a> {
+    lattice_model: &'a BasicLatticeModel,
+    max_adsorbates: usize,
+    /// Minimum distance between the anchors of two adsorbates, in angstrom.
+    min_spacing: f64,
+    /// Tolerance of the symmetry operations, in angstrom.
+    tolerance: f64,
+    /// Stop after this number of configurations.
+    max_configurations: Option<usize>,
+}
+
+impl<'a> CoverageEnumerator<'a> {
+    pub fn new(
+        lattice_model: &'a BasicLatticeModel,
+        max_adsorbates: usize,
+        min_spacing: f64,
+    ) -> Self {
+        Self {
+            lattice_model,
+            max_adsorbates,
+            min_spacing,
+            tolerance: 0.01,
+            max_configurations: None,
+        }
+    }
+    pub fn with_tolerance(self, tolerance: f64) -> Self {
+        Self { tolerance, ..self }
+    }
+    pub fn with_max_configurations(self, max_configurations: usize) -> Self {
+        Self {
+            max_configurations: Some(max_configurations),
+            ..self
+        }
+    }
+    /// The symmetry-distinct combinations of the sites, ordered by the number of adsorbates.
+    pub fn configurations(&self, final_report: &FinalReport) -> Vec<CoverageConfiguration> {
+        let positions: Vec<Point3<f64>> = final_report
+            .adsorption_sites(self.lattice_model)
+            .into_iter()
+            .map(|(_, position, _)| position)
+            .collect();
+        let operations = SymmetryFinder::new(self.lattice_model)
+            .with_tolerance(self.tolerance)
+            .find_operations();
+        let lattice = self.lattice_model.lattice_vectors();
+        let fracs: Vec<Point3<f64>> = match lattice {
+            Some(lattice) => positions
+                .iter()
+                .map(|p| lattice.mat_cart_to_frac() * p)
+                .collect(),
+            None => positions.clone(),
+        };
+        let search = CoverageSearch {
+            lattice_matrix: lattice.map(|lattice| *lattice.data()),
+            fracs,
+            operations,
+            min_spacing: self.min_spacing,
+            tolerance: self.tolerance,
+            max_configurations: self.max_configurations.unwrap_or(usize::MAX),
+        };
+        let mut accepted: Vec<Vec<usize>> = Vec::new();
+        (2..=self.max_adsorbates).for_each(|size| {
+            search.extend(&mut Vec::new(), 0, size, &mut accepted);
+        });
+        accepted
+            .into_iter()
+            .map(|site_ids| CoverageConfiguration { site_ids })
+            .collect()
+    }
+    /// Models with the adsorbates at each configuration.
+    /// Each adsorbate takes the first orientation free of clashes with the host and the placed adsorbates;
+    /// the configuration is skipped if there is none.
+    pub fn generate_models(
+        &self,
+        final_report: &FinalReport,
+        adsorbate: &MolecularAdsorbate,
+    ) -> Vec<(String, BasicLatticeModel)> {
+        let sites: Vec<NamedSite> = final_report.adsorption_sites(self.lattice_model);
+        self.configurations(final_report)
+            .iter()
+            .filter_map(|config| {
+                let mut new_lattice = self.lattice_model.clone();
+                config.site_ids().iter().try_for_each(|&i| {
+                    let (_, position, normal) = &sites[i];
+                    let (_, mut new_atoms) = adsorbate
+                        .orientations_at(position, normal, &new_lattice)
+                        .into_iter()
+                        .next()?;
+                    new_lattice.append_atom(&mut new_atoms);
+                    Some(())
+                })?;
+                Some((config.name(), new_lattice))
+            })
+            .collect()
+    }
+}
+
+/// The state shared by the depth-first search.
+struct CoverageSearch {
+    lattice_matrix: Option<Matrix3<f64>>,
+    /// Fractional coordinates of the sites, or cartesian without lattice vectors
+    fracs: Vec<Point3<f64>>,
+    operations: Vec<Matrix4<f64>>,
+    min_spacing: f64,
+    tolerance: f64,
+    max_configurations: usize,
+}
+
+impl CoverageSearch {
+    fn distance(&self, i: usize, j: usize) -> f64 {
+        let diff = self.fracs[j] - self.fracs[i];
+        match self.lattice_matrix {
+            Some(lattice_matrix) => frac_distance(&lattice_matrix, &diff),
+            None => diff.norm(),
+        }
+    }
+    /// Whether some operation maps every site of `lhs` onto a site of `rhs`.
+    fn is_equivalent(&self, lhs: &[usize], rhs: &[usize]) -> bool {
+        let lattice_matrix = match self.lattice_matrix {
+            Some(lattice_matrix) => lattice_matrix,
+            None => return lhs == rhs,
+        };
+        self.operations.iter().any(|op| {
+            lhs.iter().all(|&i| {
+                rhs.iter().any(|&j| {
+                    is_mapped_to(
+                        &lattice_matrix,
+                        op,
+                        &self.fracs[i],
+                        &self.fracs[j],
+                        self.tolerance,
+                    )
+                })
+            })
+        })
+    }
+    /// Add the sites from `start` to `chosen`, until `size` sites are chosen.
+    fn extend(
+        &self,
+        chosen: &mut Vec<usize>,
+        start: usize,
+        size: usize,
+        accepted: &mut Vec<Vec<usize>>,
+    ) {
+        if accepted.len() >= self.max_configurations {
+            return;
+        }
+        if chosen.len() == size {
+            let is_repeated = accepted
+                .iter()
+                .filter(|config| config.len() == size)
+                .any(|config| self.is_equivalent(chosen, config));
+            if !is_repeated {
+                accepted.push(chosen.clone());
+            }
+            return;
+        }
+        (start..self.fracs.len()).for_each(|next| {
+            let is_spaced = chosen
+                .iter()
+                .all(|&i| self.distance(i, next) >= self.min_spacing);
+            if is_spaced {
+                chosen.push(next);
+                self.extend(chosen, next + 1, size, accepted);
+                chosen.pop();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chemrust_core::data::{Atom, BasicLatticeModel, LatticeVectors};
+    use nalgebra::{Matrix3, Point3, Vector3};
+
+    use crate::analyzer::{
+        adsorbate::MolecularAdsorbate,
+        algorithm::{AtomImage, CoordinationPoint, FinalReport},
+    };
+
+    use super::CoverageEnumerator;
+
+    #[test]
+    fn square_coverage() {
+        // One atom in a 4 x 4 x 20 cell, with the top, two bridges and the hollow above it
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal(&Vector3::new(4.0, 4.0, 20.0)));
+        let atom = Atom::new_builder()
+            .with_symbol("Cu")
+            .with_index(0)
+            .with_coord(&Point3::new(0.0, 0.0, 5.0))
+            .ready()
+            .build();
+        let model = BasicLatticeModel::new(&Some(lattice), &[atom]);
+        let points: Vec<CoordinationPoint> = [(0.0, 0.0), (2.0, 0.0), (0.0, 2.0), (2.0, 2.0)]
+            .iter()
+            .map(|&(x, y)| {
+                CoordinationPoint::new(Point3::new(x, y, 7.0), vec![AtomImage::home(0)], 1)
+            })
+            .collect();
+        let report = FinalReport::new(Vec::new(), Vec::new(), Vec::new(), Vec::new(), points);
+        // Top + hollow, bridge + bridge; the neighbouring top and bridges are too close
+        let configs = CoverageEnumerator::new(&model, 3, 2.5).configurations(&report);
+        let ids: Vec<&[usize]> = configs.iter().map(|c| c.site_ids()).collect();
+        assert_eq!(ids, vec![&[0, 3][..], &[1, 2][..]]);
+        // The two bridges are equivalent by the 4-fold rotation
+        let configs = CoverageEnumerator::new(&model, 2, 1.5).configurations(&report);
+        let ids: Vec<&[usize]> = configs.iter().map(|c| c.site_ids()).collect();
+        assert_eq!(
+            ids,
+            vec![&[0, 1][..], &[0, 3][..], &[1, 2][..], &[1, 3][..]]
+        );
+        let models = CoverageEnumerator::new(&model, 2, 2.5)
+            .generate_models(&report, &MolecularAdsorbate::single_atom("O"));
+        assert_eq!(models[0].0, "coverage_2_site_0_3");
+        assert_eq!(models[0].1.number_of_atoms(), 3);
+    }
+}
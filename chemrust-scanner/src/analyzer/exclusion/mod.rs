@@ -0,0 +1,163 @@
+//! Exclude the sites around the existing adsorbates or defects.
+//! The masked atoms are given by their atom ids in the model, or selected by the regions.
+//! A site within `radius` of any masked atom, including the periodic images, is discarded.
+//! The masked atoms do not bond to the new atom, but still block the sites too close to them,
+//! like the other atoms excluded from bonding.
+use std::collections::HashSet;
+
+use chemrust_core::data::{custom_data_type::Region, BasicLatticeModel};
+use nalgebra::Point3;
+
+use super::algorithm::{FinalReport, PeriodicImages};
+
+/// The atoms whose neighbourhood is excluded from the sites.
+#[derive(Debug, Clone)]
+pub struct ExclusionMask {
+    /// Masked atoms by atom id in the model
+    atom_ids: Vec<usize>,
+    /// The atoms inside any of the regions are masked
+    regions: Vec<Region>,
+    /// Radius of the excluded neighbourhood, in angstrom
+    radius: f64,
+}
+
+impl ExclusionMask {
+    /// Exclude the sites within `radius` (in angstrom) of the masked atoms.
+    pub fn new(radius: f64) -> Self {
+        Self {
+            atom_ids: Vec::new(),
+            regions: Vec::new(),
+            radius,
+        }
+    }
+    /// Mask the atoms by their atom ids in the model.
+    pub fn with_atoms(self, atom_ids: &[usize]) -> Self {
+        let mut masked_ids = self.atom_ids;
+        masked_ids.extend_from_slice(atom_ids);
+        Self {
+            atom_ids: masked_ids,
+            ..self
+        }
+    }
+    /// Mask the atoms inside the region.
+    pub fn with_region(self, region: Region) -> Self {
+        let mut regions = self.regions;
+        regions.push(region);
+        Self { regions, ..self }
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+    /// The sorted ids of the masked atoms in the model.
+    pub fn masked_atoms(&self, lattice_model: &BasicLatticeModel) -> Vec<usize> {
+        let in_regions: HashSet<usize> = self
+            .regions
+            .iter()
+            .flat_map(|region| lattice_model.region_filter(region))
+            .map(|atom| atom.index())
+            .collect();
+        lattice_model
+            .atoms()
+            .iter()
+            .enumerate()
+            .filter(|(atom_id, atom)| {
+                self.atom_ids.contains(atom_id) || in_regions.contains(&atom.index())
+            })
+            .map(|(atom_id, _)| atom_id)
+            .collect()
+    }
+    /// Discard the sites around the masked atoms, see `FinalReport::retain_sites`.
+    pub fn filter(&self, report: &FinalReport, lattice_model: &BasicLatticeModel) -> FinalReport {
+        let masked_coords: Vec<Point3<f64>> = self
+            .masked_atoms(lattice_model)
+            .iter()
+            .map(|&atom_id| lattice_model.atoms()[atom_id].cartesian_coord())
+            .collect();
+        if masked_coords.is_empty() {
+            return report.clone();
+        }
+        let images =
+            PeriodicImages::new(&masked_coords, lattice_model.lattice_vectors(), self.radius);
+        let coords_kdtree = images.kdtree();
+        let mut kept = report.clone();
+        kept.retain_sites(|point| coords_kdtree.within_radius(point, self.radius).is_empty());
+        kept
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chemrust_core::data::{custom_data_type::Region, Atom, BasicLatticeModel, LatticeVectors};
+    use nalgebra::{distance, Matrix3, Point3, Vector3};
+
+    use crate::analyzer::{
+        adsorbate::AdsorptionSite, mounting_analyze::MountingChecker, site_stream::FoundSite,
+    };
+
+    use super::ExclusionMask;
+
+    #[test]
+    fn exclude_around_adatom() {
+        // Two far apart C2 dimers on a 20 Å cubic cell, with a Pt adatom above the first dimer
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal_element(20.0));
+        let atoms: Vec<Atom> = [
+            ("C", 6, [5.0, 5.0, 5.0]),
+            ("C", 6, [6.4, 5.0, 5.0]),
+            ("C", 6, [15.0, 15.0, 5.0]),
+            ("C", 6, [16.4, 15.0, 5.0]),
+            ("Pt", 78, [5.7, 5.0, 7.5]),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, &(symbol, atomic_number, xyz))| {
+            Atom::new_builder()
+                .with_symbol(symbol)
+                .with_atomic_number(atomic_number)
+                .with_index(i)
+                .with_coord(&Point3::from(xyz))
+                .ready()
+                .build()
+        })
+        .collect();
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        let carbons = &atoms[..4];
+        let plain = MountingChecker::new_builder()
+            .with_bondlength(1.5)
+            .with_forbidden_elements(&["Pt".to_string()])
+            .build();
+        let by_id = MountingChecker::new_builder()
+            .with_bondlength(1.5)
+            .with_exclusion_mask(ExclusionMask::new(4.0).with_atoms(&[4]))
+            .build();
+        let by_region = MountingChecker::new_builder()
+            .with_bondlength(1.5)
+            .with_exclusion_mask(
+                ExclusionMask::new(4.0)
+                    .with_region(Region::half_space(Point3::new(0.0, 0.0, 7.0), Vector3::z())),
+            )
+            .build();
+        assert_eq!(
+            by_id.exclusion_mask().unwrap().masked_atoms(&model),
+            vec![4]
+        );
+        assert_eq!(
+            by_region.exclusion_mask().unwrap().masked_atoms(&model),
+            vec![4]
+        );
+        let near_adatom = |site: &FoundSite| {
+            distance(&site.anchor_position(), &atoms[4].cartesian_coord()) <= 4.0
+        };
+        // The second dimer keeps its circle, the circle of the first dimer is cut by the adatom
+        let all_sites = plain.mount_search(&model, carbons);
+        assert_eq!(all_sites.circles().len(), 1);
+        assert_eq!(all_sites.arcs().len(), 1);
+        assert!(all_sites.into_sites().any(|site| near_adatom(&site)));
+        [by_id, by_region].iter().for_each(|mount_checker| {
+            let report = mount_checker.mount_search(&model, carbons);
+            assert_eq!(report.circles().len(), 1);
+            assert!(report.arcs().is_empty());
+            assert!(report.into_sites().all(|site| !near_adatom(&site)));
+        });
+    }
+}
@@ -0,0 +1,354 @@
+//! Group the chemically identical sites by the fingerprints of their local environments,
+//! for the distorted or defective hosts without the full space-group symmetry.
+//! The fingerprint of a site lists the host atoms and their periodic images within `shell` of the
+//! anchor position: the distances are grouped by the element and sorted. Two sites are near-identical
+//! when they see the same number of atoms of each element, and the sorted distances differ by no more
+//! than `tolerance`. The atoms within `tolerance` of the shell edge may be seen by only one of the
+//! two sites, so they are not required to have a counterpart.
+//!
+//! The sites of each kind are grouped separately, and each group is represented by its first site.
+use std::collections::{BTreeMap, BTreeSet};
+
+use chemrust_core::data::BasicLatticeModel;
+use kd_tree::KdMap;
+use nalgebra::{distance, Point3};
+
+use super::{
+    adsorbate::AdsorptionSite,
+    algorithm::{
+        BondingArc, BondingCircle, BondingSphere, CoordinationPoint, FinalReport, PeriodicImages,
+    },
+    symmetry::SiteClass,
+};
+
+/// The sorted distances to the neighbouring atoms of each element.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SiteFingerprint {
+    shells: BTreeMap<String, Vec<f64>>,
+    /// Radius of the local environment, in angstrom
+    shell: f64,
+}
+
+impl SiteFingerprint {
+    /// The sorted distances to the neighbouring atoms, by element symbol.
+    pub fn shells(&self) -> &BTreeMap<String, Vec<f64>> {
+        &self.shells
+    }
+    /// Whether the sorted distances of each element differ no more than `tolerance`.
+    /// The distances left over by the longer list must lie within `tolerance` of the shell edge.
+    pub fn matches(&self, other: &SiteFingerprint, tolerance: f64) -> bool {
+        let edge = self.shell.min(other.shell) - tolerance;
+        let symbols: BTreeSet<&String> = self.shells.keys().chain(other.shells.keys()).collect();
+        symbols.into_iter().all(|symbol| {
+            let distances_a = self.shells.get(symbol).map_or(&[][..], Vec::as_slice);
+            let distances_b = other.shells.get(symbol).map_or(&[][..], Vec::as_slice);
+            let shared = distances_a.len().min(distances_b.len());
+            distances_a
+                .iter()
+                .zip(distances_b.iter())
+                .all(|(a, b)| (a - b).abs() <= tolerance)
+                && distances_a[shared..]
+                    .iter()
+                    .chain(distances_b[shared..].iter())
+                    .all(|&d| d >= edge)
+        })
+    }
+}
+
+/// Compute the fingerprints of the positions in the model.
+pub struct FingerprintFinder<'a> {
+    lattice_model: &'a BasicLatticeModel,
+    /// Radius of the local environment, in angstrom
+    shell: f64,
+    /// Largest difference of the matching distances, in angstrom
+    tolerance: f64,
+    images: PeriodicImages,
+    coords_kdtree: KdMap<Point3<f64>, usize>,
+}
+
+impl<'a> FingerprintFinder<'a> {
+    /// The fingerprints out to 4 Å, matching within 0.05 Å by default.
+    pub fn new(lattice_model: &'a BasicLatticeModel) -> Self {
+        Self::build(lattice_model, 4.0, 0.05)
+    }
+    fn build(lattice_model: &'a BasicLatticeModel, shell: f64, tolerance: f64) -> Self {
+        let coords: Vec<Point3<f64>> = lattice_model
+            .atoms()
+            .iter()
+            .map(|atom| atom.cartesian_coord())
+            .collect();
+        let images = PeriodicImages::new(&coords, lattice_model.lattice_vectors(), shell);
+        let coords_kdtree = images.kdtree();
+        Self {
+            lattice_model,
+            shell,
+            tolerance,
+            images,
+            coords_kdtree,
+        }
+    }
+    pub fn with_shell(self, shell: f64) -> Self {
+        Self::build(self.lattice_model, shell, self.tolerance)
+    }
+    pub fn with_tolerance(self, tolerance: f64) -> Self {
+        Self { tolerance, ..self }
+    }
+
+    pub fn shell(&self) -> f64 {
+        self.shell
+    }
+
+    pub fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+    /// The fingerprint of the position, wrapped into the cell.
+    pub fn fingerprint(&self, position: &Point3<f64>) -> SiteFingerprint {
+        let (wrapped, _) = self.images.wrap(position);
+        let atoms = self.lattice_model.atoms();
+        let mut shells: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        self.coords_kdtree
+            .within_radius(&wrapped, self.shell)
+            .into_iter()
+            .for_each(|(coord, image_id)| {
+                let atom_id = self.images.images()[*image_id].atom_id();
+                shells
+                    .entry(atoms[atom_id].symbol().to_string())
+                    .or_default()
+                    .push(distance(&wrapped, coord));
+            });
+        shells
+            .values_mut()
+            .for_each(|distances| distances.sort_by(f64::total_cmp));
+        SiteFingerprint {
+            shells,
+            shell: self.shell,
+        }
+    }
+}
+
+/// The `FinalReport` with the sites grouped by their fingerprints.
+#[derive(Debug, Clone)]
+pub struct FingerprintReducedReport {
+    sphere_sites: Vec<SiteClass<BondingSphere>>,
+    circles: Vec<SiteClass<BondingCircle>>,
+    arcs: Vec<SiteClass<BondingArc>>,
+    cut_points: Vec<SiteClass<CoordinationPoint>>,
+    multi_cn_points: Vec<SiteClass<CoordinationPoint>>,
+}
+
+impl FingerprintReducedReport {
+    pub fn new(final_report: &FinalReport, fingerprint_finder: &FingerprintFinder) -> Self {
+        Self {
+            sphere_sites: group(final_report.sphere_sites(), fingerprint_finder),
+            circles: group(final_report.circles(), fingerprint_finder),
+            arcs: group(final_report.arcs(), fingerprint_finder),
+            cut_points: group(final_report.cut_points(), fingerprint_finder),
+            multi_cn_points: group(final_report.multi_cn_points(), fingerprint_finder),
+        }
+    }
+
+    pub fn sphere_sites(&self) -> &[SiteClass<BondingSphere>] {
+        &self.sphere_sites
+    }
+
+    pub fn circles(&self) -> &[SiteClass<BondingCircle>] {
+        &self.circles
+    }
+
+    pub fn arcs(&self) -> &[SiteClass<BondingArc>] {
+        &self.arcs
+    }
+
+    pub fn cut_points(&self) -> &[SiteClass<CoordinationPoint>] {
+        &self.cut_points
+    }
+
+    pub fn multi_cn_points(&self) -> &[SiteClass<CoordinationPoint>] {
+        &self.multi_cn_points
+    }
+    /// A `FinalReport` with one representative site per group, for the export.
+    pub fn representatives(&self) -> FinalReport {
+        FinalReport::new(
+            representatives_of(&self.sphere_sites),
+            representatives_of(&self.circles),
+            representatives_of(&self.arcs),
+            representatives_of(&self.cut_points),
+            representatives_of(&self.multi_cn_points),
+        )
+    }
+
+    pub fn report_groups(&self) -> String {
+        let summary = |name: &str, sizes: Vec<usize>| {
+            let total: usize = sizes.iter().sum();
+            format!(
+                "{name}: {total} sites in {} groups, group sizes: {:?}",
+                sizes.len(),
+                sizes
+            )
+        };
+        [
+            summary("Spheres", sizes_of(&self.sphere_sites)),
+            summary("Circles", sizes_of(&self.circles)),
+            summary("Arcs", sizes_of(&self.arcs)),
+            summary("Cut points", sizes_of(&self.cut_points)),
+            summary("Multi points", sizes_of(&self.multi_cn_points)),
+        ]
+        .join("\n")
+    }
+}
+
+impl FinalReport {
+    /// Group the chemically identical sites by the default `FingerprintFinder`.
+    pub fn reduce_by_fingerprint(
+        &self,
+        lattice_model: &BasicLatticeModel,
+    ) -> FingerprintReducedReport {
+        FingerprintReducedReport::new(self, &FingerprintFinder::new(lattice_model))
+    }
+}
+
+/// Each site joins the first group whose representative matches its fingerprint.
+fn group<T: Clone + AdsorptionSite>(
+    sites: &[T],
+    fingerprint_finder: &FingerprintFinder,
+) -> Vec<SiteClass<T>> {
+    let mut groups: Vec<(SiteFingerprint, T, usize)> = Vec::new();
+    sites.iter().for_each(|site| {
+        let fingerprint = fingerprint_finder.fingerprint(&site.anchor_position());
+        match groups.iter_mut().find(|(representative, _, _)| {
+            representative.matches(&fingerprint, fingerprint_finder.tolerance)
+        }) {
+            Some((_, _, size)) => *size += 1,
+            None => groups.push((fingerprint, site.clone(), 1)),
+        }
+    });
+    groups
+        .into_iter()
+        .map(|(_, representative, size)| SiteClass::new(representative, size))
+        .collect()
+}
+
+fn representatives_of<T: Clone>(groups: &[SiteClass<T>]) -> Vec<T> {
+    groups
+        .iter()
+        .map(|group| group.representative().clone())
+        .collect()
+}
+
+fn sizes_of<T>(groups: &[SiteClass<T>]) -> Vec<usize> {
+    groups.iter().map(|group| group.multiplicity()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use chemrust_core::data::{Atom, BasicLatticeModel, LatticeVectors};
+    use nalgebra::{Matrix3, Point3, Vector3};
+
+    use crate::analyzer::algorithm::{AtomImage, CoordinationPoint, FinalReport};
+
+    use super::{FingerprintFinder, FingerprintReducedReport};
+
+    #[test]
+    fn distorted_chain() {
+        // A chain of alternating C and O along `a`, with one C displaced by 0.01 Å:
+        // no space-group symmetry maps the tops of the two C, but their environments are near-identical.
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal(&Vector3::new(6.0, 10.0, 10.0)));
+        let atoms: Vec<Atom> = [("C", 6, 0.0), ("O", 8, 1.5), ("C", 6, 3.01), ("O", 8, 4.5)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(symbol, atomic_number, x))| {
+                Atom::new_builder()
+                    .with_symbol(symbol)
+                    .with_atomic_number(atomic_number)
+                    .with_index(i)
+                    .with_coord(&Point3::new(x, 5.0, 5.0))
+                    .ready()
+                    .build()
+            })
+            .collect();
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        let top_of = |atom_id: usize, x: f64| {
+            CoordinationPoint::new(Point3::new(x, 5.0, 7.0), vec![AtomImage::home(atom_id)], 1)
+        };
+        let report = FinalReport::new(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![
+                top_of(0, 0.0),
+                top_of(1, 1.5),
+                top_of(2, 3.01),
+                top_of(3, 4.5),
+            ],
+            Vec::new(),
+        );
+        let finder = FingerprintFinder::new(&model);
+        let reduced = report.reduce_by_fingerprint(&model);
+        // The tops of C and the tops of O
+        assert_eq!(reduced.cut_points().len(), 2);
+        assert!(reduced.cut_points().iter().all(|g| g.multiplicity() == 2));
+        assert_eq!(reduced.representatives().cut_points().len(), 2);
+        let strict = FingerprintReducedReport::new(&report, &finder.with_tolerance(1e-4));
+        assert_eq!(strict.cut_points().len(), 4);
+    }
+
+    #[test]
+    fn neighbour_at_shell_edge() {
+        // The tops of two C, each with an O just inside or just outside the 4 Å shell
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal_element(20.0));
+        let atoms: Vec<Atom> = [
+            ("C", 6, Point3::new(5.0, 10.0, 10.0)),
+            ("O", 8, Point3::new(5.0, 13.99, 12.0)),
+            ("C", 6, Point3::new(15.0, 10.0, 10.0)),
+            ("O", 8, Point3::new(15.0, 14.01, 12.0)),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, (symbol, atomic_number, coord))| {
+            Atom::new_builder()
+                .with_symbol(symbol)
+                .with_atomic_number(*atomic_number)
+                .with_index(i)
+                .with_coord(coord)
+                .ready()
+                .build()
+        })
+        .collect();
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        let top_of = |atom_id: usize, x: f64| {
+            CoordinationPoint::new(
+                Point3::new(x, 10.0, 12.0),
+                vec![AtomImage::home(atom_id)],
+                1,
+            )
+        };
+        let report = FinalReport::new(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![top_of(0, 5.0), top_of(2, 15.0)],
+            Vec::new(),
+        );
+        let finder = FingerprintFinder::new(&model);
+        assert_eq!(
+            finder
+                .fingerprint(&Point3::new(5.0, 10.0, 12.0))
+                .shells()
+                .len(),
+            2
+        );
+        assert_eq!(
+            finder
+                .fingerprint(&Point3::new(15.0, 10.0, 12.0))
+                .shells()
+                .len(),
+            1
+        );
+        let reduced = report.reduce_by_fingerprint(&model);
+        assert_eq!(reduced.cut_points().len(), 1);
+        assert_eq!(reduced.cut_points()[0].multiplicity(), 2);
+        // The O is no longer at the shell edge with a tighter tolerance
+        let strict = FingerprintReducedReport::new(&report, &finder.with_tolerance(1e-3));
+        assert_eq!(strict.cut_points().len(), 2);
+    }
+}
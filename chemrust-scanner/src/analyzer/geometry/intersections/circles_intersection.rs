@@ -1,8 +1,6 @@
-use std::cmp::Ordering::{Equal, Greater, Less};
-
 use nalgebra::{Point3, Unit};
 
-use crate::analyzer::geometry::{Circle, Line};
+use crate::analyzer::geometry::{Circle, Line, Tolerance};
 
 use super::Intersect;
 
@@ -32,90 +30,98 @@ pub enum CircleIntersectResult {
 pub struct CircleIntersectChecker {
     c1: Circle,
     c2: Circle,
-    state: CheckStage,
+    tolerance: Tolerance,
 }
 
 impl CircleIntersectChecker {
     pub fn new(c1: &Circle, c2: &Circle) -> Self {
-        let n3 = c1.normal.cross(&c2.normal);
-        // Ensure the 0.0 is proceeded and found correctly by using epsilon
-        if (n3.x - 0.0).abs() < 1e-6 && (n3.y - 0.0).abs() < 1e-6 && (n3.z - 0.0).abs() < 1e-6 {
-            CircleIntersectChecker {
-                c1: *c1,
-                c2: *c2,
-                state: CheckStage::Coplanar,
-            }
+        CircleIntersectChecker {
+            c1: *c1,
+            c2: *c2,
+            tolerance: Tolerance::default(),
+        }
+    }
+    pub fn with_tolerance(self, tolerance: Tolerance) -> Self {
+        Self { tolerance, ..self }
+    }
+    /// The circles with parallel normals, within the angle tolerance, are checked as a 2d problem.
+    fn stage(&self) -> CheckStage {
+        if self.tolerance.is_parallel(&self.c1.normal, &self.c2.normal) {
+            CheckStage::Coplanar
         } else {
-            CircleIntersectChecker {
-                c1: *c1,
-                c2: *c2,
-                state: CheckStage::Noncoplanar,
-            }
+            CheckStage::Noncoplanar
         }
     }
     pub fn check(&self) -> CircleIntersectResult {
-        match self.state {
+        match self.stage() {
             CheckStage::Coplanar => self.coplanar_check(),
             CheckStage::Noncoplanar => self.noncoplanar_check(),
         }
     }
-    /// Seems here have problems
+    /// # Notes:
+    /// With `d` the distance between the centers:
+    /// - Concentric: `Whole` if the radii are equal, otherwise `CoplanarZero`.
+    /// - `d > r1 + r2` or `d < |r1 - r2|`: apart, or one inside the other without touching, `CoplanarZero`.
+    /// - `d == r1 + r2`: externally tangent, `Single`.
+    /// - `d == |r1 - r2|`: internally tangent, `Single` on the line of centers, on the far side from the smaller circle's center
+    ///   as seen from the larger circle's center, whichever of `c1` and `c2` contains the other.
+    /// - Otherwise `Double`.
     fn coplanar_check(&self) -> CircleIntersectResult {
+        let tolerance = &self.tolerance;
         let d1d2 = self.c2.center - self.c1.center;
         // If the line between two circle is not orthogonal to the circle normal, not coplanar
         // !!!! The dot product must use absolute value to compare with the epsilon.
-        if d1d2.dot(&self.c1.normal).abs() > 1e-6 {
-            CircleIntersectResult::NonCoplanarZero
-        } else {
-            let radius_sum = self.c1.radius + self.c2.radius;
-            let radius_difference = self.c1.radius - self.c2.radius;
-            let d = d1d2.norm();
-            if d - radius_sum > 1e-6
-                || d < 1e-6 && radius_difference > 1e-6
-                || (d - radius_difference) < 1e-6
-            {
-                // Two circles out of distance or concentric or one circle is inside but not touching the edge
+        if d1d2.dot(&self.c1.normal).abs() > tolerance.distance() {
+            return CircleIntersectResult::NonCoplanarZero;
+        }
+        let radius_sum = self.c1.radius + self.c2.radius;
+        let radius_difference = (self.c1.radius - self.c2.radius).abs();
+        let d = d1d2.norm();
+        if tolerance.eq_length(d, 0.0) {
+            return if tolerance.eq_length(radius_difference, 0.0) {
+                // c1 overlaps with c2 with the same radius
+                CircleIntersectResult::Whole(self.c1)
+            } else {
                 CircleIntersectResult::CoplanarZero
-            } else if (d - radius_sum).abs() < 1e-6 {
-                let p = self.c1.center + d1d2.scale(self.c1.radius);
-                CircleIntersectResult::Single(p)
-            } else if (d - radius_difference.abs()) < 1e-6 {
-                if radius_difference.abs() < 1e-6 {
-                    // c1 overlaps with c2 with the same radius
-                    CircleIntersectResult::Whole(self.c1)
-                } else if radius_difference > 1e-6 {
-                    // c1 contains c2
-                    let n = Unit::new_normalize(d1d2);
-                    let distance = n.scale(self.c2.radius);
-                    let p = self.c2.center + distance;
-                    CircleIntersectResult::Single(p)
-                } else {
-                    // c2 contains c1
-                    let n = Unit::new_normalize(d1d2).scale(-1.0);
-                    let distance = n.scale(self.c1.radius);
-                    let p = self.c1.center + distance;
-                    CircleIntersectResult::Single(p)
-                }
+            };
+        }
+        let n = Unit::new_normalize(d1d2);
+        if tolerance.eq_length(d, radius_sum) {
+            CircleIntersectResult::Single(self.c1.center + n.scale(self.c1.radius))
+        } else if tolerance.eq_length(d, radius_difference) {
+            let p = if self.c1.radius > self.c2.radius {
+                // c1 contains c2, touching at the side of c2 away from c1's center
+                self.c2.center + n.scale(self.c2.radius)
             } else {
-                // c1 intersects with c2 when radius_difference d < radius_sum
-                CircleIntersectResult::Double(two_circles_between(&self.c1, &self.c2))
-            }
+                // c2 contains c1, touching at the side of c1 away from c2's center
+                self.c1.center - n.scale(self.c1.radius)
+            };
+            CircleIntersectResult::Single(p)
+        } else if d > radius_sum || d < radius_difference {
+            // Two circles out of distance or one circle is inside but not touching the edge
+            CircleIntersectResult::CoplanarZero
+        } else {
+            // c1 intersects with c2 when radius_difference < d < radius_sum
+            CircleIntersectResult::Double(two_circles_between(&self.c1, &self.c2))
         }
     }
     fn noncoplanar_check(&self) -> CircleIntersectResult {
         let plane_1 = self.c1.circle_plane();
         let plane_2 = self.c2.circle_plane();
         // Because the two planes have been guaranteed by checking dot product beforehand
-        let plane_intersection_line = plane_1.intersects(&plane_2).unwrap();
+        let plane_intersection_line = match plane_1.intersects_within(&plane_2, &self.tolerance) {
+            Some(line) => line,
+            None => return CircleIntersectResult::NonCoplanarZero,
+        };
         let c1_line_intersection =
-            circle_intersection_line_intersect(&self.c1, &plane_intersection_line);
+            circle_intersection_line_intersect(&self.c1, &plane_intersection_line, &self.tolerance);
         let c2_line_intersection =
-            circle_intersection_line_intersect(&self.c2, &plane_intersection_line);
+            circle_intersection_line_intersect(&self.c2, &plane_intersection_line, &self.tolerance);
         // Either circle does not touch the intersection line
         match (c1_line_intersection, c2_line_intersection) {
             (CircleIntersectResult::Single(p1), CircleIntersectResult::Single(p2)) => {
-                if let Some(p) = check_identity_points(&p1, &p2) {
-                    CircleIntersectResult::Single(p)
+                if self.tolerance.same_point(&p1, &p2) {
+                    CircleIntersectResult::Single(p1)
                 } else {
                     CircleIntersectResult::NonCoplanarZero
                 }
@@ -123,9 +129,7 @@ impl CircleIntersectChecker {
             (CircleIntersectResult::Double(points_1), CircleIntersectResult::Double(points_2)) => {
                 let (p11, p12) = points_1;
                 let (p21, p22) = points_2;
-                if check_identity_points(&p11, &p21).is_some()
-                    && check_identity_points(&p12, &p22).is_some()
-                {
+                if self.tolerance.same_point(&p11, &p21) && self.tolerance.same_point(&p12, &p22) {
                     CircleIntersectResult::Double(points_1)
                 } else {
                     CircleIntersectResult::NonCoplanarZero
@@ -156,42 +160,27 @@ fn circle_center_to_intersection_line_distance(c: &Circle, line: &Line) -> f64 {
     let co_line_angle = origin_to_center.angle(&line.d);
     origin_to_center.norm() * co_line_angle.sin()
 }
-fn circle_intersection_line_intersect(c: &Circle, line: &Line) -> CircleIntersectResult {
+/// The line is tangent to the circle when the distance to the center is within the tolerance of the radius.
+fn circle_intersection_line_intersect(
+    c: &Circle,
+    line: &Line,
+    tolerance: &Tolerance,
+) -> CircleIntersectResult {
     let distance_to_line = circle_center_to_intersection_line_distance(c, line);
-    match distance_to_line.partial_cmp(&c.radius) {
-        None => CircleIntersectResult::NonCoplanarZero,
-        Some(ord) => match ord {
-            Greater => CircleIntersectResult::NonCoplanarZero,
-            Equal => {
-                let origin_to_center = c.center - line.origin;
-                let co_line_angle = origin_to_center.angle(&line.d);
-                let od_distance = origin_to_center.norm() * co_line_angle.cos();
-                let point = line.get_point_at_line(od_distance);
-                CircleIntersectResult::Single(point)
-            }
-            Less => {
-                let d = distance_to_line;
-                let delta = (c.radius.powi(2) - d.powi(2)).sqrt();
-                let origin_to_center = c.center - line.origin;
-                let co_line_angle = origin_to_center.angle(&line.d);
-                let od_distance = origin_to_center.norm() * co_line_angle.cos();
-                let op1_distance = od_distance + delta;
-                let op2_distance = od_distance - delta;
-                let points = (
-                    line.get_point_at_line(op1_distance),
-                    line.get_point_at_line(op2_distance),
-                );
-                CircleIntersectResult::Double(points)
-            }
-        },
-    }
-}
-fn check_identity_points(p1: &Point3<f64>, p2: &Point3<f64>) -> Option<Point3<f64>> {
-    // Ensure floating point cmp
-    if (p1.x - p2.x).abs() < 1e-6 && (p1.y - p2.y).abs() < 1e-6 && (p1.z - p2.z).abs() < 1e-6 {
-        Some(*p1)
+    let origin_to_center = c.center - line.origin;
+    let co_line_angle = origin_to_center.angle(&line.d);
+    let od_distance = origin_to_center.norm() * co_line_angle.cos();
+    if tolerance.eq_length(distance_to_line, c.radius) {
+        CircleIntersectResult::Single(line.get_point_at_line(od_distance))
+    } else if distance_to_line < c.radius {
+        let delta = (c.radius.powi(2) - distance_to_line.powi(2)).sqrt();
+        let points = (
+            line.get_point_at_line(od_distance + delta),
+            line.get_point_at_line(od_distance - delta),
+        );
+        CircleIntersectResult::Double(points)
     } else {
-        None
+        CircleIntersectResult::NonCoplanarZero
     }
 }
 
@@ -199,9 +188,9 @@ fn check_identity_points(p1: &Point3<f64>, p2: &Point3<f64>) -> Option<Point3<f6
 mod test {
     use nalgebra::{Point3, Vector3};
 
-    use crate::analyzer::geometry::Circle;
+    use crate::analyzer::geometry::{Circle, Tolerance};
 
-    use super::two_circles_between;
+    use super::{two_circles_between, CircleIntersectChecker, CircleIntersectResult};
 
     #[test]
     fn two_circles() {
@@ -210,4 +199,51 @@ mod test {
         let points = two_circles_between(&c1, &c2);
         dbg!(points);
     }
+    #[test]
+    fn coplanar_containing() {
+        let check = |c1: &Circle, c2: &Circle| CircleIntersectChecker::new(c1, c2).check();
+        let big = Circle::new(Point3::origin(), 3.0, Vector3::z_axis());
+        // Internally tangent at (3, 0, 0), whichever contains the other
+        let small = Circle::new(Point3::new(2.0, 0.0, 0.0), 1.0, Vector3::z_axis());
+        for res in [check(&big, &small), check(&small, &big)] {
+            match res {
+                CircleIntersectResult::Single(p) => {
+                    assert!((p - Point3::new(3.0, 0.0, 0.0)).norm() < 1e-6)
+                }
+                res => panic!("Expected a single point, got {:?}", res),
+            }
+        }
+        // Strictly inside, not touching
+        let inner = Circle::new(Point3::new(0.5, 0.0, 0.0), 1.0, Vector3::z_axis());
+        assert!(matches!(
+            check(&big, &inner),
+            CircleIntersectResult::CoplanarZero
+        ));
+        assert!(matches!(
+            check(&inner, &big),
+            CircleIntersectResult::CoplanarZero
+        ));
+        // Externally tangent at (3, 0, 0)
+        let outer = Circle::new(Point3::new(4.0, 0.0, 0.0), 1.0, -Vector3::z_axis());
+        match check(&big, &outer) {
+            CircleIntersectResult::Single(p) => {
+                assert!((p - Point3::new(3.0, 0.0, 0.0)).norm() < 1e-6)
+            }
+            res => panic!("Expected a single point, got {:?}", res),
+        }
+        assert!(matches!(check(&big, &big), CircleIntersectResult::Whole(_)));
+        // A gap of 1e-4 is tangent with a looser tolerance
+        let near = Circle::new(Point3::new(4.0001, 0.0, 0.0), 1.0, Vector3::z_axis());
+        assert!(matches!(
+            check(&big, &near),
+            CircleIntersectResult::CoplanarZero
+        ));
+        let loose = Tolerance::default().with_distance(1e-3);
+        assert!(matches!(
+            CircleIntersectChecker::new(&big, &near)
+                .with_tolerance(loose)
+                .check(),
+            CircleIntersectResult::Single(_)
+        ));
+    }
 }
@@ -5,11 +5,15 @@ mod spheres_intersection;
 pub use circles_intersection::{CircleIntersectChecker, CircleIntersectResult};
 pub use spheres_intersection::SphereIntersectResult;
 
-use super::GeometryObject;
+use super::{GeometryObject, Tolerance};
 
 pub trait Intersect<T: GeometryObject> {
     type Result;
-    fn intersects(&self, rhs: &T) -> Self::Result;
+    /// Intersect with the default [`Tolerance`].
+    fn intersects(&self, rhs: &T) -> Self::Result {
+        self.intersects_within(rhs, &Tolerance::default())
+    }
+    fn intersects_within(&self, rhs: &T, tolerance: &Tolerance) -> Self::Result;
 }
 
 #[cfg(test)]
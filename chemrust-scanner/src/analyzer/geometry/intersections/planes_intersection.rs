@@ -1,13 +1,17 @@
 use nalgebra::{Matrix2, Point3, Unit, Vector2};
 
-use crate::analyzer::geometry::{Line, Plane};
+use crate::analyzer::geometry::{Line, Plane, Tolerance};
 
 use super::Intersect;
 
 impl Intersect<Plane> for Plane {
     type Result = Option<Line>;
 
-    fn intersects(&self, rhs: &Plane) -> Self::Result {
+    fn intersects_within(&self, rhs: &Plane, tolerance: &Tolerance) -> Self::Result {
+        // Parallel planes do not intersect at a line
+        if tolerance.is_parallel(&self.n, &rhs.n) {
+            return None;
+        }
         let n3 = self.n.cross(&rhs.n);
         // Perform the reduction methods
         // let z = 0, we have:
@@ -1,6 +1,6 @@
 use nalgebra::{Point3, Unit};
 
-use crate::analyzer::geometry::{Circle, Sphere};
+use crate::analyzer::geometry::{Circle, Sphere, Tolerance};
 
 use super::Intersect;
 
@@ -15,28 +15,32 @@ pub enum SphereIntersectResult {
 impl Intersect<Sphere> for Sphere {
     type Result = SphereIntersectResult;
 
-    fn intersects(&self, rhs: &Sphere) -> Self::Result {
+    fn intersects_within(&self, rhs: &Sphere, tolerance: &Tolerance) -> Self::Result {
         let vector_rhs_to_self = self.center - rhs.center; // Vector from rhs to self
         let d = vector_rhs_to_self.norm();
         let radius_sum = self.radius + rhs.radius;
         let radius_diff = self.radius - rhs.radius;
+        // The tangent cases are checked first, so a distance within the tolerance of touching
+        // does not produce a vanishing circle.
         match d {
-            x if radius_diff < x && x < radius_sum => {
-                SphereIntersectResult::Circle(two_spheres_between(self, rhs))
+            x if tolerance.eq_length(x, 0.0) && tolerance.eq_length(radius_diff, 0.0) => {
+                SphereIntersectResult::Whole(*self)
             }
-            x if (x - radius_sum).abs() < 1e-6 => {
+            x if tolerance.eq_length(x, radius_sum) => {
                 let n = Unit::new_normalize(vector_rhs_to_self);
                 let p = rhs.point_at_surface(&n);
                 SphereIntersectResult::SinglePoint(p)
             }
-            x if x < 1e-6 && radius_diff < 1e-6 => SphereIntersectResult::Whole(*self),
-            x if (x - radius_diff).abs() < 1e-6 => {
+            x if !tolerance.eq_length(x, 0.0) && tolerance.eq_length(x, radius_diff.abs()) => {
                 // Self radius larger than rhs
                 let direction = if radius_diff > 0.0 { -1.0 } else { 1.0 };
                 let n = Unit::new_normalize(vector_rhs_to_self.scale(direction));
                 let p = rhs.point_at_surface(&n);
                 SphereIntersectResult::SinglePoint(p)
             }
+            x if radius_diff.abs() < x && x < radius_sum => {
+                SphereIntersectResult::Circle(two_spheres_between(self, rhs))
+            }
             _ => SphereIntersectResult::Zero,
         }
     }
@@ -60,7 +64,7 @@ fn two_spheres_between(s1: &Sphere, s2: &Sphere) -> Circle {
 mod test {
     use nalgebra::Point3;
 
-    use crate::analyzer::geometry::Sphere;
+    use crate::analyzer::geometry::{Intersect, Sphere, SphereIntersectResult};
 
     use super::two_spheres_between;
 
@@ -71,4 +75,26 @@ mod test {
         let c = two_spheres_between(&s1, &s2);
         dbg!(c);
     }
+    #[test]
+    fn spheres_of_different_radii() {
+        let small = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0);
+        let large = Sphere::new(Point3::new(0.0, 0.0, 1.0), 2.0);
+        // Internally tangent at (0, 0, -1), regardless of the order
+        for (s1, s2) in [(small, large), (large, small)] {
+            match s1.intersects(&s2) {
+                SphereIntersectResult::SinglePoint(p) => {
+                    assert!((p - Point3::new(0.0, 0.0, -1.0)).norm() < 1e-6)
+                }
+                res => panic!("Expected a single point, got {:?}", res),
+            }
+        }
+        let shifted = Sphere::new(Point3::new(0.0, 0.0, 2.0), 2.0);
+        match small.intersects(&shifted) {
+            SphereIntersectResult::Circle(c) => {
+                assert!((c.center.z - 0.25).abs() < 1e-6);
+                assert!((c.radius - (1.0_f64 - 0.0625).sqrt()).abs() < 1e-6);
+            }
+            res => panic!("Expected a circle, got {:?}", res),
+        }
+    }
 }
@@ -1,4 +1,10 @@
 mod intersections;
 mod primitives;
+mod tetrahedralization;
+mod tolerance;
+mod triangulation;
 pub use intersections::*;
 pub use primitives::*;
+pub use tetrahedralization::DelaunayTetrahedralization;
+pub use tolerance::Tolerance;
+pub use triangulation::DelaunayTriangulation;
@@ -1,4 +1,10 @@
-use nalgebra::{distance_squared, Point3, UnitVector3};
+use std::f64::consts::PI;
+
+use nalgebra::{Point3, UnitVector3};
+
+use crate::analyzer::geometry::Tolerance;
+
+use super::Circle;
 
 /// Section from a circle
 /// The arc sweeps from the starting position (indicated by the `radius_starting_dir`,
@@ -32,10 +38,11 @@ impl Arc {
         center: &Point3<f64>,
         start_point: &Point3<f64>,
         end_point: &Point3<f64>,
+        tolerance: &Tolerance,
     ) -> Option<Self> {
         let sc = start_point - center;
         let ec = end_point - center;
-        if (sc.norm() - ec.norm()).abs() > 1e-6 {
+        if !tolerance.eq_length(sc.norm(), ec.norm()) {
             return None;
         }
         let radius = sc.norm();
@@ -50,16 +57,105 @@ impl Arc {
             radius_starting_dir,
         })
     }
-    pub fn is_on_arc(&self, point: &Point3<f64>) -> bool {
-        let distance = distance_squared(&self.center, point);
-        let same_distance = (distance - self.radius).abs() < 1e-6;
+    /// Section of the circle from `start_angle` sweeping `theta`, with the angles
+    /// measured from the `u` direction of `Circle::basis()`.
+    pub fn from_circle(circle: &Circle, start_angle: f64, theta: f64) -> Self {
+        let start_point = circle.point_at(start_angle);
+        Self {
+            center: circle.center,
+            radius: circle.radius,
+            theta: theta.clamp(0.0, 2.0 * PI),
+            normal: circle.normal,
+            radius_starting_dir: UnitVector3::new_normalize(start_point - circle.center),
+        }
+    }
+
+    pub fn center(&self) -> Point3<f64> {
+        self.center
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn theta(&self) -> f64 {
+        self.theta
+    }
+
+    pub fn normal(&self) -> UnitVector3<f64> {
+        self.normal
+    }
+    /// The point on the arc at `angle` swept from the starting position.
+    pub fn point_at(&self, angle: f64) -> Point3<f64> {
+        let ending_dir = self.normal.cross(&self.radius_starting_dir);
+        self.center
+            + (self.radius_starting_dir.scale(angle.cos()) + ending_dir.scale(angle.sin()))
+                .scale(self.radius)
+    }
+    /// `n` evenly spaced points on the arc, each at the middle of the `n` equal sections.
+    pub fn sample_points(&self, n: usize) -> Vec<Point3<f64>> {
+        (0..n)
+            .map(|i| self.point_at(self.theta * (i as f64 + 0.5) / n as f64))
+            .collect()
+    }
+    /// The point with the largest `z` on the arc.
+    pub fn topmost_point(&self) -> Point3<f64> {
+        let ending_dir = self.normal.cross(&self.radius_starting_dir);
+        // z(t) = r * (cos(t) * s_z + sin(t) * e_z) reaches the maximum at t = atan2(e_z, s_z)
+        let stationary = ending_dir
+            .z
+            .atan2(self.radius_starting_dir.z)
+            .rem_euclid(2.0 * PI);
+        let mut candidates = vec![self.point_at(0.0), self.point_at(self.theta)];
+        if stationary <= self.theta {
+            candidates.push(self.point_at(stationary));
+        }
+        candidates
+            .into_iter()
+            .max_by(|a, b| a.z.total_cmp(&b.z))
+            .unwrap()
+    }
+    /// The point is on the circle of the arc, and within the swept angle from the starting position.
+    pub fn is_on_arc(&self, point: &Point3<f64>, tolerance: &Tolerance) -> bool {
+        let circle = Circle::new(self.center, self.radius, self.normal);
+        if !circle.is_on_circle(point, tolerance) {
+            return false;
+        }
         let c_to_p = UnitVector3::new_normalize(point - self.center);
-        let angle = self.radius_starting_dir.angle(&c_to_p);
-        // If cross product vector dot normal > 0.0, same direction, within the arc,
-        // else opposite direction, out of the arc
-        let cross_product = self.radius_starting_dir.cross(&c_to_p).dot(&self.normal);
-        cross_product > 0.0
-            && (angle < self.theta || (angle - self.theta).abs() < 1e-6)
-            && same_distance
+        let ending_dir = self.normal.cross(&self.radius_starting_dir);
+        // Swept angle in `[0, 2PI)` from the starting position, counter-clockwise about the normal
+        let angle = c_to_p
+            .dot(&ending_dir)
+            .atan2(c_to_p.dot(&self.radius_starting_dir))
+            .rem_euclid(2.0 * PI);
+        angle <= self.theta + tolerance.angle() || 2.0 * PI - angle <= tolerance.angle()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::PI;
+
+    use nalgebra::{Point3, Vector3};
+
+    use crate::analyzer::geometry::{Circle, Tolerance};
+
+    use super::Arc;
+
+    #[test]
+    fn points_on_arc() {
+        let tolerance = Tolerance::default();
+        let circle = Circle::new(Point3::new(0.0, 0.0, 1.0), 2.0, Vector3::z_axis());
+        assert!(circle.is_on_circle(&Point3::new(0.0, 2.0, 1.0), &tolerance));
+        // The squared distance equals to the radius, but the point is not on the circle
+        assert!(!circle.is_on_circle(&Point3::new(0.0, 2.0_f64.sqrt(), 1.0), &tolerance));
+        assert!(!circle.is_on_circle(&Point3::new(0.0, 2.0, 1.1), &tolerance));
+        // The first quarter from +x
+        let arc = Arc::from_circle(&circle, 0.0, PI / 2.0);
+        assert!(arc.is_on_arc(&arc.point_at(0.0), &tolerance));
+        assert!(arc.is_on_arc(&arc.point_at(PI / 4.0), &tolerance));
+        assert!(arc.is_on_arc(&arc.point_at(PI / 2.0), &tolerance));
+        assert!(!arc.is_on_arc(&arc.point_at(PI), &tolerance));
+        assert!(!arc.is_on_arc(&arc.point_at(-PI / 4.0), &tolerance));
     }
 }
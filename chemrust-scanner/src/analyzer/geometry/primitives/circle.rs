@@ -1,4 +1,6 @@
-use nalgebra::{distance_squared, Point3, UnitVector3, Vector3};
+use nalgebra::{distance, Point3, UnitVector3, Vector3};
+
+use crate::analyzer::geometry::Tolerance;
 
 use super::{GeometryObject, Plane};
 
@@ -19,40 +21,51 @@ impl Circle {
             normal,
         }
     }
-    pub fn is_on_circle(&self, point: &Point3<f64>) -> bool {
-        let distance = distance_squared(&self.center, point);
-        // use `matches!` macro
-        matches!(
-            distance.partial_cmp(&self.radius),
-            Some(std::cmp::Ordering::Equal)
-        )
+    /// The point is on the plane of the circle, and at the radius from the center, within the distance tolerance.
+    pub fn is_on_circle(&self, point: &Point3<f64>, tolerance: &Tolerance) -> bool {
+        let height = (point - self.center).dot(&self.normal);
+        tolerance.eq_length(height, 0.0)
+            && tolerance.eq_length(distance(&self.center, point), self.radius)
+    }
+    /// Orthonormal basis `(u, v)` on the plane of the circle, with `u x v = normal`.
+    /// `u` points to the topmost point of the circle along `+z`. When the circle lies
+    /// in the xy-plane, i.e. the normal is parallel to `z` within the default angle tolerance,
+    /// `u` is taken from the projection of `+x`. The default is used so every caller gets the
+    /// same basis of a circle.
+    pub fn basis(&self) -> (UnitVector3<f64>, UnitVector3<f64>) {
+        let project = |axis: Vector3<f64>| axis - self.normal.scale(axis.dot(&self.normal));
+        let u = if Tolerance::default().is_parallel(&self.normal, &Vector3::z_axis()) {
+            UnitVector3::new_normalize(project(Vector3::x()))
+        } else {
+            UnitVector3::new_normalize(project(Vector3::z()))
+        };
+        let v = UnitVector3::new_normalize(self.normal.cross(&u));
+        (u, v)
+    }
+    /// The point on the circle at `angle` (in radians) from the `u` direction of `basis()`.
+    pub fn point_at(&self, angle: f64) -> Point3<f64> {
+        let (u, v) = self.basis();
+        self.center + (u.scale(angle.cos()) + v.scale(angle.sin())).scale(self.radius)
+    }
+    /// The point with the largest `z` on the circle.
+    pub fn topmost_point(&self) -> Point3<f64> {
+        self.point_at(0.0)
     }
     pub fn circle_plane(&self) -> Plane {
         Plane::from_point_normal(self.center, self.normal)
     }
+    /// The shortest and the longest distances from the point to the circle.
     pub fn point_to_circle_distances(&self, point: &Point3<f64>) -> (f64, f64) {
         // Vector from center to point
         let center_to_point: Vector3<f64> = point - self.center;
         // Because norm is a unit vector, dot product of `cp` and `norm` is the length of
-        // the projection.
-        let dot_cp_norm: f64 = center_to_point.dot(&self.normal);
-        if dot_cp_norm.abs() < 1e-6 {
-            return (self.radius, self.radius);
-        }
-        // Get the coordinate of the projection point
-        let projection_point: Point3<f64> = point - self.normal.scale(dot_cp_norm);
-        let center_to_projection_direction: UnitVector3<f64> =
-            UnitVector3::new_normalize(projection_point - self.center);
-        let point_1: Point3<f64> = self.center + center_to_projection_direction.scale(self.radius);
-        let dist_1 = (point - point_1).norm();
-        let point_2: Point3<f64> =
-            self.center + center_to_projection_direction.scale(-1.0 * self.radius);
-        let dist_2 = (point - point_2).norm();
-        if dist_1 < dist_2 {
-            (dist_1, dist_2)
-        } else {
-            (dist_2, dist_1)
-        }
+        // the projection, i.e., the height of the point above the plane of the circle.
+        let height: f64 = center_to_point.dot(&self.normal);
+        // Distance from the center to the projection of the point on the plane
+        let in_plane: f64 = (center_to_point - self.normal.scale(height)).norm();
+        let dist_1 = ((in_plane - self.radius).powi(2) + height.powi(2)).sqrt();
+        let dist_2 = ((in_plane + self.radius).powi(2) + height.powi(2)).sqrt();
+        (dist_1, dist_2)
     }
 }
 
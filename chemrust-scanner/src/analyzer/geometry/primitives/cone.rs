@@ -1,5 +1,7 @@
 use nalgebra::{Point3, UnitVector3};
 
+use crate::analyzer::geometry::Tolerance;
+
 use super::GeometryObject;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -27,15 +29,16 @@ impl Cone {
             tip,
         }
     }
-    pub fn point_in_cone(&self, point: &Point3<f64>) -> bool {
+    /// Strictly inside the lateral surface, by the distance tolerance; the base is included.
+    pub fn point_in_cone(&self, point: &Point3<f64>, tolerance: &Tolerance) -> bool {
         let point_to_tip = point - self.tip;
         let cone_dist = point_to_tip.dot(&self.normal);
-        if cone_dist < 0.0 || cone_dist - self.h() > 1e-6 {
+        if cone_dist < 0.0 || cone_dist - self.h() > tolerance.distance() {
             return false;
         }
         let cone_radius = (cone_dist / self.h()) * self.r();
         let orth_distance = (point_to_tip - self.normal().scale(cone_dist)).norm();
-        orth_distance - cone_radius < -1e-6
+        orth_distance - cone_radius < -tolerance.distance()
     }
 
     pub fn tip(&self) -> Point3<f64> {
@@ -1,4 +1,6 @@
-use nalgebra::{Point3, UnitVector3};
+use std::f64::consts::PI;
+
+use nalgebra::{Point3, UnitVector3, Vector3};
 
 use super::GeometryObject;
 
@@ -16,6 +18,18 @@ impl Sphere {
     pub fn point_at_surface(&self, direction: &UnitVector3<f64>) -> Point3<f64> {
         self.center + direction.scale(self.radius)
     }
+    /// `n` nearly evenly distributed directions over the sphere, by the Fibonacci lattice.
+    pub fn fibonacci_directions(n: usize) -> Vec<UnitVector3<f64>> {
+        let golden_angle = PI * (3.0 - 5.0_f64.sqrt());
+        (0..n)
+            .map(|i| {
+                let z = 1.0 - 2.0 * (i as f64 + 0.5) / n as f64;
+                let r = (1.0 - z * z).sqrt();
+                let phi = golden_angle * i as f64;
+                UnitVector3::new_normalize(Vector3::new(r * phi.cos(), r * phi.sin(), z))
+            })
+            .collect()
+    }
 }
 
 impl GeometryObject for Sphere {}
@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use nalgebra::{Matrix3, Point3, Vector3};
+
+/// Delaunay tetrahedralization of points in space, by the Bowyer-Watson algorithm.
+/// 1. Start from a super tetrahedron enclosing all the points.
+/// 2. Insert the points one by one: the tetrahedra whose circumsphere contains the point are removed,
+///    and the cavity is re-tetrahedralized by connecting its boundary faces to the point.
+/// 3. Remove the tetrahedra sharing vertices with the super tetrahedron.
+///
+/// The tetrahedra are stored with positive orientation, i.e., `(b - a) x (c - a)` points to `d`.
+/// The circumspheres are cached along, as the in-sphere test is done against every tetrahedron.
+#[derive(Debug, Clone)]
+pub struct DelaunayTetrahedralization {
+    points: Vec<Point3<f64>>,
+    tetrahedra: Vec<[usize; 4]>,
+}
+
+/// A tetrahedron with its circumcenter and squared circumradius.
+#[derive(Debug, Clone, Copy)]
+struct Cell {
+    vertices: [usize; 4],
+    center: Point3<f64>,
+    radius_squared: f64,
+}
+
+impl DelaunayTetrahedralization {
+    pub fn new(points: &[Point3<f64>]) -> Self {
+        let n = points.len();
+        if n < 4 {
+            return Self {
+                points: points.to_vec(),
+                tetrahedra: Vec::new(),
+            };
+        }
+        let lower = points
+            .iter()
+            .fold(Vector3::repeat(f64::INFINITY), |acc, p| acc.inf(&p.coords));
+        let upper = points
+            .iter()
+            .fold(Vector3::repeat(f64::NEG_INFINITY), |acc, p| {
+                acc.sup(&p.coords)
+            });
+        let center = Point3::from((lower + upper) / 2.0);
+        let span = (upper - lower).max().max(1.0) * 20.0;
+        let mut vertices = points.to_vec();
+        vertices.push(center + Vector3::new(-span, -span, -span));
+        vertices.push(center + Vector3::new(span, -span, -span));
+        vertices.push(center + Vector3::new(0.0, span, -span));
+        vertices.push(center + Vector3::new(0.0, 0.0, span));
+        let mut cells: Vec<Cell> = new_cell(&vertices, [n, n + 1, n + 2, n + 3])
+            .into_iter()
+            .collect();
+        (0..n).for_each(|i| {
+            let p = vertices[i];
+            let (bad, good): (Vec<Cell>, Vec<Cell>) =
+                cells.iter().partition(|cell| in_circumsphere(cell, &p));
+            let mut face_count: HashMap<[usize; 3], usize> = HashMap::new();
+            let faces: Vec<[usize; 3]> = bad.iter().flat_map(faces_of).collect();
+            faces.iter().for_each(|face| {
+                *face_count.entry(sorted_face(face)).or_insert(0) += 1;
+            });
+            cells = good;
+            // The boundary of the cavity: faces not shared by two removed tetrahedra
+            faces
+                .iter()
+                .filter(|face| face_count[&sorted_face(face)] == 1)
+                .for_each(|face| {
+                    if let Some(cell) = new_cell(&vertices, [face[0], face[1], face[2], i]) {
+                        cells.push(cell)
+                    }
+                });
+        });
+        let tetrahedra = cells
+            .into_iter()
+            .map(|cell| cell.vertices)
+            .filter(|tet| tet.iter().all(|&v| v < n))
+            .collect();
+        Self {
+            points: points.to_vec(),
+            tetrahedra,
+        }
+    }
+
+    pub fn points(&self) -> &[Point3<f64>] {
+        self.points.as_ref()
+    }
+
+    pub fn tetrahedra(&self) -> &[[usize; 4]] {
+        self.tetrahedra.as_ref()
+    }
+    /// Center and radius of the circumsphere of the tetrahedron.
+    pub fn circumsphere(&self, tetrahedron: &[usize; 4]) -> (Point3<f64>, f64) {
+        let (center, radius_squared) = circumsphere_of(&tetrahedron.map(|i| self.points[i]))
+            .expect("Degenerate tetrahedra are not kept");
+        (center, radius_squared.sqrt())
+    }
+}
+
+/// Six times the signed volume of the tetrahedron.
+fn orientation(vertices: &[Point3<f64>; 4]) -> f64 {
+    let [a, b, c, d] = vertices;
+    (b - a).cross(&(c - a)).dot(&(d - a))
+}
+
+/// The circumcenter and squared circumradius, `None` for flat tetrahedra.
+fn circumsphere_of(vertices: &[Point3<f64>; 4]) -> Option<(Point3<f64>, f64)> {
+    let [a, b, c, d] = vertices;
+    let (ab, ac, ad) = (b - a, c - a, d - a);
+    let m = Matrix3::from_rows(&[ab.transpose(), ac.transpose(), ad.transpose()]);
+    let rhs = Vector3::new(ab.norm_squared(), ac.norm_squared(), ad.norm_squared()) / 2.0;
+    let offset = m.try_inverse()? * rhs;
+    Some((a + offset, offset.norm_squared()))
+}
+
+/// Build the tetrahedron with positive orientation. `None` if the vertices are (nearly) coplanar.
+fn new_cell(vertices: &[Point3<f64>], tet: [usize; 4]) -> Option<Cell> {
+    let points = tet.map(|i| vertices[i]);
+    let volume = orientation(&points);
+    let scale = [
+        points[1] - points[0],
+        points[2] - points[0],
+        points[3] - points[0],
+    ]
+    .iter()
+    .map(|v| v.norm())
+    .fold(0.0, f64::max);
+    if volume.abs() <= 1e-12 * scale.powi(3) {
+        return None;
+    }
+    let vertices = if volume > 0.0 {
+        tet
+    } else {
+        [tet[1], tet[0], tet[2], tet[3]]
+    };
+    let (center, radius_squared) = circumsphere_of(&points)?;
+    Some(Cell {
+        vertices,
+        center,
+        radius_squared,
+    })
+}
+
+fn faces_of(cell: &Cell) -> [[usize; 3]; 4] {
+    let [a, b, c, d] = cell.vertices;
+    [[a, b, c], [a, b, d], [a, c, d], [b, c, d]]
+}
+
+fn sorted_face(face: &[usize; 3]) -> [usize; 3] {
+    let mut sorted = *face;
+    sorted.sort();
+    sorted
+}
+
+/// The points on the circumsphere are treated as outside, so the cospherical points
+/// (e.g., the corners of a cube) do not remove the existing tetrahedra.
+fn in_circumsphere(cell: &Cell, p: &Point3<f64>) -> bool {
+    (p - cell.center).norm_squared() < cell.radius_squared * (1.0 - 1e-10)
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::Point3;
+
+    use super::DelaunayTetrahedralization;
+
+    #[test]
+    fn cube_corners() {
+        // The 8 corners of a unit cube are cospherical, split into tetrahedra filling the cube
+        let points: Vec<Point3<f64>> = (0..8)
+            .map(|i| Point3::new((i & 1) as f64, ((i >> 1) & 1) as f64, (i >> 2) as f64))
+            .collect();
+        let tetrahedralization = DelaunayTetrahedralization::new(&points);
+        let volume: f64 = tetrahedralization
+            .tetrahedra()
+            .iter()
+            .map(|tet| {
+                let [a, b, c, d] = tet.map(|i| points[i]);
+                (b - a).cross(&(c - a)).dot(&(d - a)) / 6.0
+            })
+            .sum();
+        assert!((volume - 1.0).abs() < 1e-9);
+        tetrahedralization.tetrahedra().iter().for_each(|tet| {
+            let (center, radius) = tetrahedralization.circumsphere(tet);
+            assert!((center - Point3::new(0.5, 0.5, 0.5)).norm() < 1e-9);
+            assert!((radius - 3.0_f64.sqrt() / 2.0).abs() < 1e-9);
+        })
+    }
+}
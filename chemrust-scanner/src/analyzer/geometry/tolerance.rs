@@ -0,0 +1,77 @@
+use nalgebra::{Point3, UnitVector3};
+
+/// Numerical tolerances of the geometric comparisons.
+/// - `distance`: two lengths (in angstrom) differing less than this are equal, e.g., tangency and points on a circle.
+/// - `angle`: two directions (in radians) differing less than this are parallel, e.g., coplanar circles.
+/// - `merge_distance`: the found sites closer than this are the same site, when merging the results.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    distance: f64,
+    angle: f64,
+    merge_distance: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            distance: 1e-6,
+            angle: 1e-6,
+            merge_distance: 1e-5,
+        }
+    }
+}
+
+impl Tolerance {
+    pub fn new(distance: f64, angle: f64, merge_distance: f64) -> Self {
+        Self {
+            distance,
+            angle,
+            merge_distance,
+        }
+    }
+    pub fn with_distance(self, distance: f64) -> Self {
+        Self { distance, ..self }
+    }
+    pub fn with_angle(self, angle: f64) -> Self {
+        Self { angle, ..self }
+    }
+    pub fn with_merge_distance(self, merge_distance: f64) -> Self {
+        Self {
+            merge_distance,
+            ..self
+        }
+    }
+
+    pub fn distance(&self) -> f64 {
+        self.distance
+    }
+
+    pub fn angle(&self) -> f64 {
+        self.angle
+    }
+
+    pub fn merge_distance(&self) -> f64 {
+        self.merge_distance
+    }
+    /// `|a - b| <= distance`
+    pub fn eq_length(&self, a: f64, b: f64) -> bool {
+        (a - b).abs() <= self.distance
+    }
+    pub fn same_point(&self, p1: &Point3<f64>, p2: &Point3<f64>) -> bool {
+        (p1 - p2).norm() <= self.distance
+    }
+    /// The points closer than `merge_distance` on every axis are the same site.
+    pub fn same_site(&self, p1: &Point3<f64>, p2: &Point3<f64>) -> bool {
+        (p1 - p2).amax() < self.merge_distance
+    }
+    /// Parallel or antiparallel, by the sine of the angle between the directions.
+    pub fn is_parallel(&self, n1: &UnitVector3<f64>, n2: &UnitVector3<f64>) -> bool {
+        n1.cross(n2).norm() <= self.angle.sin()
+    }
+    /// Snap the coordinates down to the grid of `merge_distance`, to clear the meaningless digits
+    /// for the ease of sort and deduplicate.
+    pub fn snap(&self, point: &Point3<f64>) -> Point3<f64> {
+        let grid = self.merge_distance.recip();
+        point.map(|v| (v * grid).floor() / grid)
+    }
+}
@@ -0,0 +1,158 @@
+use nalgebra::{Point2, Vector2};
+
+/// Delaunay triangulation of points in a plane, by the Bowyer-Watson algorithm.
+/// 1. Start from a super triangle enclosing all the points.
+/// 2. Insert the points one by one: the triangles whose circumcircle contains the point are removed,
+///    and the cavity is re-triangulated by connecting its boundary edges to the point.
+/// 3. Remove the triangles sharing vertices with the super triangle.
+///
+/// The triangles are stored in counter-clockwise order of the indices of the input points.
+#[derive(Debug, Clone)]
+pub struct DelaunayTriangulation {
+    points: Vec<Point2<f64>>,
+    triangles: Vec<[usize; 3]>,
+}
+
+impl DelaunayTriangulation {
+    pub fn new(points: &[Point2<f64>]) -> Self {
+        let n = points.len();
+        if n < 3 {
+            return Self {
+                points: points.to_vec(),
+                triangles: Vec::new(),
+            };
+        }
+        let lower = points
+            .iter()
+            .fold(Vector2::repeat(f64::INFINITY), |acc, p| acc.inf(&p.coords));
+        let upper = points
+            .iter()
+            .fold(Vector2::repeat(f64::NEG_INFINITY), |acc, p| {
+                acc.sup(&p.coords)
+            });
+        let center = Point2::from((lower + upper) / 2.0);
+        let span = (upper - lower).max().max(1.0) * 20.0;
+        let mut vertices = points.to_vec();
+        vertices.push(center + Vector2::new(-span, -span));
+        vertices.push(center + Vector2::new(span, -span));
+        vertices.push(center + Vector2::new(0.0, span));
+        let mut triangles: Vec<[usize; 3]> = vec![[n, n + 1, n + 2]];
+        (0..n).for_each(|i| {
+            let p = vertices[i];
+            let (bad, good): (Vec<[usize; 3]>, Vec<[usize; 3]>) = triangles
+                .iter()
+                .partition(|tri| in_circumcircle(&vertices, tri, &p));
+            let edges: Vec<[usize; 2]> = bad
+                .iter()
+                .flat_map(|tri| [[tri[0], tri[1]], [tri[1], tri[2]], [tri[2], tri[0]]])
+                .collect();
+            // The boundary of the cavity: edges not shared by two removed triangles
+            let boundary = edges.iter().filter(|edge| {
+                edges
+                    .iter()
+                    .filter(|other| sorted_edge(other) == sorted_edge(edge))
+                    .count()
+                    == 1
+            });
+            triangles = good;
+            boundary.for_each(|edge| {
+                let tri = [edge[0], edge[1], i];
+                if orientation(&vertices, &tri) > 0.0 {
+                    triangles.push(tri)
+                } else if orientation(&vertices, &tri) < 0.0 {
+                    triangles.push([edge[1], edge[0], i])
+                }
+            });
+        });
+        triangles.retain(|tri| tri.iter().all(|&v| v < n));
+        Self {
+            points: points.to_vec(),
+            triangles,
+        }
+    }
+
+    pub fn points(&self) -> &[Point2<f64>] {
+        self.points.as_ref()
+    }
+
+    pub fn triangles(&self) -> &[[usize; 3]] {
+        self.triangles.as_ref()
+    }
+    /// The unique edges of the triangles, with the smaller index first.
+    pub fn edges(&self) -> Vec<[usize; 2]> {
+        let mut edges: Vec<[usize; 2]> = self
+            .triangles
+            .iter()
+            .flat_map(|tri| {
+                [[tri[0], tri[1]], [tri[1], tri[2]], [tri[2], tri[0]]].map(|e| sorted_edge(&e))
+            })
+            .collect();
+        edges.sort();
+        edges.dedup();
+        edges
+    }
+    /// Center and radius of the circumcircle of the triangle.
+    pub fn circumcircle(&self, triangle: &[usize; 3]) -> (Point2<f64>, f64) {
+        let [a, b, c] = triangle.map(|i| self.points[i]);
+        let (ab, ac) = (b - a, c - a);
+        let d = 2.0 * (ab.x * ac.y - ab.y * ac.x);
+        let offset = Vector2::new(
+            ac.y * ab.norm_squared() - ab.y * ac.norm_squared(),
+            ab.x * ac.norm_squared() - ac.x * ab.norm_squared(),
+        ) / d;
+        (a + offset, offset.norm())
+    }
+    /// Whether the point is inside the triangle or on its edges, within the tolerance
+    /// of the barycentric coordinates.
+    pub fn contains(&self, triangle: &[usize; 3], point: &Point2<f64>, tolerance: f64) -> bool {
+        let [a, b, c] = triangle.map(|i| self.points[i]);
+        let (v0, v1, v2) = (b - a, c - a, point - a);
+        let d = v0.x * v1.y - v0.y * v1.x;
+        let u = (v2.x * v1.y - v2.y * v1.x) / d;
+        let v = (v0.x * v2.y - v0.y * v2.x) / d;
+        u >= -tolerance && v >= -tolerance && u + v <= 1.0 + tolerance
+    }
+}
+
+fn sorted_edge(edge: &[usize; 2]) -> [usize; 2] {
+    [edge[0].min(edge[1]), edge[0].max(edge[1])]
+}
+
+/// Twice the signed area of the triangle, positive when counter-clockwise.
+fn orientation(vertices: &[Point2<f64>], tri: &[usize; 3]) -> f64 {
+    let [a, b, c] = tri.map(|i| vertices[i]);
+    (b - a).perp(&(c - a))
+}
+
+/// The in-circle determinant of a counter-clockwise triangle.
+/// The points on the circumcircle are treated as outside, so the cocircular points
+/// (e.g., the corners of a square) do not remove the existing triangles.
+fn in_circumcircle(vertices: &[Point2<f64>], tri: &[usize; 3], p: &Point2<f64>) -> bool {
+    let [a, b, c] = tri.map(|i| vertices[i] - p);
+    let det = a.norm_squared() * b.perp(&c) - b.norm_squared() * a.perp(&c)
+        + c.norm_squared() * a.perp(&b);
+    let scale = a.norm_squared().max(b.norm_squared()).max(c.norm_squared());
+    det > 1e-10 * scale * scale
+}
+
+#[cfg(test)]
+mod test {
+    use nalgebra::Point2;
+
+    use super::DelaunayTriangulation;
+
+    #[test]
+    fn square_grid() {
+        // 3 x 3 grid of points: 4 squares, 8 triangles, 16 edges
+        let points: Vec<Point2<f64>> = (0..3)
+            .flat_map(|i| (0..3).map(move |j| Point2::new(i as f64, j as f64)))
+            .collect();
+        let triangulation = DelaunayTriangulation::new(&points);
+        assert_eq!(triangulation.triangles().len(), 8);
+        assert_eq!(triangulation.edges().len(), 16);
+        triangulation.triangles().iter().for_each(|tri| {
+            let (_, radius) = triangulation.circumcircle(tri);
+            assert!((radius - 2.0_f64.sqrt() / 2.0).abs() < 1e-6);
+        })
+    }
+}
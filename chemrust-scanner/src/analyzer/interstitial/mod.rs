@@ -0,0 +1,252 @@
+//! Find the interstitial voids of a bulk crystal, to dope the new atom inside.
+//! 1. The atoms and their periodic images within `cutoff` to the cell are tetrahedralized by Delaunay.
+//! 2. The circumcenter of each tetrahedron is a vertex of the Voronoi diagram, the center of an empty
+//!    sphere touching the four atoms of the tetrahedron. The circumradius is the largest radius of
+//!    the empty sphere.
+//! 3. Only the centers inside the home cell are kept, so each periodic void appears once. The tetrahedra
+//!    sharing the same circumcenter are merged, e.g., the octahedral void of the fcc lattice, and all the
+//!    touching atoms are the coordinating atoms.
+//! 4. The voids smaller than `min_radius` are dropped.
+//!
+//! The radius is measured between the centers of the atoms, without the atomic radii.
+//! A void larger than `cutoff` can not be trusted, as the atoms out of the images may fall inside its
+//! sphere; they are dropped as well.
+use chemrust_core::data::BasicLatticeModel;
+use nalgebra::{distance, Point3};
+
+use super::{
+    algorithm::{AtomImage, CoordinationPoint, FinalReport, PeriodicImages},
+    geometry::{DelaunayTetrahedralization, Tolerance},
+};
+
+#[derive(Debug, Clone)]
+pub struct InterstitialVoid {
+    /// The center of the void, with the coordinating atoms and their distances to the center
+    point: CoordinationPoint,
+    /// Radius of the largest empty sphere at the center, in angstrom
+    radius: f64,
+}
+
+impl InterstitialVoid {
+    pub fn center(&self) -> Point3<f64> {
+        self.point.coord()
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+    /// The atoms touching the empty sphere.
+    pub fn coordinating_atoms(&self) -> &[AtomImage] {
+        self.point.connecting_atoms()
+    }
+
+    pub fn point(&self) -> &CoordinationPoint {
+        &self.point
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InterstitialSites {
+    voids: Vec<InterstitialVoid>,
+}
+
+impl InterstitialSites {
+    /// The voids from the largest to the smallest.
+    pub fn voids(&self) -> &[InterstitialVoid] {
+        self.voids.as_ref()
+    }
+    /// One line per void: the radius, the number of coordinating atoms and the center.
+    pub fn report_voids(&self) -> String {
+        self.voids
+            .iter()
+            .map(|void| {
+                format!(
+                    "radius: {:.4}, cn: {}, center: {:.4} {:.4} {:.4}",
+                    void.radius,
+                    void.coordinating_atoms().len(),
+                    void.center().x,
+                    void.center().y,
+                    void.center().z
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+    /// All the voids as multi-coordinated points, to export like the sites from the `MountingChecker`.
+    pub fn report(&self) -> FinalReport {
+        FinalReport::new(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            self.voids.iter().map(|void| void.point.clone()).collect(),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InterstitialFinder {
+    /// Smallest radius of the reported voids, in angstrom
+    min_radius: f64,
+    /// Range of the periodic images around the cell, in angstrom
+    cutoff: f64,
+    tolerance: Tolerance,
+}
+
+impl InterstitialFinder {
+    pub fn new_builder() -> InterstitialFinderBuilder {
+        InterstitialFinderBuilder::new()
+    }
+    /// Find the voids in the periodic model.
+    /// # Panics
+    /// Panics if the model has no lattice vectors.
+    pub fn find(&self, lattice_model: &BasicLatticeModel) -> InterstitialSites {
+        let lattice_vectors = lattice_model
+            .lattice_vectors()
+            .expect("Interstitial voids are searched in a periodic model");
+        let coords: Vec<Point3<f64>> = lattice_model
+            .atoms()
+            .iter()
+            .map(|atom| atom.cartesian_coord())
+            .collect();
+        let images = PeriodicImages::new(&coords, Some(lattice_vectors), self.cutoff);
+        let tetrahedralization = DelaunayTetrahedralization::new(images.coords());
+        let mut found: Vec<(Point3<f64>, f64, Vec<AtomImage>)> = Vec::new();
+        tetrahedralization
+            .tetrahedra()
+            .iter()
+            .for_each(|tetrahedron| {
+                let (center, radius) = tetrahedralization.circumsphere(tetrahedron);
+                if radius < self.min_radius || radius > self.cutoff {
+                    return;
+                }
+                // A void on the cell boundary is kept on the lower side, as `wrap` does.
+                let (_, shift) = images.wrap(&center);
+                if shift != [0, 0, 0] {
+                    return;
+                }
+                let vertices: Vec<AtomImage> = tetrahedron
+                    .iter()
+                    .map(|&image_id| images.images()[image_id])
+                    .collect();
+                match found
+                    .iter_mut()
+                    .find(|(existed, _, _)| self.tolerance.same_site(existed, &center))
+                {
+                    Some((_, _, atoms)) => atoms.extend(vertices),
+                    None => found.push((center, radius, vertices)),
+                }
+            });
+        let mut voids: Vec<InterstitialVoid> = found
+            .into_iter()
+            .map(|(center, radius, mut atoms)| {
+                atoms.sort();
+                atoms.dedup();
+                let distances: Vec<f64> = atoms
+                    .iter()
+                    .map(|atom| distance(&center, &images.image_coord(atom)))
+                    .collect();
+                let mut point = CoordinationPoint::new(center, atoms.clone(), atoms.len() as u32);
+                point.set_bonds(atoms, distances);
+                InterstitialVoid { point, radius }
+            })
+            .collect();
+        voids.sort_by(|a, b| b.radius.total_cmp(&a.radius));
+        InterstitialSites { voids }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InterstitialFinderBuilder {
+    min_radius: f64,
+    cutoff: f64,
+    tolerance: Tolerance,
+}
+
+impl InterstitialFinderBuilder {
+    pub fn new() -> Self {
+        Self {
+            min_radius: 0.0,
+            cutoff: 5.0,
+            tolerance: Tolerance::default(),
+        }
+    }
+    /// Drop the voids whose empty sphere is smaller than `min_radius`, in angstrom.
+    pub fn with_min_radius(self, min_radius: f64) -> Self {
+        Self { min_radius, ..self }
+    }
+    /// Range of the periodic images around the cell, 5 Å by default.
+    /// It also limits the largest void to report.
+    pub fn with_cutoff(self, cutoff: f64) -> Self {
+        Self { cutoff, ..self }
+    }
+    /// The voids closer than the `merge_distance` are merged.
+    pub fn with_tolerance(self, tolerance: Tolerance) -> Self {
+        Self { tolerance, ..self }
+    }
+    pub fn build(self) -> InterstitialFinder {
+        InterstitialFinder {
+            min_radius: self.min_radius,
+            cutoff: self.cutoff,
+            tolerance: self.tolerance,
+        }
+    }
+}
+
+impl Default for InterstitialFinderBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chemrust_core::data::{Atom, BasicLatticeModel, LatticeVectors};
+    use nalgebra::{Matrix3, Point3};
+
+    use super::InterstitialFinder;
+
+    #[test]
+    fn fcc_voids() {
+        let a = 3.61;
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal_element(a));
+        let atoms: Vec<Atom> = [
+            [0.0, 0.0, 0.0],
+            [0.5, 0.5, 0.0],
+            [0.5, 0.0, 0.5],
+            [0.0, 0.5, 0.5],
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, frac)| {
+            Atom::new_builder()
+                .with_symbol("Cu")
+                .with_atomic_number(29)
+                .with_index(i)
+                .with_coord(&Point3::new(frac[0] * a, frac[1] * a, frac[2] * a))
+                .ready()
+                .build()
+        })
+        .collect();
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        let sites = InterstitialFinder::new_builder()
+            .with_min_radius(1.0)
+            .build()
+            .find(&model);
+        // 4 octahedral voids of radius a / 2, and 8 tetrahedral voids of radius a * sqrt(3) / 4
+        let (octahedral, tetrahedral): (Vec<_>, Vec<_>) = sites
+            .voids()
+            .iter()
+            .partition(|void| void.coordinating_atoms().len() == 6);
+        assert_eq!(octahedral.len(), 4);
+        assert_eq!(tetrahedral.len(), 8);
+        assert!(octahedral
+            .iter()
+            .all(|void| (void.radius() - a / 2.0).abs() < 1e-6));
+        assert!(tetrahedral.iter().all(|void| {
+            void.coordinating_atoms().len() == 4
+                && (void.radius() - a * 3.0_f64.sqrt() / 4.0).abs() < 1e-6
+        }));
+        assert_eq!(sites.report().multi_cn_points().len(), 12);
+    }
+}
@@ -1,11 +1,42 @@
 #![allow(dead_code)]
 
-mod algorithm;
+mod adsorbate;
+pub(crate) mod algorithm;
+mod bond_cones;
+mod coverage;
+mod exclusion;
+mod fingerprint;
 mod geometry;
+mod interstitial;
 mod mounting_analyze;
+mod relaxation;
+mod search_strategy;
+mod site_enumeration;
+mod site_ranking;
+mod site_stream;
+mod symmetry;
 
 pub use crate::analyzer::mounting_analyze::MountingChecker;
-pub use algorithm::{FinalReport, IntersectChecker};
+pub use adsorbate::{AdsorptionSite, MolecularAdsorbate};
+pub use algorithm::{ArcRepresentative, FinalReport, IntersectChecker, LBEMountingChecker};
+pub use bond_cones::BondConeFilter;
+pub use coverage::{CoverageConfiguration, CoverageEnumerator};
+pub use exclusion::ExclusionMask;
+pub use fingerprint::{FingerprintFinder, FingerprintReducedReport, SiteFingerprint};
+pub use geometry::Tolerance;
+pub use interstitial::{
+    InterstitialFinder, InterstitialFinderBuilder, InterstitialSites, InterstitialVoid,
+};
+pub use relaxation::{
+    AdatomRelaxer, LennardJonesPotential, MorsePotential, PairPotential, RelaxedPosition,
+};
+pub use search_strategy::{GridSearch, IntersectSearch, LocalEnvSearch, SearchStrategy};
+pub use site_enumeration::{
+    SitePlacement, SurfaceSite, SurfaceSiteEnumerator, SurfaceSiteKind, SurfaceSites,
+};
+pub use site_ranking::{RankedReport, ScoredSite, SiteScorer};
+pub use site_stream::{FoundSite, SiteStream};
+pub use symmetry::{SiteClass, SymmetryFinder, SymmetryReducedReport};
 
 #[cfg(test)]
 mod test {
@@ -129,7 +160,7 @@ mod test {
             .with_element(mount_element)
             .with_bondlength(mount_distance)
             .build();
-        let final_report = mount_checker.mount_search(lattice.atoms(), lattice.atoms());
+        let final_report = mount_checker.mount_search(&lattice, lattice.atoms());
         println!(
             "New element: {}, bonding_distance: {}",
             mount_element.symbol(),
@@ -1,17 +1,20 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use castep_periodic_table::{
     data::ELEMENT_TABLE,
     element::{Element, LookupElement},
 };
-use chemrust_core::data::{atom::AtomCollections, Atom};
+use chemrust_core::data::{Atom, BasicLatticeModel};
 
-use crate::{
-    analyzer::algorithm::{ideal_bondlength, is_bonded},
-    IntersectChecker,
+use crate::analyzer::{
+    algorithm::{ideal_bondlength, is_bonded},
+    bond_cones::BondConeFilter,
+    exclusion::ExclusionMask,
+    geometry::Tolerance,
+    search_strategy::{IntersectSearch, LocalEnvSearch, SearchStrategy},
 };
 
-use super::algorithm::{FinalReport, Ready};
+use super::algorithm::{ArcRepresentative, FinalReport};
 
 pub const LOWER_FAC: f64 = 0.6;
 pub const UPPER_FAC: f64 = 1.15;
@@ -19,66 +22,229 @@ pub const UPPER_FAC: f64 = 1.15;
 #[derive(Debug, Clone)]
 pub struct MountingChecker {
     mount_element: Element,
-    mount_distance: f64,
+    /// Default target bondlength for all host elements
+    mount_distance: Option<f64>,
+    /// Target bondlength to specific host elements, by element symbol
+    host_bondlengths: HashMap<String, f64>,
+    /// Accepted `(min, max)` window of the default bondlength
+    bondlength_window: Option<(f64, f64)>,
+    /// How to place the new atom on the clash-free arcs of the circles
+    arc_representative: ArcRepresentative,
+    /// Numerical tolerances of the intersection checks
+    tolerance: Tolerance,
+    /// Only the host atoms of these elements can bond to the new atom, if given
+    allowed_elements: Option<HashSet<String>>,
+    /// The host atoms of these elements never bond to the new atom
+    forbidden_elements: HashSet<String>,
+    /// Only the host atoms whose target bondlength to the new atom passes `is_bonded` can bond.
+    /// Off by default, so every host element bonds at the target bondlength unless forbidden.
+    bonded_pairs_only: bool,
+    /// Half-angle in radians of the cones along the existing bonds, to discard the shielded sites
+    bond_cone_angle: Option<f64>,
+    /// The atoms whose neighbourhood is excluded from the sites
+    exclusion_mask: Option<ExclusionMask>,
 }
 
 impl MountingChecker {
     pub fn new_builder() -> MountingCheckerBuilder {
         MountingCheckerBuilder::new()
     }
-    fn available_atoms(&self, atoms: &[Atom]) -> Vec<Atom> {
+    /// The target bondlength between the mounting element and the given host atom.
+    /// Priority: the bondlength set for the host element > the default bondlength >
+    /// the `ideal_bondlength` of the element pair.
+    pub fn bondlength_to(&self, host_atom: &Atom) -> f64 {
+        self.host_bondlengths
+            .get(host_atom.symbol())
+            .copied()
+            .or(self.mount_distance)
+            .or(self.bondlength_window.map(|(min, max)| (min + max) / 2.0))
+            .unwrap_or_else(|| {
+                ideal_bondlength(
+                    host_atom.atomic_number(),
+                    self.mount_element.atomic_number(),
+                )
+            })
+    }
+    /// The accepted `(min, max)` bondlength between the mounting element and the given host atom.
+    /// The window set for the default bondlength is shifted to the target bondlength of the host atom,
+    /// keeping the same deviations below and above the target.
+    pub fn bondlength_window_to(&self, host_atom: &Atom) -> (f64, f64) {
+        let bondlength = self.bondlength_to(host_atom);
+        match self.bondlength_window {
+            Some((min, max)) => {
+                let default_bondlength = self.mount_distance.unwrap_or((min + max) / 2.0);
+                (
+                    (bondlength - (default_bondlength - min)).max(0.0),
+                    bondlength + (max - default_bondlength),
+                )
+            }
+            None => (bondlength, bondlength),
+        }
+    }
+    /// Whether the host atom can bond to the new atom, by the allowed and forbidden elements,
+    /// and `is_bonded` between the target and the ideal bondlengths if `bonded_pairs_only`.
+    pub fn is_bonding_partner(&self, host_atom: &Atom) -> bool {
+        let symbol = host_atom.symbol();
+        let is_allowed = self
+            .allowed_elements
+            .as_ref()
+            .map(|allowed| allowed.contains(symbol))
+            .unwrap_or(true);
+        if !is_allowed || self.forbidden_elements.contains(symbol) {
+            return false;
+        }
+        if !self.bonded_pairs_only {
+            return true;
+        }
+        let ideal_bondlength = ideal_bondlength(
+            host_atom.atomic_number(),
+            self.mount_element.atomic_number(),
+        );
+        is_bonded(
+            self.bondlength_to(host_atom),
+            ideal_bondlength,
+            LOWER_FAC,
+            UPPER_FAC,
+        )
+    }
+    pub(crate) fn available_atoms(&self, atoms: &[Atom]) -> Vec<Atom> {
         atoms
             .iter()
-            .filter(|atom| {
-                let ideal_bondlength =
-                    ideal_bondlength(atom.atomic_number(), self.mount_element.atomic_number());
-                is_bonded(self.mount_distance, ideal_bondlength, LOWER_FAC, UPPER_FAC)
-            })
+            .filter(|atom| self.is_bonding_partner(atom))
             .cloned()
             .collect()
     }
+    /// Symbols of the host elements that can bond to the new atom.
     pub fn available_elements(&self, atoms: &[Atom]) -> HashSet<String> {
         atoms
             .iter()
-            .filter(|atom| {
-                let ideal_bondlength =
-                    ideal_bondlength(atom.atomic_number(), self.mount_element.atomic_number());
-                is_bonded(self.mount_distance, ideal_bondlength, LOWER_FAC, UPPER_FAC)
-            })
+            .filter(|atom| self.is_bonding_partner(atom))
             .map(|atom| atom.symbol().into())
-            .collect::<Vec<String>>()
-            .drain(..)
-            .collect::<HashSet<String>>()
-    }
-    pub fn mount_search(&self, model_atoms: &[Atom], to_check_atoms: &[Atom]) -> FinalReport {
-        // let available_atoms: Vec<Atom> = self.available_atoms(atoms);
-        // println!("available_atoms number: {}", available_atoms.len());
-        let collections: AtomCollections = model_atoms.into();
-        let to_check_atom_collections: AtomCollections = to_check_atoms.into();
-        let coords = collections.cartesian_coords().to_vec();
-        let to_check_coords = to_check_atom_collections.cartesian_coords().to_vec();
-        IntersectChecker::<Ready>::new(&coords)
-            .set_check_atoms(&to_check_coords)
-            .start_with_radius(self.mount_distance)
-            .check_spheres()
-            .analyze_circle_intersects()
-            .analyze_points()
-            .report()
-            .clone()
+            .collect()
+    }
+    /// The target bondlengths to all atoms of the model, by atom id.
+    pub fn bondlengths_in(&self, lattice_model: &BasicLatticeModel) -> Vec<f64> {
+        lattice_model
+            .atoms()
+            .iter()
+            .map(|atom| self.bondlength_to(atom))
+            .collect()
+    }
+    /// Whether each atom of the model is a bonding partner, by atom id.
+    /// The atoms masked by the `ExclusionMask` do not bond.
+    pub(crate) fn bonding_atoms_in(&self, lattice_model: &BasicLatticeModel) -> Vec<bool> {
+        let masked_atoms = self
+            .exclusion_mask
+            .as_ref()
+            .map(|mask| mask.masked_atoms(lattice_model))
+            .unwrap_or_default();
+        lattice_model
+            .atoms()
+            .iter()
+            .enumerate()
+            .map(|(atom_id, atom)| {
+                self.is_bonding_partner(atom) && masked_atoms.binary_search(&atom_id).is_err()
+            })
+            .collect()
+    }
+
+    pub fn arc_representative(&self) -> ArcRepresentative {
+        self.arc_representative
+    }
+
+    pub fn tolerance(&self) -> Tolerance {
+        self.tolerance
+    }
+
+    pub fn exclusion_mask(&self) -> Option<&ExclusionMask> {
+        self.exclusion_mask.as_ref()
+    }
+    /// Search the mounting sites around `to_check_atoms` in the model.
+    /// The periodic images of the model atoms are considered when the model has lattice vectors.
+    /// The atoms that are not bonding partners are skipped from `to_check_atoms`, and only
+    /// block the sites too close to them.
+    pub fn mount_search(
+        &self,
+        lattice_model: &BasicLatticeModel,
+        to_check_atoms: &[Atom],
+    ) -> FinalReport {
+        self.search_with(&IntersectSearch, lattice_model, to_check_atoms)
+    }
+    /// Search the mounting sites in the local bonding environments of `to_check_atoms`,
+    /// avoiding the sites on top of the existing bonds. See `LBEMountingChecker`.
+    /// The atoms are identified by `Atom::index()` in the model.
+    pub fn local_env_search(
+        &self,
+        lattice_model: &BasicLatticeModel,
+        to_check_atoms: &[Atom],
+    ) -> FinalReport {
+        self.search_with(&LocalEnvSearch, lattice_model, to_check_atoms)
+    }
+    /// Search the mounting sites around `to_check_atoms` by the given strategy,
+    /// then discard the sites shielded by the bond cones, and the sites around the masked atoms if set.
+    pub fn search_with(
+        &self,
+        strategy: &dyn SearchStrategy,
+        lattice_model: &BasicLatticeModel,
+        to_check_atoms: &[Atom],
+    ) -> FinalReport {
+        let report = strategy.search(self, lattice_model, to_check_atoms);
+        let report =
+            self.shield_by_bond_cones(report, lattice_model, &self.bondlengths_in(lattice_model));
+        match &self.exclusion_mask {
+            Some(mask) => mask.filter(&report, lattice_model),
+            None => report,
+        }
+    }
+    /// Apply the `BondConeFilter` if set, with the cones reaching the longest target bondlength
+    /// beyond the bonded neighbours.
+    fn shield_by_bond_cones(
+        &self,
+        report: FinalReport,
+        lattice_model: &BasicLatticeModel,
+        bondlengths: &[f64],
+    ) -> FinalReport {
+        match self.bond_cone_angle {
+            Some(half_angle) => {
+                let reach = bondlengths.iter().cloned().fold(0.0, f64::max);
+                BondConeFilter::new(lattice_model, half_angle, reach)
+                    .with_tolerance(self.tolerance)
+                    .filter(&report)
+            }
+            None => report,
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct MountingCheckerBuilder {
     mount_element: Option<Element>,
-    mount_distance: f64,
+    mount_distance: Option<f64>,
+    host_bondlengths: HashMap<String, f64>,
+    bondlength_window: Option<(f64, f64)>,
+    arc_representative: ArcRepresentative,
+    tolerance: Tolerance,
+    allowed_elements: Option<HashSet<String>>,
+    forbidden_elements: HashSet<String>,
+    bonded_pairs_only: bool,
+    bond_cone_angle: Option<f64>,
+    exclusion_mask: Option<ExclusionMask>,
 }
 
 impl MountingCheckerBuilder {
     pub fn new() -> Self {
         Self {
             mount_element: None,
-            mount_distance: 0.0,
+            mount_distance: None,
+            host_bondlengths: HashMap::new(),
+            bondlength_window: None,
+            arc_representative: ArcRepresentative::default(),
+            tolerance: Tolerance::default(),
+            allowed_elements: None,
+            forbidden_elements: HashSet::new(),
+            bonded_pairs_only: false,
+            bond_cone_angle: None,
+            exclusion_mask: None,
         }
     }
     pub fn with_element(self, element: &Element) -> Self {
@@ -87,9 +253,94 @@ impl MountingCheckerBuilder {
             ..self
         }
     }
+    /// Default bondlength to all host elements.
+    /// Without it, the `ideal_bondlength` of each element pair is used.
     pub fn with_bondlength(self, bond_length: f64) -> Self {
         Self {
-            mount_distance: bond_length,
+            mount_distance: Some(bond_length),
+            ..self
+        }
+    }
+    /// Bondlength to the atoms of the given host element, overriding the default.
+    pub fn with_host_bondlength(self, host_element_symbol: &str, bond_length: f64) -> Self {
+        let mut host_bondlengths = self.host_bondlengths;
+        host_bondlengths.insert(host_element_symbol.to_string(), bond_length);
+        Self {
+            host_bondlengths,
+            ..self
+        }
+    }
+    /// Set bondlengths of multiple host elements at once.
+    pub fn with_host_bondlengths(self, table: &HashMap<String, f64>) -> Self {
+        let mut host_bondlengths = self.host_bondlengths;
+        host_bondlengths.extend(table.iter().map(|(k, v)| (k.clone(), *v)));
+        Self {
+            host_bondlengths,
+            ..self
+        }
+    }
+    /// Accept the sites with bondlengths between `min` and `max`, instead of the exact target bondlength.
+    /// The target bondlength defaults to the middle of the window if `with_bondlength` is not set.
+    /// The host elements with specific bondlengths use the same deviations around their own targets.
+    /// # Panics
+    /// Panics if `min` is larger than `max`.
+    pub fn with_bondlength_window(self, min: f64, max: f64) -> Self {
+        assert!(min <= max, "Invalid bondlength window: {min} > {max}");
+        Self {
+            bondlength_window: Some((min, max)),
+            ..self
+        }
+    }
+    /// How to choose the position of the new atom on the clash-free arcs.
+    pub fn with_arc_representative(self, arc_representative: ArcRepresentative) -> Self {
+        Self {
+            arc_representative,
+            ..self
+        }
+    }
+    /// Loosen or tighten the numerical tolerances of the intersection checks.
+    pub fn with_tolerance(self, tolerance: Tolerance) -> Self {
+        Self { tolerance, ..self }
+    }
+    /// Only the host atoms of these elements can bond to the new atom.
+    /// The other atoms still block the sites too close to them.
+    pub fn with_allowed_elements(self, symbols: &[String]) -> Self {
+        Self {
+            allowed_elements: Some(symbols.iter().cloned().collect()),
+            ..self
+        }
+    }
+    /// The host atoms of these elements never bond to the new atom,
+    /// but still block the sites too close to them.
+    pub fn with_forbidden_elements(self, symbols: &[String]) -> Self {
+        let mut forbidden_elements = self.forbidden_elements;
+        forbidden_elements.extend(symbols.iter().cloned());
+        Self {
+            forbidden_elements,
+            ..self
+        }
+    }
+    /// Exclude the host atoms whose target bondlength is not a bond to the new element by `is_bonded`,
+    /// i.e., out of `LOWER_FAC` to `UPPER_FAC` times their ideal bondlength.
+    pub fn with_bonded_pairs_only(self, bonded_pairs_only: bool) -> Self {
+        Self {
+            bonded_pairs_only,
+            ..self
+        }
+    }
+    /// Discard the sites inside the cones along the existing host bonds, with the half-angle in radians.
+    /// See `BondConeFilter`.
+    pub fn with_bond_cone_filter(self, half_angle: f64) -> Self {
+        Self {
+            bond_cone_angle: Some(half_angle),
+            ..self
+        }
+    }
+    /// Discard the sites around the masked atoms, which do not bond but still block the sites.
+    /// See `ExclusionMask`.
+    pub fn with_exclusion_mask(self, exclusion_mask: ExclusionMask) -> Self {
+        Self {
+            exclusion_mask: Some(exclusion_mask),
             ..self
         }
     }
@@ -100,6 +351,15 @@ impl MountingCheckerBuilder {
         MountingChecker {
             mount_element,
             mount_distance: self.mount_distance,
+            host_bondlengths: self.host_bondlengths,
+            bondlength_window: self.bondlength_window,
+            arc_representative: self.arc_representative,
+            tolerance: self.tolerance,
+            allowed_elements: self.allowed_elements,
+            forbidden_elements: self.forbidden_elements,
+            bonded_pairs_only: self.bonded_pairs_only,
+            bond_cone_angle: self.bond_cone_angle,
+            exclusion_mask: self.exclusion_mask,
         }
     }
 }
@@ -109,3 +369,46 @@ impl Default for MountingCheckerBuilder {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use castep_periodic_table::{data::ELEMENT_TABLE, element::LookupElement};
+    use chemrust_core::data::Atom;
+    use nalgebra::Point3;
+
+    use super::MountingChecker;
+
+    #[test]
+    fn bonding_partners() {
+        let atoms: Vec<Atom> = ["C", "Pt"]
+            .iter()
+            .enumerate()
+            .map(|(i, &symbol)| {
+                Atom::new_builder()
+                    .with_symbol(symbol)
+                    .with_index(i)
+                    .with_coord(&Point3::new(3.0 * i as f64, 0.0, 0.0))
+                    .ready()
+                    .build()
+            })
+            .collect();
+        let symbols = |elements: HashSet<String>| {
+            let mut symbols: Vec<String> = elements.into_iter().collect();
+            symbols.sort();
+            symbols
+        };
+        let builder = MountingChecker::new_builder()
+            .with_element(ELEMENT_TABLE.get_by_symbol("H").unwrap())
+            .with_bondlength(1.5);
+        // By default, every host element bonds, however far the target bondlength is from a C-H bond
+        let all = builder.clone().build();
+        assert_eq!(symbols(all.available_elements(&atoms)), ["C", "Pt"]);
+        // 1.5 Å is too long for a C-H bond, but fits a Pt-H bond
+        let bonded_only = builder.clone().with_bonded_pairs_only(true).build();
+        assert_eq!(symbols(bonded_only.available_elements(&atoms)), ["Pt"]);
+        let forbidden = builder.with_forbidden_elements(&["Pt".to_string()]).build();
+        assert_eq!(symbols(forbidden.available_elements(&atoms)), ["C"]);
+    }
+}
@@ -0,0 +1,384 @@
+//! Quick relaxation of the new atom at each site, with the host frozen.
+//! The exact intersections put the new atom at the target bondlengths to its connecting atoms,
+//! but often a little too close to a third atom. Starting from the anchor position of each site
+//! (the mounting point of a sphere, the anchor of a circle, the representative of an arc, or the point),
+//! the new atom is moved down the forces of a `PairPotential` to the host atoms and their periodic images,
+//! by the FIRE algorithm (Bitzek et al., Phys. Rev. Lett. 2006, 97, 170201).
+//!
+//! The positions still above the force tolerance after `max_steps` are dropped. The converged positions
+//! are wrapped into the cell, and measured again: the host atoms within `is_bonded` of the target
+//! bondlengths are the connecting atoms. The sites converged to the same minimum, by the minimum image
+//! distance, are merged, keeping the first one. The relaxed sites are reported as points: one- and
+//! two-coordinated sites in the `cut_points`, the others in the `multi_cn_points`.
+use chemrust_core::data::BasicLatticeModel;
+use kd_tree::KdMap;
+use nalgebra::{distance, Matrix3, Point3, Vector3};
+use rayon::prelude::*;
+
+use super::{
+    adsorbate::AdsorptionSite,
+    algorithm::{is_bonded, AtomImage, CoordinationPoint, FinalReport, PeriodicImages},
+    mounting_analyze::{LOWER_FAC, UPPER_FAC},
+    symmetry::frac_distance,
+};
+
+mod potential;
+
+pub use potential::{LennardJonesPotential, MorsePotential, PairPotential};
+
+/// Relax the new atom at the sites by FIRE under the pair potential.
+#[derive(Debug, Clone)]
+pub struct AdatomRelaxer<P: PairPotential> {
+    potential: P,
+    /// Target bondlength to each host atom, by atom id, to find the bonds after the relaxation
+    bondlengths: Vec<f64>,
+    /// Stop when the force is below this value, in eV/Å
+    force_tolerance: f64,
+    max_steps: usize,
+    /// Largest displacement in one step, in angstrom
+    max_displacement: f64,
+    /// The relaxed sites closer than this distance are merged, in angstrom
+    merge_distance: f64,
+}
+
+/// The relaxed position of the new atom.
+#[derive(Debug, Clone, Copy)]
+pub struct RelaxedPosition {
+    position: Point3<f64>,
+    /// Energy in eV
+    energy: f64,
+    /// The remaining force in eV/Å
+    force: f64,
+    steps: usize,
+}
+
+impl RelaxedPosition {
+    pub fn position(&self) -> Point3<f64> {
+        self.position
+    }
+
+    pub fn energy(&self) -> f64 {
+        self.energy
+    }
+
+    pub fn force(&self) -> f64 {
+        self.force
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+    /// Whether the remaining force is within the force tolerance.
+    pub fn is_converged(&self, force_tolerance: f64) -> bool {
+        self.force <= force_tolerance
+    }
+}
+
+impl<P: PairPotential> AdatomRelaxer<P> {
+    /// Relax under `potential`, and find the bonds by the target `bondlengths` to the host atoms.
+    /// 0.01 eV/Å force tolerance, 500 steps, 0.1 Å largest displacement and 0.05 Å merge distance by default.
+    pub fn new(potential: P, bondlengths: &[f64]) -> Self {
+        Self {
+            potential,
+            bondlengths: bondlengths.to_vec(),
+            force_tolerance: 0.01,
+            max_steps: 500,
+            max_displacement: 0.1,
+            merge_distance: 0.05,
+        }
+    }
+    pub fn with_force_tolerance(self, force_tolerance: f64) -> Self {
+        Self {
+            force_tolerance,
+            ..self
+        }
+    }
+    pub fn with_max_steps(self, max_steps: usize) -> Self {
+        Self { max_steps, ..self }
+    }
+    pub fn with_max_displacement(self, max_displacement: f64) -> Self {
+        Self {
+            max_displacement,
+            ..self
+        }
+    }
+    pub fn with_merge_distance(self, merge_distance: f64) -> Self {
+        Self {
+            merge_distance,
+            ..self
+        }
+    }
+
+    pub fn potential(&self) -> &P {
+        &self.potential
+    }
+    /// Relax the new atom at every site of the report, and merge the sites converged together.
+    /// The sites not converged within `max_steps` are dropped.
+    pub fn relax(
+        &self,
+        final_report: &FinalReport,
+        lattice_model: &BasicLatticeModel,
+    ) -> FinalReport {
+        let environment = Environment::new(lattice_model, self.potential.cutoff());
+        let starts: Vec<Point3<f64>> = [
+            anchors_of(final_report.sphere_sites()),
+            anchors_of(final_report.circles()),
+            anchors_of(final_report.arcs()),
+            anchors_of(final_report.cut_points()),
+            anchors_of(final_report.multi_cn_points()),
+        ]
+        .concat();
+        let relaxed: Vec<RelaxedPosition> = starts
+            .par_iter()
+            .map(|start| self.relax_position(&environment, start))
+            .collect();
+        let mut sites: Vec<CoordinationPoint> = Vec::new();
+        relaxed
+            .iter()
+            .filter(|relaxed| relaxed.is_converged(self.force_tolerance))
+            .for_each(|relaxed| {
+                let (wrapped, _) = environment.images.wrap(&relaxed.position);
+                let is_copy = sites.iter().any(|existed| {
+                    environment.distance(&existed.coord(), &wrapped) < self.merge_distance
+                });
+                if !is_copy {
+                    sites.push(self.measure(&environment, &wrapped))
+                }
+            });
+        let (cut_points, multi_cn_points): (Vec<CoordinationPoint>, Vec<CoordinationPoint>) =
+            sites.into_iter().partition(|site| site.cn() <= 2);
+        FinalReport::new(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            cut_points,
+            multi_cn_points,
+        )
+    }
+    /// Relax the new atom from the `start` position in the model, by FIRE.
+    pub fn relax_from(
+        &self,
+        lattice_model: &BasicLatticeModel,
+        start: &Point3<f64>,
+    ) -> RelaxedPosition {
+        let environment = Environment::new(lattice_model, self.potential.cutoff());
+        self.relax_position(&environment, start)
+    }
+    fn relax_position(&self, environment: &Environment, start: &Point3<f64>) -> RelaxedPosition {
+        // FIRE parameters, as suggested by Bitzek et al.
+        const N_MIN: usize = 5;
+        const F_INC: f64 = 1.1;
+        const F_DEC: f64 = 0.5;
+        const ALPHA_START: f64 = 0.1;
+        const F_ALPHA: f64 = 0.99;
+        let dt_max = 10.0 * self.max_displacement;
+        let mut dt = self.max_displacement;
+        let mut alpha = ALPHA_START;
+        let mut steps_since_uphill = 0;
+        let mut position = *start;
+        let mut velocity = Vector3::zeros();
+        let (mut energy, mut force) = environment.energy_and_force(&self.potential, &position);
+        let mut steps = 0;
+        while steps < self.max_steps && force.norm() > self.force_tolerance {
+            if force.dot(&velocity) > 0.0 {
+                velocity = (1.0 - alpha) * velocity + alpha * velocity.norm() * force.normalize();
+                if steps_since_uphill > N_MIN {
+                    dt = (dt * F_INC).min(dt_max);
+                    alpha *= F_ALPHA;
+                }
+                steps_since_uphill += 1;
+            } else {
+                velocity = Vector3::zeros();
+                dt *= F_DEC;
+                alpha = ALPHA_START;
+                steps_since_uphill = 0;
+            }
+            velocity += force * dt;
+            let displacement = velocity * dt;
+            position += if displacement.norm() > self.max_displacement {
+                displacement.normalize() * self.max_displacement
+            } else {
+                displacement
+            };
+            (energy, force) = environment.energy_and_force(&self.potential, &position);
+            steps += 1;
+        }
+        RelaxedPosition {
+            position,
+            energy,
+            force: force.norm(),
+            steps,
+        }
+    }
+    /// The site at the wrapped position, bonded to the host atoms within `is_bonded` of their target bondlengths.
+    fn measure(&self, environment: &Environment, position: &Point3<f64>) -> CoordinationPoint {
+        let max_bondlength = self.bondlengths.iter().cloned().fold(0.0, f64::max);
+        let mut bonds: Vec<(AtomImage, f64)> = environment
+            .coords_kdtree
+            .within_radius(position, max_bondlength * UPPER_FAC)
+            .into_iter()
+            .map(|(coord, image_id)| {
+                (
+                    environment.images.images()[*image_id],
+                    distance(position, coord),
+                )
+            })
+            .filter(|(image, d)| {
+                is_bonded(*d, self.bondlengths[image.atom_id()], LOWER_FAC, UPPER_FAC)
+            })
+            .collect();
+        bonds.sort_by_key(|bond| bond.0);
+        let (atoms, distances): (Vec<AtomImage>, Vec<f64>) = bonds.into_iter().unzip();
+        let mut site = CoordinationPoint::new(*position, atoms.clone(), atoms.len() as u32);
+        site.set_bonds(atoms, distances);
+        site
+    }
+}
+
+/// The host atoms and their periodic images around the cell.
+struct Environment {
+    images: PeriodicImages,
+    coords_kdtree: KdMap<Point3<f64>, usize>,
+    cutoff: f64,
+    lattice_matrix: Option<Matrix3<f64>>,
+    frac_matrix: Option<Matrix3<f64>>,
+}
+
+impl Environment {
+    fn new(lattice_model: &BasicLatticeModel, cutoff: f64) -> Self {
+        let coords: Vec<Point3<f64>> = lattice_model
+            .atoms()
+            .iter()
+            .map(|atom| atom.cartesian_coord())
+            .collect();
+        let images = PeriodicImages::new(&coords, lattice_model.lattice_vectors(), cutoff);
+        let coords_kdtree = images.kdtree();
+        let lattice_vectors = lattice_model.lattice_vectors();
+        Self {
+            images,
+            coords_kdtree,
+            cutoff,
+            lattice_matrix: lattice_vectors.map(|lattice| *lattice.data()),
+            frac_matrix: lattice_vectors.map(|lattice| lattice.mat_cart_to_frac()),
+        }
+    }
+    /// The distance between the positions, by the minimum image under PBC.
+    fn distance(&self, p1: &Point3<f64>, p2: &Point3<f64>) -> f64 {
+        match (self.lattice_matrix, self.frac_matrix) {
+            (Some(lattice_matrix), Some(to_frac)) => {
+                frac_distance(&lattice_matrix, &(to_frac * (p2 - p1)))
+            }
+            _ => distance(p1, p2),
+        }
+    }
+    /// The energy and the force on the new atom at the position, wrapped into the cell.
+    fn energy_and_force<P: PairPotential>(
+        &self,
+        potential: &P,
+        position: &Point3<f64>,
+    ) -> (f64, Vector3<f64>) {
+        let (wrapped, _) = self.images.wrap(position);
+        self.coords_kdtree
+            .within_radius(&wrapped, self.cutoff)
+            .into_iter()
+            .fold(
+                (0.0, Vector3::zeros()),
+                |(energy, force), (coord, image_id)| {
+                    let atom_id = self.images.images()[*image_id].atom_id();
+                    let r_vec = wrapped - coord;
+                    let r = r_vec.norm();
+                    let (e, de_dr) = potential.evaluate(atom_id, r);
+                    (energy + e, force - r_vec / r * de_dr)
+                },
+            )
+    }
+}
+
+fn anchors_of<T: AdsorptionSite>(sites: &[T]) -> Vec<Point3<f64>> {
+    sites.iter().map(|site| site.anchor_position()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use chemrust_core::data::{BasicLatticeModel, LatticeVectors};
+    use nalgebra::{Matrix3, Point3, Vector3};
+
+    use crate::analyzer::{
+        algorithm::{AtomImage, CoordinationPoint, FinalReport},
+        search_strategy::test::carbon_triangle,
+    };
+
+    use super::{AdatomRelaxer, MorsePotential};
+
+    #[test]
+    fn converge_to_hollow() {
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal_element(15.0));
+        // The triangle in the middle of the cell
+        let atoms = carbon_triangle(Point3::new(7.0, 7.0, 7.5));
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        let centroid = Point3::new(7.8, 7.0 + 0.8 / 3.0_f64.sqrt(), 7.5);
+        let bondlengths = vec![1.5; 3];
+        // A bridge of atoms 0 and 1 too close to atom 2, and a point off the hollow
+        let bridge = CoordinationPoint::new(
+            centroid + Vector3::new(0.0, -0.2, 1.0),
+            vec![AtomImage::home(0), AtomImage::home(1)],
+            2,
+        );
+        let off_hollow = CoordinationPoint::new(
+            centroid + Vector3::new(-0.1, 0.1, 1.4),
+            vec![AtomImage::home(0)],
+            1,
+        );
+        let report = FinalReport::new(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![bridge, off_hollow],
+            Vec::new(),
+        );
+        let relaxer = AdatomRelaxer::new(MorsePotential::new(&bondlengths), &bondlengths)
+            .with_force_tolerance(1e-4);
+        let relaxed = relaxer.relax(&report, &model);
+        assert!(relaxed.cut_points().is_empty());
+        assert_eq!(relaxed.multi_cn_points().len(), 1);
+        let hollow = &relaxed.multi_cn_points()[0];
+        assert_eq!(hollow.cn(), 3);
+        assert!(hollow
+            .bond_distances()
+            .iter()
+            .all(|d| (d - 1.5).abs() < 1e-3));
+        // Not converged in a few steps
+        let unfinished = relaxer.with_max_steps(3).relax(&report, &model);
+        assert!(unfinished.cut_points().is_empty());
+        assert!(unfinished.multi_cn_points().is_empty());
+    }
+
+    #[test]
+    fn merge_across_boundary() {
+        // The hollow of the triangle sits on the `a` boundary
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal_element(15.0));
+        let atoms = carbon_triangle(Point3::new(-0.8, 7.0, 7.5));
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        let centroid = Point3::new(0.0, 7.0 + 0.8 / 3.0_f64.sqrt(), 7.5);
+        let bondlengths = vec![1.5; 3];
+        // Mirror images across the boundary, converging to the two sides of it
+        let starts: Vec<CoordinationPoint> = [-0.2, 0.2]
+            .iter()
+            .map(|&dx| {
+                CoordinationPoint::new(
+                    centroid + Vector3::new(dx, 0.0, 1.2),
+                    vec![AtomImage::home(0)],
+                    1,
+                )
+            })
+            .collect();
+        let report = FinalReport::new(Vec::new(), Vec::new(), Vec::new(), starts, Vec::new());
+        let relaxer = AdatomRelaxer::new(MorsePotential::new(&bondlengths), &bondlengths);
+        let left = relaxer.relax_from(&model, &(centroid + Vector3::new(-0.2, 0.0, 1.2)));
+        let right = relaxer.relax_from(&model, &(centroid + Vector3::new(0.2, 0.0, 1.2)));
+        assert!(left.position().x < 0.0 && right.position().x > 0.0);
+        let relaxed = relaxer.relax(&report, &model);
+        assert_eq!(relaxed.multi_cn_points().len(), 1);
+        assert_eq!(relaxed.multi_cn_points()[0].cn(), 3);
+    }
+}
@@ -0,0 +1,113 @@
+use castep_periodic_table::element::Element;
+use chemrust_core::data::BasicLatticeModel;
+
+use crate::analyzer::site_ranking::{uff_lj_parameters, KCAL_PER_MOL_TO_EV};
+
+/// Interaction between the new atom and each host atom, as a function of their distance.
+pub trait PairPotential: Sync {
+    /// The energy in eV and its derivative by the distance `r` in eV/Å,
+    /// between the new atom and the host atom of `atom_id`.
+    fn evaluate(&self, atom_id: usize, r: f64) -> (f64, f64);
+    /// The interactions beyond this distance are ignored, in angstrom.
+    fn cutoff(&self) -> f64;
+}
+
+/// `E = D [(1 - exp(-a (r - r0)))^2 - 1]`, with the minimum `-D` at the target bondlength `r0`
+/// to each host atom. It pulls the new atom to the bondlengths of the site, and pushes it away
+/// from the atoms closer than their bondlengths.
+#[derive(Debug, Clone)]
+pub struct MorsePotential {
+    /// Target bondlength to each host atom, by atom id
+    equilibrium_distances: Vec<f64>,
+    /// Depth `D` of the well, in eV
+    well_depth: f64,
+    /// Width parameter `a` of the well, in 1/Å
+    width: f64,
+    cutoff: f64,
+}
+
+impl MorsePotential {
+    /// Morse wells at the target bondlengths to the host atoms, by atom id.
+    /// 1 eV depth, 2 / Å width and 6 Å cutoff by default.
+    pub fn new(equilibrium_distances: &[f64]) -> Self {
+        Self {
+            equilibrium_distances: equilibrium_distances.to_vec(),
+            well_depth: 1.0,
+            width: 2.0,
+            cutoff: 6.0,
+        }
+    }
+    pub fn with_well_depth(self, well_depth: f64) -> Self {
+        Self { well_depth, ..self }
+    }
+    pub fn with_width(self, width: f64) -> Self {
+        Self { width, ..self }
+    }
+    pub fn with_cutoff(self, cutoff: f64) -> Self {
+        Self { cutoff, ..self }
+    }
+}
+
+impl PairPotential for MorsePotential {
+    fn evaluate(&self, atom_id: usize, r: f64) -> (f64, f64) {
+        let decay = (-self.width * (r - self.equilibrium_distances[atom_id])).exp();
+        let energy = self.well_depth * ((1.0 - decay).powi(2) - 1.0);
+        let derivative = 2.0 * self.well_depth * self.width * (1.0 - decay) * decay;
+        (energy, derivative)
+    }
+
+    fn cutoff(&self) -> f64 {
+        self.cutoff
+    }
+}
+
+/// The UFF Lennard-Jones potential, as used by the `SiteScorer`.
+/// The van der Waals minimum is far beyond the bondlengths, so it only suits the physisorbed atoms.
+#[derive(Debug, Clone)]
+pub struct LennardJonesPotential {
+    /// Mixed `(x, D)` to each host atom, in angstrom and eV
+    pair_parameters: Vec<(f64, f64)>,
+    cutoff: f64,
+}
+
+impl LennardJonesPotential {
+    /// The UFF parameters of the new element to the atoms of the model, 8 Å cutoff by default.
+    /// # Panics
+    /// Panics if any element has no UFF parameters.
+    pub fn new(new_element: &Element, lattice_model: &BasicLatticeModel) -> Self {
+        let parameters_of = |atomic_number: u8| {
+            uff_lj_parameters(atomic_number)
+                .unwrap_or_else(|| panic!("No UFF parameters of atomic number {atomic_number}"))
+        };
+        let (x_new, d_new) = parameters_of(new_element.atomic_number());
+        let pair_parameters = lattice_model
+            .atoms()
+            .iter()
+            .map(|atom| {
+                let (x, d) = parameters_of(atom.atomic_number());
+                ((x * x_new).sqrt(), (d * d_new).sqrt() * KCAL_PER_MOL_TO_EV)
+            })
+            .collect();
+        Self {
+            pair_parameters,
+            cutoff: 8.0,
+        }
+    }
+    pub fn with_cutoff(self, cutoff: f64) -> Self {
+        Self { cutoff, ..self }
+    }
+}
+
+impl PairPotential for LennardJonesPotential {
+    fn evaluate(&self, atom_id: usize, r: f64) -> (f64, f64) {
+        let (x, d) = self.pair_parameters[atom_id];
+        let ratio6 = (x / r).powi(6);
+        let energy = d * (ratio6 * ratio6 - 2.0 * ratio6);
+        let derivative = -12.0 * d * (ratio6 * ratio6 - ratio6) / r;
+        (energy, derivative)
+    }
+
+    fn cutoff(&self) -> f64 {
+        self.cutoff
+    }
+}
@@ -0,0 +1,313 @@
+//! Exhaustive site search on a grid.
+//! 1. The region of interest, the bounding box of the atoms to check padded by the longest bondlength,
+//!    is rasterized on a cubic grid with the given spacing.
+//! 2. At each grid point, the distances to the atoms and their periodic images are measured. A grid point
+//!    is accepted if no atom is closer than its bondlength window, and at least one of the atoms to check
+//!    falls within its window. The atoms within their windows are the bonded atoms of the point.
+//! 3. The neighbouring accepted points bonded to the same atoms are clustered into one site, placed at the
+//!    point of the cluster closest to the target bondlengths.
+//! 4. The sites are wrapped into the cell and the periodic copies are merged.
+//!
+//! A grid point is at most half the diagonal of a grid cell away from the exact site, so the windows
+//! are widened by this amount. The sites are reported as points: one- and two-coordinated sites in the
+//! `cut_points`, the others in the `multi_cn_points`.
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use chemrust_core::data::{Atom, BasicLatticeModel};
+use nalgebra::{distance, Point3, Vector3};
+use rayon::prelude::*;
+
+use crate::analyzer::{
+    algorithm::{AtomImage, CoordinationPoint, FinalReport, PeriodicImages},
+    mounting_analyze::MountingChecker,
+};
+
+use super::SearchStrategy;
+
+/// Rasterize the region around the atoms to check, and cluster the grid points
+/// satisfying the bondlength windows into sites.
+#[derive(Debug, Clone, Copy)]
+pub struct GridSearch {
+    /// Distance between the neighbouring grid points, in angstrom
+    spacing: f64,
+}
+
+/// An accepted grid point with its bonds, sorted by the atom images.
+struct GridPoint {
+    index: [i64; 3],
+    coord: Point3<f64>,
+    bonds: Vec<(AtomImage, f64)>,
+    /// Sum of the squared deviations from the target bondlengths
+    deviation: f64,
+}
+
+impl GridSearch {
+    /// # Panics
+    /// Panics if `spacing` is not positive.
+    pub fn new(spacing: f64) -> Self {
+        assert!(spacing > 0.0, "Invalid grid spacing: {spacing}");
+        Self { spacing }
+    }
+
+    pub fn spacing(&self) -> f64 {
+        self.spacing
+    }
+    /// Half of the diagonal of a grid cell: the farthest distance from a point to the grid.
+    fn half_diagonal(&self) -> f64 {
+        self.spacing * 3.0_f64.sqrt() / 2.0
+    }
+}
+
+impl Default for GridSearch {
+    /// 0.1 Å spacing.
+    fn default() -> Self {
+        Self::new(0.1)
+    }
+}
+
+impl SearchStrategy for GridSearch {
+    fn search(
+        &self,
+        mount_checker: &MountingChecker,
+        lattice_model: &BasicLatticeModel,
+        to_check_atoms: &[Atom],
+    ) -> FinalReport {
+        let to_check_ids: HashSet<usize> = mount_checker
+            .available_atoms(to_check_atoms)
+            .iter()
+            .map(|atom| atom.index())
+            .collect();
+        if to_check_ids.is_empty() {
+            return FinalReport::new(Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        }
+        let bondlengths = mount_checker.bondlengths_in(lattice_model);
+        let bonding_atoms = mount_checker.bonding_atoms_in(lattice_model);
+        let windows: Vec<(f64, f64)> = lattice_model
+            .atoms()
+            .iter()
+            .map(|atom| mount_checker.bondlength_window_to(atom))
+            .collect();
+        let widen = self.half_diagonal();
+        let reach = windows.iter().map(|&(_, max)| max).fold(0.0, f64::max) + widen;
+        let coords: Vec<Point3<f64>> = lattice_model
+            .atoms()
+            .iter()
+            .map(|atom| atom.cartesian_coord())
+            .collect();
+        let images = PeriodicImages::new(&coords, lattice_model.lattice_vectors(), reach);
+        let coords_kdtree = images.kdtree();
+        let measure = |index: [i64; 3], coord: Point3<f64>| -> Option<GridPoint> {
+            let mut bonds: Vec<(AtomImage, f64)> = Vec::new();
+            for (image_coord, image_id) in coords_kdtree.within_radius(&coord, reach) {
+                let image = images.images()[*image_id];
+                let (min, max) = windows[image.atom_id()];
+                let d = distance(&coord, image_coord);
+                if d < min - widen {
+                    return None;
+                }
+                if d <= max + widen && bonding_atoms[image.atom_id()] {
+                    bonds.push((image, d));
+                }
+            }
+            if !bonds
+                .iter()
+                .any(|(image, _)| to_check_ids.contains(&image.atom_id()))
+            {
+                return None;
+            }
+            bonds.sort_by_key(|bond| bond.0);
+            let deviation = bonds
+                .iter()
+                .map(|(image, d)| (d - bondlengths[image.atom_id()]).powi(2))
+                .sum();
+            Some(GridPoint {
+                index,
+                coord,
+                bonds,
+                deviation,
+            })
+        };
+        let (origin, counts) = self.region(&coords, &to_check_ids, reach);
+        let grid_points: Vec<GridPoint> = (0..counts[0])
+            .into_par_iter()
+            .flat_map_iter(|i| {
+                (0..counts[1]).flat_map(move |j| (0..counts[2]).map(move |k| [i, j, k]))
+            })
+            .filter_map(|index| {
+                let coord = origin
+                    + Vector3::new(index[0] as f64, index[1] as f64, index[2] as f64)
+                        * self.spacing;
+                measure(index, coord)
+            })
+            .collect();
+        let mut sites: Vec<(Vec<AtomImage>, Point3<f64>, CoordinationPoint)> = Vec::new();
+        clusters(&grid_points).into_iter().for_each(|cluster| {
+            let points: Vec<&GridPoint> = cluster.iter().map(|&id| &grid_points[id]).collect();
+            // Translate the cluster to put its first bonded atom in the home cell,
+            // so the periodic copies of the cluster share the same atoms and centroid.
+            let offset = points[0].bonds[0].0.offset();
+            let back_shift = [-offset[0], -offset[1], -offset[2]];
+            let atoms: Vec<AtomImage> = points[0]
+                .bonds
+                .iter()
+                .map(|(image, _)| image.translate(back_shift))
+                .collect();
+            let centroid = Point3::from(
+                points
+                    .iter()
+                    .map(|point| point.coord.coords)
+                    .sum::<Vector3<f64>>()
+                    / points.len() as f64,
+            ) + images.translation(back_shift);
+            let is_copy = sites.iter().any(|(existed_atoms, existed_centroid, _)| {
+                existed_atoms == &atoms && distance(existed_centroid, &centroid) < 2.0 * widen
+            });
+            if !is_copy {
+                let site = self.place_site(&points, &images);
+                sites.push((atoms, centroid, site))
+            }
+        });
+        let (cut_points, multi_cn_points): (Vec<CoordinationPoint>, Vec<CoordinationPoint>) = sites
+            .into_iter()
+            .map(|(_, _, site)| site)
+            .partition(|site| site.cn() <= 2);
+        FinalReport::new(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            cut_points,
+            multi_cn_points,
+        )
+    }
+}
+
+impl GridSearch {
+    /// Place the site at the point of the cluster closest to the target bondlengths. Among the points
+    /// equally close within the grid resolution, e.g., on a one-coordinated cap, the one nearest to the
+    /// centroid of the cluster is chosen. The point is wrapped into the cell with its bonds.
+    fn place_site(&self, points: &[&GridPoint], images: &PeriodicImages) -> CoordinationPoint {
+        let min_deviation = points
+            .iter()
+            .map(|point| point.deviation)
+            .fold(f64::INFINITY, f64::min);
+        let centroid = Point3::from(
+            points
+                .iter()
+                .map(|point| point.coord.coords)
+                .sum::<Vector3<f64>>()
+                / points.len() as f64,
+        );
+        let best = points
+            .iter()
+            .filter(|point| point.deviation <= min_deviation + self.half_diagonal().powi(2))
+            .min_by(|a, b| distance(&a.coord, &centroid).total_cmp(&distance(&b.coord, &centroid)))
+            .expect("Clusters are not empty");
+        let (wrapped, shift) = images.wrap(&best.coord);
+        let back_shift = [-shift[0], -shift[1], -shift[2]];
+        let (atoms, distances): (Vec<AtomImage>, Vec<f64>) = best
+            .bonds
+            .iter()
+            .map(|(image, d)| (image.translate(back_shift), *d))
+            .unzip();
+        let mut site = CoordinationPoint::new(wrapped, atoms.clone(), atoms.len() as u32);
+        site.set_bonds(atoms, distances);
+        site
+    }
+    /// The lowest corner and the number of grid points along each axis, covering the atoms
+    /// to check padded by `reach`.
+    fn region(
+        &self,
+        coords: &[Point3<f64>],
+        to_check_ids: &HashSet<usize>,
+        reach: f64,
+    ) -> (Point3<f64>, [i64; 3]) {
+        let (lower, upper) = to_check_ids.iter().map(|&id| coords[id].coords).fold(
+            (
+                Vector3::repeat(f64::INFINITY),
+                Vector3::repeat(f64::NEG_INFINITY),
+            ),
+            |(lower, upper), p| (lower.inf(&p), upper.sup(&p)),
+        );
+        let lower = lower.add_scalar(-reach);
+        let upper = upper.add_scalar(reach);
+        let counts = (upper - lower).map(|length| (length / self.spacing).ceil() as i64 + 1);
+        (Point3::from(lower), [counts.x, counts.y, counts.z])
+    }
+}
+
+/// Group the grid points into clusters of neighbouring points (sharing a face, an edge or
+/// a corner) bonded to the same atoms. Returns the ids of the points in each cluster.
+fn clusters(grid_points: &[GridPoint]) -> Vec<Vec<usize>> {
+    let lookup: HashMap<[i64; 3], usize> = grid_points
+        .iter()
+        .enumerate()
+        .map(|(id, point)| (point.index, id))
+        .collect();
+    let same_atoms = |a: &GridPoint, b: &GridPoint| {
+        a.bonds.len() == b.bonds.len()
+            && a.bonds
+                .iter()
+                .zip(b.bonds.iter())
+                .all(|(bond_a, bond_b)| bond_a.0 == bond_b.0)
+    };
+    let mut visited = vec![false; grid_points.len()];
+    let mut clusters = Vec::new();
+    (0..grid_points.len()).for_each(|start| {
+        if visited[start] {
+            return;
+        }
+        visited[start] = true;
+        let mut cluster = vec![start];
+        let mut queue = VecDeque::from([start]);
+        while let Some(current) = queue.pop_front() {
+            let [i, j, k] = grid_points[current].index;
+            for (di, dj, dk) in itertools::iproduct!(-1..=1, -1..=1, -1..=1) {
+                if let Some(&next) = lookup.get(&[i + di, j + dj, k + dk]) {
+                    if !visited[next] && same_atoms(&grid_points[current], &grid_points[next]) {
+                        visited[next] = true;
+                        cluster.push(next);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        clusters.push(cluster);
+    });
+    clusters
+}
+
+#[cfg(test)]
+mod test {
+    use chemrust_core::data::{BasicLatticeModel, LatticeVectors};
+    use nalgebra::{distance, Matrix3, Point3};
+
+    use crate::analyzer::{
+        mounting_analyze::MountingChecker, search_strategy::test::carbon_triangle,
+    };
+
+    use super::GridSearch;
+
+    #[test]
+    fn cross_check_with_intersections() {
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal_element(15.0));
+        // The triangle in the middle of the cell
+        let atoms = carbon_triangle(Point3::new(7.0, 7.0, 7.5));
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        let mount_checker = MountingChecker::new_builder().with_bondlength(1.5).build();
+        let analytic = mount_checker.mount_search(&model, &atoms);
+        let grid = mount_checker.search_with(&GridSearch::new(0.1), &model, &atoms);
+        // The hollow sites above and below the triangle, within the grid resolution
+        assert_eq!(grid.multi_cn_points().len(), 2);
+        grid.multi_cn_points().iter().for_each(|site| {
+            assert_eq!(site.cn(), 3);
+            assert!(analytic
+                .multi_cn_points()
+                .iter()
+                .any(|exact| distance(&exact.coord(), &site.coord()) < 0.1));
+        });
+        assert!(analytic.multi_cn_points().iter().all(|exact| grid
+            .multi_cn_points()
+            .iter()
+            .any(|site| distance(&exact.coord(), &site.coord()) < 0.1)));
+    }
+}
@@ -0,0 +1,149 @@
+//! Interchangeable ways to search the mounting sites.
+//! A `SearchStrategy` takes the bonding settings of the `MountingChecker`: the target bondlengths
+//! and windows to each host atom, and which host atoms can bond. All strategies report the sites in
+//! a `FinalReport`, so the results of different strategies on the same model can be cross-checked.
+//! - `IntersectSearch`: the analytic sphere/circle/point intersections, see `IntersectChecker`.
+//! - `LocalEnvSearch`: the intersections within the local bonding environments, see `LBEMountingChecker`.
+//! - `GridSearch`: the exhaustive scan on a fine grid, robust for distorted structures.
+use chemrust_core::data::{atom::AtomCollections, Atom, BasicLatticeModel};
+
+use super::{
+    algorithm::{FinalReport, IntersectChecker, LBEMountingChecker, Ready},
+    mounting_analyze::MountingChecker,
+};
+
+mod grid;
+
+pub use grid::GridSearch;
+
+pub trait SearchStrategy {
+    /// Search the sites around `to_check_atoms` in the model, with the bonding settings of `mount_checker`.
+    fn search(
+        &self,
+        mount_checker: &MountingChecker,
+        lattice_model: &BasicLatticeModel,
+        to_check_atoms: &[Atom],
+    ) -> FinalReport;
+}
+
+/// The analytic intersection search over the whole model.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IntersectSearch;
+
+impl SearchStrategy for IntersectSearch {
+    fn search(
+        &self,
+        mount_checker: &MountingChecker,
+        lattice_model: &BasicLatticeModel,
+        to_check_atoms: &[Atom],
+    ) -> FinalReport {
+        let available_atoms: Vec<Atom> = mount_checker.available_atoms(to_check_atoms);
+        let collections: AtomCollections = lattice_model.atoms().into();
+        let to_check_atom_collections: AtomCollections = available_atoms.into();
+        let coords = collections.cartesian_coords().to_vec();
+        let to_check_coords = to_check_atom_collections.cartesian_coords().to_vec();
+        let bondlengths = mount_checker.bondlengths_in(lattice_model);
+        let bondlength_windows: Vec<(f64, f64)> = lattice_model
+            .atoms()
+            .iter()
+            .map(|atom| mount_checker.bondlength_window_to(atom))
+            .collect();
+        let atomic_numbers: Vec<u8> = lattice_model
+            .atoms()
+            .iter()
+            .map(|atom| atom.atomic_number())
+            .collect();
+        IntersectChecker::<Ready>::new(&coords)
+            .set_check_atoms(&to_check_coords)
+            .set_atomic_numbers(&atomic_numbers)
+            .set_bonding_atoms(&mount_checker.bonding_atoms_in(lattice_model))
+            .set_lattice_vectors(lattice_model.lattice_vectors())
+            .set_bondlength_windows(&bondlength_windows)
+            .set_arc_representative(mount_checker.arc_representative())
+            .set_tolerance(mount_checker.tolerance())
+            .start_with_radii(&bondlengths)
+            .check_spheres()
+            .analyze_circle_intersects()
+            .analyze_points()
+            .report()
+            .clone()
+    }
+}
+
+/// The intersection search within the local bonding environments of the atoms,
+/// avoiding the sites on top of the existing bonds.
+/// The atoms are identified by `Atom::index()` in the model.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalEnvSearch;
+
+impl SearchStrategy for LocalEnvSearch {
+    fn search(
+        &self,
+        mount_checker: &MountingChecker,
+        lattice_model: &BasicLatticeModel,
+        to_check_atoms: &[Atom],
+    ) -> FinalReport {
+        let to_check_ids: Vec<usize> = mount_checker
+            .available_atoms(to_check_atoms)
+            .iter()
+            .map(|atom| atom.index())
+            .collect();
+        LBEMountingChecker::new(lattice_model, &mount_checker.bondlengths_in(lattice_model))
+            .with_bonding_atoms(&mount_checker.bonding_atoms_in(lattice_model))
+            .with_tolerance(mount_checker.tolerance())
+            .mount_search(&to_check_ids)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use chemrust_core::data::{Atom, BasicLatticeModel};
+    use nalgebra::Point3;
+
+    use crate::analyzer::mounting_analyze::MountingChecker;
+
+    use super::{GridSearch, IntersectSearch};
+
+    /// An equilateral triangle of carbons with 1.6 Å sides in the `xy` plane, the first atom at `origin`.
+    pub(crate) fn carbon_triangle(origin: Point3<f64>) -> Vec<Atom> {
+        [[0.0, 0.0], [1.6, 0.0], [0.8, 1.6 * 0.75_f64.sqrt()]]
+            .iter()
+            .enumerate()
+            .map(|(i, xy)| {
+                Atom::new_builder()
+                    .with_symbol("C")
+                    .with_atomic_number(6)
+                    .with_index(i)
+                    .with_coord(&Point3::new(origin.x + xy[0], origin.y + xy[1], origin.z))
+                    .ready()
+                    .build()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn isolated_molecule() {
+        // The triangle without lattice vectors
+        let atoms = carbon_triangle(Point3::origin());
+        let model = BasicLatticeModel::new(&None, &atoms);
+        let mount_checker = MountingChecker::new_builder().with_bondlength(1.5).build();
+        // The hollow sites above and below the triangle
+        [
+            mount_checker.search_with(&IntersectSearch, &model, &atoms),
+            mount_checker.search_with(&GridSearch::new(0.1), &model, &atoms),
+        ]
+        .iter()
+        .for_each(|report| {
+            assert!(!report.multi_cn_points().is_empty());
+            assert!(report.multi_cn_points().iter().all(|site| site.cn() == 3));
+            assert!(report
+                .multi_cn_points()
+                .iter()
+                .any(|site| site.coord().z > 0.0));
+            assert!(report
+                .multi_cn_points()
+                .iter()
+                .any(|site| site.coord().z < 0.0));
+        });
+    }
+}
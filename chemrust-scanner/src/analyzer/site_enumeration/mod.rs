@@ -0,0 +1,572 @@
+//! Enumerate the classic adsorption sites of a slab: top, bridge and hollow.
+//! 1. The surface atoms are found by the `SurfaceDetector`, and projected onto the surface plane,
+//!    normal to `a x b` (or `z` without lattice vectors).
+//! 2. A Delaunay triangulation is built over the projected surface atoms and their periodic images
+//!    in the neighbouring cells. Only the sites inside the home cell are kept, so each periodic site
+//!    appears once.
+//! 3. Each surface atom is a top site, each edge a bridge site and each triangle a hollow site.
+//!    - A bridge is dropped if another surface atom is as close to its midpoint as the two atoms
+//!      of the edge, e.g., the diagonal of a square.
+//!    - A hollow is placed at the circumcenter of the triangle, and dropped if the circumcenter is outside.
+//!      The triangles sharing the same circumcenter are merged, e.g., the 4-fold hollow of a square.
+//!    - A 3-fold hollow is `HcpHollow` if an atom of the subsurface layer is right below, otherwise `FccHollow`.
+//! 4. The new atom is placed above the site at the requested height, or at the target bondlength
+//!    to the connecting surface atoms.
+use std::fmt::Display;
+
+use castep_periodic_table::{
+    data::ELEMENT_TABLE,
+    element::{Element, LookupElement},
+};
+use chemrust_core::data::{
+    lattice::{SurfaceDetector, SurfaceSide},
+    Atom, BasicLatticeModel,
+};
+use nalgebra::{Matrix2, Point2, Point3, Vector2, Vector3};
+
+use super::{
+    algorithm::{ideal_bondlength, AtomImage, CoordinationPoint, FinalReport},
+    geometry::DelaunayTriangulation,
+};
+
+/// Tolerance of the lateral positions of the sites and atoms, in angstrom.
+const LATERAL_TOLERANCE: f64 = 1e-3;
+/// Maximum lateral distance of a subsurface atom to be right below a hollow site, in angstrom.
+const SUBSURFACE_TOLERANCE: f64 = 0.5;
+/// Thickness of the subsurface layer below its highest atom, in angstrom.
+const LAYER_THICKNESS: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SurfaceSiteKind {
+    Top,
+    Bridge,
+    /// 3-fold hollow without a subsurface atom below
+    FccHollow,
+    /// 3-fold hollow above a subsurface atom
+    HcpHollow,
+    /// Hollow with other coordination, or without a subsurface layer to tell
+    Hollow,
+}
+
+impl Display for SurfaceSiteKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            SurfaceSiteKind::Top => "top",
+            SurfaceSiteKind::Bridge => "bridge",
+            SurfaceSiteKind::FccHollow => "fcc_hollow",
+            SurfaceSiteKind::HcpHollow => "hcp_hollow",
+            SurfaceSiteKind::Hollow => "hollow",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// Where to put the new atom above the site.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SitePlacement {
+    /// Height above the mean height of the connecting atoms, in angstrom
+    Height(f64),
+    /// Bondlength to every connecting atom, in angstrom
+    Bondlength(f64),
+}
+
+#[derive(Debug, Clone)]
+pub struct SurfaceSite {
+    kind: SurfaceSiteKind,
+    point: CoordinationPoint,
+}
+
+impl SurfaceSite {
+    pub fn kind(&self) -> SurfaceSiteKind {
+        self.kind
+    }
+
+    pub fn point(&self) -> &CoordinationPoint {
+        &self.point
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SurfaceSites {
+    sites: Vec<SurfaceSite>,
+}
+
+impl SurfaceSites {
+    pub fn sites(&self) -> &[SurfaceSite] {
+        self.sites.as_ref()
+    }
+
+    pub fn sites_of_kind(&self, kind: SurfaceSiteKind) -> Vec<&SurfaceSite> {
+        self.sites.iter().filter(|site| site.kind == kind).collect()
+    }
+    /// Number of sites of each kind.
+    pub fn report_sites(&self) -> String {
+        [
+            SurfaceSiteKind::Top,
+            SurfaceSiteKind::Bridge,
+            SurfaceSiteKind::FccHollow,
+            SurfaceSiteKind::HcpHollow,
+            SurfaceSiteKind::Hollow,
+        ]
+        .iter()
+        .map(|kind| format!("{}: {}", kind, self.sites_of_kind(*kind).len()))
+        .collect::<Vec<String>>()
+        .join("\n")
+    }
+    /// The sites as points labelled by their kinds, to export like the sites from the `MountingChecker`.
+    /// The tops and bridges are cut points, and the hollows are multi-coordinated points.
+    pub fn report(&self) -> FinalReport {
+        let (cut_points, multi_cn_points): (Vec<CoordinationPoint>, Vec<CoordinationPoint>) = self
+            .sites
+            .iter()
+            .map(|site| site.point.clone().with_label(&site.kind.to_string()))
+            .partition(|point| point.cn() <= 2);
+        FinalReport::new(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            cut_points,
+            multi_cn_points,
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SurfaceSiteEnumerator {
+    mount_element: Element,
+    /// Without it, the new atom is placed at the `ideal_bondlength` to each host element.
+    placement: Option<SitePlacement>,
+    side: SurfaceSide,
+}
+
+/// A surface atom or its periodic image, projected on the surface plane.
+struct SurfaceVertex {
+    image: AtomImage,
+    lateral: Point2<f64>,
+    height: f64,
+}
+
+/// The frame of one side of the slab: the heights along the outward normal and the
+/// lateral coordinates in an orthonormal in-plane basis.
+struct SideFrame {
+    normal: Vector3<f64>,
+    plane_basis: [Vector3<f64>; 2],
+    /// `a` and `b` in the lateral coordinates, if periodic
+    cell: Option<Matrix2<f64>>,
+    lattice_ab: [Vector3<f64>; 2],
+}
+
+impl SideFrame {
+    fn new(lattice_model: &BasicLatticeModel, sign: f64) -> Self {
+        let (normal, first_axis, lattice_ab) = match lattice_model.lattice_vectors() {
+            Some(lattice) => {
+                let a: Vector3<f64> = lattice.data().column(0).into();
+                let b: Vector3<f64> = lattice.data().column(1).into();
+                (a.cross(&b).normalize() * sign, a.normalize(), [a, b])
+            }
+            None => (
+                Vector3::z() * sign,
+                Vector3::x(),
+                [Vector3::zeros(), Vector3::zeros()],
+            ),
+        };
+        let plane_basis = [first_axis, normal.cross(&first_axis)];
+        let mut frame = Self {
+            normal,
+            plane_basis,
+            cell: None,
+            lattice_ab,
+        };
+        if lattice_model.lattice_vectors().is_some() {
+            let [a, b] = lattice_ab.map(|v| frame.lateral(&Point3::from(v)).coords);
+            frame.cell = Some(Matrix2::from_columns(&[a, b]));
+        }
+        frame
+    }
+    fn lateral(&self, point: &Point3<f64>) -> Point2<f64> {
+        Point2::new(
+            point.coords.dot(&self.plane_basis[0]),
+            point.coords.dot(&self.plane_basis[1]),
+        )
+    }
+    fn height(&self, point: &Point3<f64>) -> f64 {
+        point.coords.dot(&self.normal)
+    }
+    fn point(&self, lateral: &Point2<f64>, height: f64) -> Point3<f64> {
+        Point3::from(
+            self.plane_basis[0] * lateral.x
+                + self.plane_basis[1] * lateral.y
+                + self.normal * height,
+        )
+    }
+    fn frac(&self, lateral: &Vector2<f64>) -> Option<Vector2<f64>> {
+        self.cell.map(|cell| cell.try_inverse().unwrap() * lateral)
+    }
+    /// Whether the lateral position is in the home cell; always true without lattice vectors.
+    fn in_home_cell(&self, lateral: &Point2<f64>) -> bool {
+        match self.frac(&lateral.coords) {
+            Some(frac) => frac.iter().all(|v| (v + 1e-8).floor() == 0.0),
+            None => true,
+        }
+    }
+    /// Lateral distance between two positions, by the minimum image when periodic.
+    fn lateral_distance(&self, from: &Point2<f64>, to: &Point2<f64>) -> f64 {
+        let diff = to - from;
+        match (self.cell, self.frac(&diff)) {
+            (Some(cell), Some(frac)) => {
+                let wrapped = frac.map(|v| v - v.round());
+                (-1..=1)
+                    .flat_map(|i| (-1..=1).map(move |j| Vector2::new(i as f64, j as f64)))
+                    .map(|shift| (cell * (wrapped + shift)).norm())
+                    .fold(f64::INFINITY, f64::min)
+            }
+            _ => diff.norm(),
+        }
+    }
+    fn image_coord(&self, atom: &Atom, image: &AtomImage) -> Point3<f64> {
+        let [i, j, _] = image.offset();
+        atom.cartesian_coord() + self.lattice_ab[0] * i as f64 + self.lattice_ab[1] * j as f64
+    }
+}
+
+impl SurfaceSiteEnumerator {
+    pub fn new_builder() -> SurfaceSiteEnumeratorBuilder {
+        SurfaceSiteEnumeratorBuilder::new()
+    }
+    /// Enumerate the sites on the surface side(s) of the model.
+    pub fn enumerate(&self, lattice_model: &BasicLatticeModel) -> SurfaceSites {
+        let sites = match self.side {
+            SurfaceSide::Top => self.enumerate_side(lattice_model, SurfaceSide::Top),
+            SurfaceSide::Bottom => self.enumerate_side(lattice_model, SurfaceSide::Bottom),
+            SurfaceSide::Both => [SurfaceSide::Top, SurfaceSide::Bottom]
+                .iter()
+                .flat_map(|side| self.enumerate_side(lattice_model, *side))
+                .collect(),
+        };
+        SurfaceSites { sites }
+    }
+    /// The target bondlength between the new atom and the host atom.
+    fn bondlength_to(&self, host_atom: &Atom) -> f64 {
+        match self.placement {
+            Some(SitePlacement::Bondlength(bondlength)) => bondlength,
+            _ => ideal_bondlength(
+                host_atom.atomic_number(),
+                self.mount_element.atomic_number(),
+            ),
+        }
+    }
+    fn enumerate_side(
+        &self,
+        lattice_model: &BasicLatticeModel,
+        side: SurfaceSide,
+    ) -> Vec<SurfaceSite> {
+        let sign = if side == SurfaceSide::Bottom {
+            -1.0
+        } else {
+            1.0
+        };
+        let frame = SideFrame::new(lattice_model, sign);
+        let atoms = lattice_model.atoms();
+        let surface_ids = SurfaceDetector::new(lattice_model).surface_atom_ids(side);
+        let vertices = surface_vertices(&frame, atoms, &surface_ids);
+        let lateral_points: Vec<Point2<f64>> = vertices.iter().map(|v| v.lateral).collect();
+        let triangulation = DelaunayTriangulation::new(&lateral_points);
+        let subsurface_layer = subsurface_layer(&frame, atoms, &surface_ids);
+        let mut candidates: Vec<(SurfaceSiteKind, Point2<f64>, Vec<usize>)> = Vec::new();
+        // Top sites: the surface atoms wrapped into the home cell
+        vertices
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| frame.in_home_cell(&v.lateral))
+            .for_each(|(i, v)| candidates.push((SurfaceSiteKind::Top, v.lateral, vec![i])));
+        // Bridge sites: the edges without other atoms closer to the midpoint
+        triangulation.edges().iter().for_each(|&[i, j]| {
+            let midpoint =
+                Point2::from((lateral_points[i].coords + lateral_points[j].coords) / 2.0);
+            if !frame.in_home_cell(&midpoint) {
+                return;
+            }
+            let half_length = (lateral_points[i] - lateral_points[j]).norm() / 2.0;
+            let is_blocked = lateral_points.iter().enumerate().any(|(k, p)| {
+                k != i && k != j && (p - midpoint).norm() <= half_length + LATERAL_TOLERANCE
+            });
+            if !is_blocked {
+                candidates.push((SurfaceSiteKind::Bridge, midpoint, vec![i, j]))
+            }
+        });
+        // Hollow sites: the circumcenters inside the triangles, merged when shared
+        let mut hollows: Vec<Point2<f64>> = Vec::new();
+        triangulation.triangles().iter().for_each(|triangle| {
+            let (center, radius) = triangulation.circumcircle(triangle);
+            let is_repeated = hollows
+                .iter()
+                .any(|h| (h - center).norm() < LATERAL_TOLERANCE);
+            if is_repeated
+                || !frame.in_home_cell(&center)
+                || !triangulation.contains(triangle, &center, 1e-6)
+            {
+                return;
+            }
+            hollows.push(center);
+            let connecting: Vec<usize> = lateral_points
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| (*p - center).norm() <= radius + LATERAL_TOLERANCE)
+                .map(|(k, _)| k)
+                .collect();
+            let kind = if connecting.len() != 3 || subsurface_layer.is_empty() {
+                SurfaceSiteKind::Hollow
+            } else if subsurface_layer
+                .iter()
+                .any(|below| frame.lateral_distance(below, &center) < SUBSURFACE_TOLERANCE)
+            {
+                SurfaceSiteKind::HcpHollow
+            } else {
+                SurfaceSiteKind::FccHollow
+            };
+            candidates.push((kind, center, connecting))
+        });
+        candidates
+            .into_iter()
+            .filter_map(|(kind, lateral, connecting)| {
+                let connecting: Vec<&SurfaceVertex> =
+                    connecting.iter().map(|&k| &vertices[k]).collect();
+                self.place(&frame, atoms, &lateral, &connecting)
+                    .map(|point| SurfaceSite { kind, point })
+            })
+            .collect()
+    }
+    /// Place the new atom above the lateral position, returns `None` if the bondlength
+    /// is too short to reach the site.
+    fn place(
+        &self,
+        frame: &SideFrame,
+        atoms: &[Atom],
+        lateral: &Point2<f64>,
+        connecting: &[&SurfaceVertex],
+    ) -> Option<CoordinationPoint> {
+        let heights: Option<Vec<f64>> = connecting
+            .iter()
+            .map(|v| match self.placement {
+                Some(SitePlacement::Height(height)) => Some(v.height + height),
+                _ => {
+                    let bondlength = self.bondlength_to(&atoms[v.image.atom_id()]);
+                    let lateral_distance = (v.lateral - lateral).norm();
+                    (bondlength > lateral_distance + 1e-6)
+                        .then(|| v.height + (bondlength.powi(2) - lateral_distance.powi(2)).sqrt())
+                }
+            })
+            .collect();
+        let heights = heights?;
+        let height = heights.iter().sum::<f64>() / heights.len() as f64;
+        let coord = frame.point(lateral, height);
+        let mut bonds: Vec<(AtomImage, f64)> = connecting
+            .iter()
+            .map(|v| {
+                let atom_coord = frame.image_coord(&atoms[v.image.atom_id()], &v.image);
+                (v.image, (coord - atom_coord).norm())
+            })
+            .collect();
+        bonds.sort_by_key(|bond| bond.0);
+        let (images, distances): (Vec<AtomImage>, Vec<f64>) = bonds.into_iter().unzip();
+        let mut point = CoordinationPoint::new(coord, images.clone(), images.len() as u32);
+        point.set_bonds(images, distances);
+        Some(point)
+    }
+}
+
+/// The surface atoms projected on the plane, wrapped into the home cell, together with
+/// the images in the neighbouring cells when periodic.
+fn surface_vertices(
+    frame: &SideFrame,
+    atoms: &[Atom],
+    surface_ids: &[usize],
+) -> Vec<SurfaceVertex> {
+    surface_ids
+        .iter()
+        .flat_map(|&id| {
+            let coord = atoms[id].cartesian_coord();
+            let lateral = frame.lateral(&coord);
+            let height = frame.height(&coord);
+            match (frame.cell, frame.frac(&lateral.coords)) {
+                (Some(cell), Some(frac)) => {
+                    let base = frac.map(|v| -(v + 1e-8).floor() as i32);
+                    (-1..=1)
+                        .flat_map(|i| (-1..=1).map(move |j| [base.x + i, base.y + j]))
+                        .map(|[i, j]| SurfaceVertex {
+                            image: AtomImage::new(id, [i, j, 0]),
+                            lateral: lateral + cell * Vector2::new(i as f64, j as f64),
+                            height,
+                        })
+                        .collect::<Vec<SurfaceVertex>>()
+                }
+                _ => vec![SurfaceVertex {
+                    image: AtomImage::home(id),
+                    lateral,
+                    height,
+                }],
+            }
+        })
+        .collect()
+}
+
+/// Lateral positions of the highest layer of the atoms below the surface.
+fn subsurface_layer(frame: &SideFrame, atoms: &[Atom], surface_ids: &[usize]) -> Vec<Point2<f64>> {
+    let below: Vec<(Point2<f64>, f64)> = atoms
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !surface_ids.contains(i))
+        .map(|(_, atom)| {
+            let coord = atom.cartesian_coord();
+            (frame.lateral(&coord), frame.height(&coord))
+        })
+        .collect();
+    let top = below
+        .iter()
+        .map(|(_, h)| *h)
+        .fold(f64::NEG_INFINITY, f64::max);
+    below
+        .into_iter()
+        .filter(|(_, h)| *h > top - LAYER_THICKNESS)
+        .map(|(p, _)| p)
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct SurfaceSiteEnumeratorBuilder {
+    mount_element: Option<Element>,
+    placement: Option<SitePlacement>,
+    side: SurfaceSide,
+}
+
+impl SurfaceSiteEnumeratorBuilder {
+    pub fn new() -> Self {
+        Self {
+            mount_element: None,
+            placement: None,
+            side: SurfaceSide::Top,
+        }
+    }
+    pub fn with_element(self, element: &Element) -> Self {
+        Self {
+            mount_element: Some(element.clone()),
+            ..self
+        }
+    }
+    /// Place the new atom at the bondlength to every connecting atom.
+    /// Without it, the `ideal_bondlength` of each element pair is used.
+    pub fn with_bondlength(self, bondlength: f64) -> Self {
+        Self {
+            placement: Some(SitePlacement::Bondlength(bondlength)),
+            ..self
+        }
+    }
+    /// Place the new atom at the height above the connecting atoms, overriding the bondlength.
+    pub fn with_height(self, height: f64) -> Self {
+        Self {
+            placement: Some(SitePlacement::Height(height)),
+            ..self
+        }
+    }
+    /// Which side of the slab to enumerate, `SurfaceSide::Top` by default.
+    pub fn with_side(self, side: SurfaceSide) -> Self {
+        Self { side, ..self }
+    }
+    pub fn build(self) -> SurfaceSiteEnumerator {
+        let mount_element = self
+            .mount_element
+            .unwrap_or(ELEMENT_TABLE.get_by_symbol("H").unwrap().clone());
+        SurfaceSiteEnumerator {
+            mount_element,
+            placement: self.placement,
+            side: self.side,
+        }
+    }
+}
+
+impl Default for SurfaceSiteEnumeratorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use castep_periodic_table::{data::ELEMENT_TABLE, element::LookupElement};
+    use chemrust_core::data::{Atom, BasicLatticeModel, LatticeVectors};
+    use nalgebra::{Matrix3, Point3, Vector3};
+
+    use super::{SurfaceSiteEnumerator, SurfaceSiteKind};
+
+    #[test]
+    fn fcc_111_sites() {
+        // Pt(111) 1 x 1 slab of three layers in ABC stacking
+        let a = 2.77;
+        let layer_spacing = a * (2.0_f64 / 3.0).sqrt();
+        let a_vec = Vector3::new(a, 0.0, 0.0);
+        let b_vec = Vector3::new(-a / 2.0, a * 3.0_f64.sqrt() / 2.0, 0.0);
+        let lattice = LatticeVectors::new(Matrix3::from_columns(&[
+            a_vec,
+            b_vec,
+            Vector3::new(0.0, 0.0, 20.0),
+        ]));
+        let atoms: Vec<Atom> = (0..3)
+            .map(|i| {
+                let shift = (a_vec + b_vec * 2.0) * i as f64 / 3.0;
+                Atom::new_builder()
+                    .with_symbol("Pt")
+                    .with_index(i)
+                    .with_coord(&Point3::from(
+                        shift + Vector3::z() * (5.0 + layer_spacing * i as f64),
+                    ))
+                    .ready()
+                    .build()
+            })
+            .collect();
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        let sites = SurfaceSiteEnumerator::new_builder()
+            .with_element(ELEMENT_TABLE.get_by_symbol("O").unwrap())
+            .with_bondlength(2.0)
+            .build()
+            .enumerate(&model);
+        assert_eq!(sites.sites_of_kind(SurfaceSiteKind::Top).len(), 1);
+        assert_eq!(sites.sites_of_kind(SurfaceSiteKind::Bridge).len(), 3);
+        let fcc = sites.sites_of_kind(SurfaceSiteKind::FccHollow);
+        let hcp = sites.sites_of_kind(SurfaceSiteKind::HcpHollow);
+        assert_eq!(fcc.len(), 1);
+        assert_eq!(hcp.len(), 1);
+        sites.sites().iter().for_each(|site| {
+            site.point()
+                .bond_distances()
+                .iter()
+                .for_each(|d| assert!((d - 2.0).abs() < 1e-6));
+        });
+        // The hcp hollow is above the second layer atom, the fcc hollow above the first layer atom
+        let lateral_distance = |site_coord: Point3<f64>, atom_id: usize| {
+            let diff = site_coord - atoms[atom_id].cartesian_coord();
+            Vector3::new(diff.x, diff.y, 0.0).norm()
+        };
+        let hcp_to_second = (-1..=1)
+            .flat_map(|i| (-1..=1).map(move |j| a_vec * i as f64 + b_vec * j as f64))
+            .map(|shift| lateral_distance(hcp[0].point().coord() - shift, 1))
+            .fold(f64::INFINITY, f64::min);
+        assert!(hcp_to_second < 1e-6);
+        let report = sites.report();
+        assert_eq!(report.cut_points().len(), 4);
+        assert_eq!(report.multi_cn_points().len(), 2);
+        // The fcc and hcp hollows over the same three atoms, and the three bridges between the
+        // images of the same atom, are exported under distinct names
+        let names: Vec<String> = report
+            .adsorption_sites(&model)
+            .into_iter()
+            .map(|(name, _, _)| name)
+            .collect();
+        assert_eq!(names.iter().collect::<HashSet<_>>().len(), 6);
+        assert!(names.contains(&"top_atom_2".to_string()));
+        assert!(names.contains(&"bridge_atom_2_2_site_0".to_string()));
+        assert!(names.contains(&"fcc_hollow_atom_2_2_2".to_string()));
+        assert!(names.contains(&"hcp_hollow_atom_2_2_2".to_string()));
+    }
+}
@@ -0,0 +1,349 @@
+//! Cheap pre-screening of the found sites, to pick the candidates worth a DFT calculation.
+//! Each site is scored in eV by an empirical potential of the new atom at the anchor position:
+//! - The Lennard-Jones energy to the host atoms and their periodic images within `cutoff`,
+//!   by the UFF parameters with the geometric mixing rules: `x_ij = sqrt(x_i x_j)`, `D_ij = sqrt(D_i D_j)`,
+//!   `E = D_ij [(x_ij / r)^12 - 2 (x_ij / r)^6]`.
+//!   The connecting atoms of the site are bonded, not van der Waals contacts, so they are excluded.
+//! - A coordination heuristic: `coordination_bonus` is subtracted per connecting atom, favouring
+//!   the higher coordinated sites.
+//!
+//! A lower score is a better site.
+use std::collections::HashMap;
+
+use castep_periodic_table::element::Element;
+use chemrust_core::data::BasicLatticeModel;
+use nalgebra::{distance, Point3};
+
+use super::{
+    adsorbate::AdsorptionSite,
+    algorithm::{
+        AtomImage, BondingArc, BondingCircle, BondingSphere, CoordinationPoint, FinalReport,
+        PeriodicImages,
+    },
+};
+
+mod uff;
+
+pub(crate) use uff::{uff_lj_parameters, KCAL_PER_MOL_TO_EV};
+
+/// Score the sites by the UFF Lennard-Jones energy and the coordination.
+#[derive(Debug, Clone)]
+pub struct SiteScorer {
+    new_element: Element,
+    /// Range of the Lennard-Jones interactions, in angstrom
+    cutoff: f64,
+    /// Energy subtracted per connecting atom of the site, in eV
+    coordination_bonus: f64,
+    /// `(x, D)` replacing the UFF parameters of the elements, by element symbol
+    lj_parameters: HashMap<String, (f64, f64)>,
+}
+
+impl SiteScorer {
+    /// Score the sites for the new element, with 8 Å cutoff and 0.1 eV coordination bonus per bond.
+    pub fn new(new_element: &Element) -> Self {
+        Self {
+            new_element: new_element.clone(),
+            cutoff: 8.0,
+            coordination_bonus: 0.1,
+            lj_parameters: HashMap::new(),
+        }
+    }
+    pub fn with_cutoff(self, cutoff: f64) -> Self {
+        Self { cutoff, ..self }
+    }
+    pub fn with_coordination_bonus(self, coordination_bonus: f64) -> Self {
+        Self {
+            coordination_bonus,
+            ..self
+        }
+    }
+    /// Use the van der Waals distance `x` in angstrom and the well depth `d` in kcal/mol
+    /// for the element, instead of the bundled UFF parameters.
+    pub fn with_lj_parameters(self, symbol: &str, x: f64, d: f64) -> Self {
+        let mut lj_parameters = self.lj_parameters;
+        lj_parameters.insert(symbol.to_string(), (x, d));
+        Self {
+            lj_parameters,
+            ..self
+        }
+    }
+
+    pub fn cutoff(&self) -> f64 {
+        self.cutoff
+    }
+
+    pub fn coordination_bonus(&self) -> f64 {
+        self.coordination_bonus
+    }
+    /// The `(x, D)` of the element, the custom one first.
+    /// # Panics
+    /// Panics if the element has no UFF parameters and no custom ones are given.
+    fn parameters_of(&self, symbol: &str, atomic_number: u8) -> (f64, f64) {
+        self.lj_parameters
+            .get(symbol)
+            .copied()
+            .or_else(|| uff_lj_parameters(atomic_number))
+            .unwrap_or_else(|| panic!("No Lennard-Jones parameters of {symbol}"))
+    }
+    /// Score all the sites of the report, see the module documentation.
+    pub fn rank(
+        &self,
+        final_report: &FinalReport,
+        lattice_model: &BasicLatticeModel,
+    ) -> RankedReport {
+        let coords: Vec<Point3<f64>> = lattice_model
+            .atoms()
+            .iter()
+            .map(|atom| atom.cartesian_coord())
+            .collect();
+        let images = PeriodicImages::new(&coords, lattice_model.lattice_vectors(), self.cutoff);
+        let coords_kdtree = images.kdtree();
+        let new_symbol = self.new_element.symbol();
+        let (x_new, d_new) = self.parameters_of(&new_symbol, self.new_element.atomic_number());
+        // Mixed parameters to each host atom, in angstrom and eV
+        let pair_parameters: Vec<(f64, f64)> = lattice_model
+            .atoms()
+            .iter()
+            .map(|atom| {
+                let (x, d) = self.parameters_of(atom.symbol(), atom.atomic_number());
+                ((x * x_new).sqrt(), (d * d_new).sqrt() * KCAL_PER_MOL_TO_EV)
+            })
+            .collect();
+        let score = |position: Point3<f64>, connecting_atoms: &[AtomImage]| -> f64 {
+            let (wrapped, shift) = images.wrap(&position);
+            let back_shift = [-shift[0], -shift[1], -shift[2]];
+            let bonded: Vec<AtomImage> = connecting_atoms
+                .iter()
+                .map(|image| image.translate(back_shift))
+                .collect();
+            let lj_energy: f64 = coords_kdtree
+                .within_radius(&wrapped, self.cutoff)
+                .into_iter()
+                .map(|(coord, image_id)| (images.images()[*image_id], distance(&wrapped, coord)))
+                .filter(|(image, _)| !bonded.contains(image))
+                .map(|(image, r)| {
+                    let (x, d) = pair_parameters[image.atom_id()];
+                    let ratio6 = (x / r).powi(6);
+                    d * (ratio6 * ratio6 - 2.0 * ratio6)
+                })
+                .sum();
+            lj_energy - self.coordination_bonus * connecting_atoms.len() as f64
+        };
+        let score_points = |points: &[CoordinationPoint]| {
+            scored(points, |p| score(p.anchor_position(), p.connecting_atoms()))
+        };
+        RankedReport {
+            sphere_sites: scored(final_report.sphere_sites(), |s| {
+                score(
+                    s.anchor_position(),
+                    &[AtomImage::home(s.locating_atom_id())],
+                )
+            }),
+            circles: scored(final_report.circles(), |c| {
+                score(c.anchor_position(), &c.connecting_atoms())
+            }),
+            arcs: scored(final_report.arcs(), |a| {
+                score(a.anchor_position(), &a.connecting_atoms())
+            }),
+            cut_points: score_points(final_report.cut_points()),
+            multi_cn_points: score_points(final_report.multi_cn_points()),
+        }
+    }
+}
+
+/// A site with its score in eV.
+#[derive(Debug, Clone)]
+pub struct ScoredSite<T> {
+    site: T,
+    score: f64,
+}
+
+impl<T> ScoredSite<T> {
+    pub fn site(&self) -> &T {
+        &self.site
+    }
+
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+}
+
+/// The `FinalReport` with the sites of each kind sorted by their scores, the lowest first.
+#[derive(Debug, Clone)]
+pub struct RankedReport {
+    sphere_sites: Vec<ScoredSite<BondingSphere>>,
+    circles: Vec<ScoredSite<BondingCircle>>,
+    arcs: Vec<ScoredSite<BondingArc>>,
+    cut_points: Vec<ScoredSite<CoordinationPoint>>,
+    multi_cn_points: Vec<ScoredSite<CoordinationPoint>>,
+}
+
+impl RankedReport {
+    pub fn sphere_sites(&self) -> &[ScoredSite<BondingSphere>] {
+        &self.sphere_sites
+    }
+
+    pub fn circles(&self) -> &[ScoredSite<BondingCircle>] {
+        &self.circles
+    }
+
+    pub fn arcs(&self) -> &[ScoredSite<BondingArc>] {
+        &self.arcs
+    }
+
+    pub fn cut_points(&self) -> &[ScoredSite<CoordinationPoint>] {
+        &self.cut_points
+    }
+
+    pub fn multi_cn_points(&self) -> &[ScoredSite<CoordinationPoint>] {
+        &self.multi_cn_points
+    }
+    /// The scores of all sites, the lowest first.
+    pub fn scores(&self) -> Vec<f64> {
+        let mut scores: Vec<f64> = [
+            scores_of(&self.sphere_sites),
+            scores_of(&self.circles),
+            scores_of(&self.arcs),
+            scores_of(&self.cut_points),
+            scores_of(&self.multi_cn_points),
+        ]
+        .concat();
+        scores.sort_by(f64::total_cmp);
+        scores
+    }
+    /// A `FinalReport` with the `n` best sites of all kinds, for the export.
+    /// The sites tied with the `n`-th score are all kept.
+    pub fn top(&self, n: usize) -> FinalReport {
+        self.select(Some(n), None)
+    }
+    /// A `FinalReport` with the sites scored below `threshold` eV, for the export.
+    pub fn below(&self, threshold: f64) -> FinalReport {
+        self.select(None, Some(threshold))
+    }
+    /// A `FinalReport` with the `top_n` best sites that are also scored below `max_score` eV.
+    /// All the sites are kept if neither limit is given.
+    pub fn select(&self, top_n: Option<usize>, max_score: Option<f64>) -> FinalReport {
+        let nth_score = match top_n {
+            Some(0) => f64::NEG_INFINITY,
+            Some(n) => self.scores().get(n - 1).copied().unwrap_or(f64::INFINITY),
+            None => f64::INFINITY,
+        };
+        let max_score = max_score.unwrap_or(f64::INFINITY);
+        self.filtered(|score| score <= nth_score && score < max_score)
+    }
+    fn filtered<F: Fn(f64) -> bool + Copy>(&self, keep: F) -> FinalReport {
+        FinalReport::new(
+            sites_of(&self.sphere_sites, keep),
+            sites_of(&self.circles, keep),
+            sites_of(&self.arcs, keep),
+            sites_of(&self.cut_points, keep),
+            sites_of(&self.multi_cn_points, keep),
+        )
+    }
+
+    pub fn report_scores(&self) -> String {
+        let summary = |name: &str, scores: Vec<f64>| {
+            let texts: Vec<String> = scores.iter().map(|s| format!("{s:.4}")).collect();
+            format!("{name} scores (eV): [{}]", texts.join(", "))
+        };
+        [
+            summary("Spheres", scores_of(&self.sphere_sites)),
+            summary("Circles", scores_of(&self.circles)),
+            summary("Arcs", scores_of(&self.arcs)),
+            summary("Cut points", scores_of(&self.cut_points)),
+            summary("Multi points", scores_of(&self.multi_cn_points)),
+        ]
+        .join("\n")
+    }
+}
+
+impl FinalReport {
+    /// Score and sort the sites by the default `SiteScorer` of the new element.
+    pub fn rank_by_potential(
+        &self,
+        lattice_model: &BasicLatticeModel,
+        new_element: &Element,
+    ) -> RankedReport {
+        SiteScorer::new(new_element).rank(self, lattice_model)
+    }
+}
+
+/// Score the sites and sort them, the lowest first. The order of the equal scores is kept.
+fn scored<T: Clone, F: Fn(&T) -> f64>(sites: &[T], score: F) -> Vec<ScoredSite<T>> {
+    let mut scored: Vec<ScoredSite<T>> = sites
+        .iter()
+        .map(|site| ScoredSite {
+            site: site.clone(),
+            score: score(site),
+        })
+        .collect();
+    scored.sort_by(|a, b| a.score.total_cmp(&b.score));
+    scored
+}
+
+fn scores_of<T>(sites: &[ScoredSite<T>]) -> Vec<f64> {
+    sites.iter().map(|s| s.score).collect()
+}
+
+fn sites_of<T: Clone, F: Fn(f64) -> bool>(sites: &[ScoredSite<T>], keep: F) -> Vec<T> {
+    sites
+        .iter()
+        .filter(|s| keep(s.score))
+        .map(|s| s.site.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use castep_periodic_table::{data::ELEMENT_TABLE, element::LookupElement};
+    use chemrust_core::data::{Atom, BasicLatticeModel, LatticeVectors};
+    use nalgebra::{Matrix3, Point3};
+
+    use crate::analyzer::algorithm::{AtomImage, CoordinationPoint, FinalReport};
+
+    use super::SiteScorer;
+
+    #[test]
+    fn crowded_site_ranks_last() {
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal_element(12.0));
+        let atoms: Vec<Atom> = [[4.0, 6.0, 6.0], [8.0, 6.0, 6.0], [6.0, 6.0, 8.2]]
+            .iter()
+            .enumerate()
+            .map(|(i, xyz)| {
+                Atom::new_builder()
+                    .with_symbol("C")
+                    .with_atomic_number(6)
+                    .with_index(i)
+                    .with_coord(&Point3::from(*xyz))
+                    .ready()
+                    .build()
+            })
+            .collect();
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        // Bonded to atoms 0 and 1, but only 2.2 Å below atom 2
+        let crowded = CoordinationPoint::new(
+            Point3::new(6.0, 6.0, 6.0),
+            vec![AtomImage::home(0), AtomImage::home(1)],
+            2,
+        );
+        // Bonded to atom 0, away from the others
+        let open = CoordinationPoint::new(Point3::new(4.0, 6.0, 4.0), vec![AtomImage::home(0)], 1);
+        let report = FinalReport::new(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![crowded, open],
+            Vec::new(),
+        );
+        let pt = ELEMENT_TABLE.get_by_symbol("Pt").unwrap();
+        let ranked = SiteScorer::new(pt).rank(&report, &model);
+        assert!(ranked.cut_points()[0].score() < ranked.cut_points()[1].score());
+        assert_eq!(ranked.cut_points()[0].site().cn(), 1);
+        let best = ranked.top(1);
+        assert_eq!(best.cut_points().len(), 1);
+        assert_eq!(best.cut_points()[0].cn(), 1);
+        let threshold = ranked.cut_points()[1].score();
+        assert_eq!(ranked.below(threshold).cut_points().len(), 1);
+        assert_eq!(ranked.top(5).cut_points().len(), 2);
+    }
+}
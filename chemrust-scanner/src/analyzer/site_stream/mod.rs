@@ -0,0 +1,340 @@
+//! Stream the mounting sites batch by batch, instead of materializing the whole `FinalReport`.
+//! The to-check atoms are searched in batches of `batch_size` by the `SearchStrategy`, and the sites
+//! of each batch are yielded once the batch is done. Only the stages of the current batch are held,
+//! so the caller can stop early, filter the sites on the fly, or export them one by one.
+//!
+//! Whether a circle is cut by other circles depends on the circles around it, so each batch is
+//! searched together with the to-check atoms within twice the reach of the bonds, including the
+//! periodic images. A site found by several batches is yielded only by the batch of its first
+//! to-check atom, in the order of `to_check_atoms`, so the copies are dropped without remembering
+//! the yielded sites.
+//!
+//! The memory is bounded by the batch, not the time: each batch runs the `SearchStrategy` over the
+//! whole model, so the periodic images and the kd-trees of all atoms are built again for every batch,
+//! i.e., `N / batch_size` times for `N` to-check atoms, and the halo atoms are searched again by
+//! the neighbouring batches. Streaming trades this repeated setup for the memory of the full report;
+//! raise the batch size when the setup dominates.
+use std::collections::{BTreeSet, HashMap};
+
+use chemrust_core::data::{Atom, BasicLatticeModel};
+use kd_tree::KdMap;
+use nalgebra::{Point3, UnitVector3};
+
+use super::{
+    adsorbate::AdsorptionSite,
+    algorithm::{
+        BondingArc, BondingCircle, BondingSphere, CoordinationPoint, FinalReport, PeriodicImages,
+    },
+    mounting_analyze::MountingChecker,
+    search_strategy::SearchStrategy,
+};
+
+/// A site of any kind in the `FinalReport`.
+#[derive(Debug, Clone)]
+pub enum FoundSite {
+    Sphere(BondingSphere),
+    Circle(BondingCircle),
+    Arc(BondingArc),
+    /// Point bonding to at most two atoms
+    CutPoint(CoordinationPoint),
+    /// Point bonding to three or more atoms
+    MultiCnPoint(CoordinationPoint),
+}
+
+impl FoundSite {
+    /// Indices of the bonding atoms in the model, regardless of the periodic images.
+    pub fn connecting_atom_ids(&self) -> Vec<usize> {
+        match self {
+            FoundSite::Sphere(sphere) => vec![sphere.locating_atom_id()],
+            FoundSite::Circle(circle) => circle
+                .connecting_atoms()
+                .iter()
+                .map(|image| image.atom_id())
+                .collect(),
+            FoundSite::Arc(arc) => arc
+                .connecting_atoms()
+                .iter()
+                .map(|image| image.atom_id())
+                .collect(),
+            FoundSite::CutPoint(point) | FoundSite::MultiCnPoint(point) => {
+                point.connecting_atom_ids()
+            }
+        }
+    }
+}
+
+impl AdsorptionSite for FoundSite {
+    fn anchor_position(&self) -> Point3<f64> {
+        match self {
+            FoundSite::Sphere(sphere) => sphere.anchor_position(),
+            FoundSite::Circle(circle) => circle.anchor_position(),
+            FoundSite::Arc(arc) => arc.anchor_position(),
+            FoundSite::CutPoint(point) | FoundSite::MultiCnPoint(point) => point.anchor_position(),
+        }
+    }
+    fn outward_normal(&self, lattice_model: &BasicLatticeModel) -> UnitVector3<f64> {
+        match self {
+            FoundSite::Sphere(sphere) => sphere.outward_normal(lattice_model),
+            FoundSite::Circle(circle) => circle.outward_normal(lattice_model),
+            FoundSite::Arc(arc) => arc.outward_normal(lattice_model),
+            FoundSite::CutPoint(point) | FoundSite::MultiCnPoint(point) => {
+                point.outward_normal(lattice_model)
+            }
+        }
+    }
+}
+
+impl FinalReport {
+    /// The sites of all kinds, in the order of spheres, circles, arcs, cut points and multi points.
+    pub fn into_sites(self) -> impl Iterator<Item = FoundSite> {
+        self.sphere_sites
+            .into_iter()
+            .map(FoundSite::Sphere)
+            .chain(self.circles.into_iter().map(FoundSite::Circle))
+            .chain(self.arcs.into_iter().map(FoundSite::Arc))
+            .chain(self.cut_points.into_iter().map(FoundSite::CutPoint))
+            .chain(
+                self.multi_cn_points
+                    .into_iter()
+                    .map(FoundSite::MultiCnPoint),
+            )
+    }
+}
+
+impl Extend<FoundSite> for FinalReport {
+    fn extend<I: IntoIterator<Item = FoundSite>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|site| match site {
+            FoundSite::Sphere(sphere) => self.sphere_sites.push(sphere),
+            FoundSite::Circle(circle) => self.circles.push(circle),
+            FoundSite::Arc(arc) => self.arcs.push(arc),
+            FoundSite::CutPoint(point) => self.cut_points.push(point),
+            FoundSite::MultiCnPoint(point) => self.multi_cn_points.push(point),
+        })
+    }
+}
+
+impl FromIterator<FoundSite> for FinalReport {
+    fn from_iter<I: IntoIterator<Item = FoundSite>>(iter: I) -> Self {
+        let mut report =
+            FinalReport::new(Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        report.extend(iter);
+        report
+    }
+}
+
+/// Iterator over the sites around the to-check atoms, searched batch by batch.
+/// Each batch is a full `SearchStrategy::search` on the whole model, see the module docs for the cost.
+pub struct SiteStream<'a> {
+    mount_checker: &'a MountingChecker,
+    strategy: &'a dyn SearchStrategy,
+    lattice_model: &'a BasicLatticeModel,
+    /// The to-check atoms that can bond, in the given order
+    to_check_atoms: Vec<Atom>,
+    /// Position in `to_check_atoms`, by atom index in the model
+    positions: HashMap<usize, usize>,
+    /// Images of `to_check_atoms` within `halo` of the cell
+    images: PeriodicImages,
+    images_kdtree: KdMap<Point3<f64>, usize>,
+    /// Distance within which the to-check atoms join the search of a batch
+    halo: f64,
+    batch_size: usize,
+    next_batch: usize,
+    pending: std::vec::IntoIter<FoundSite>,
+}
+
+impl<'a> SiteStream<'a> {
+    /// Search 16 to-check atoms per batch by default.
+    /// The atoms that are not bonding partners are skipped from `to_check_atoms`.
+    pub fn new(
+        mount_checker: &'a MountingChecker,
+        strategy: &'a dyn SearchStrategy,
+        lattice_model: &'a BasicLatticeModel,
+        to_check_atoms: &[Atom],
+    ) -> Self {
+        let to_check_atoms = mount_checker.available_atoms(to_check_atoms);
+        let positions = to_check_atoms
+            .iter()
+            .enumerate()
+            .map(|(position, atom)| (atom.index(), position))
+            .collect();
+        let reach = lattice_model
+            .atoms()
+            .iter()
+            .map(|atom| mount_checker.bondlength_window_to(atom).1)
+            .fold(0.0, f64::max);
+        let halo = 2.0 * reach + mount_checker.tolerance().distance();
+        let coords: Vec<Point3<f64>> = to_check_atoms
+            .iter()
+            .map(|atom| atom.cartesian_coord())
+            .collect();
+        let images = PeriodicImages::new(&coords, lattice_model.lattice_vectors(), halo);
+        let images_kdtree = images.kdtree();
+        Self {
+            mount_checker,
+            strategy,
+            lattice_model,
+            to_check_atoms,
+            positions,
+            images,
+            images_kdtree,
+            halo,
+            batch_size: 16,
+            next_batch: 0,
+            pending: Vec::new().into_iter(),
+        }
+    }
+    /// Number of to-check atoms yielding their sites at once. Larger batches run fewer full searches
+    /// and repeat less work on the atoms shared across the batches, at the cost of more memory.
+    /// # Panics
+    /// Panics if `batch_size` is zero.
+    pub fn with_batch_size(self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "The batch size must be positive");
+        Self { batch_size, ..self }
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+    /// The batch that yields the site: the batch of its first connecting to-check atom.
+    fn owner_batch(&self, site: &FoundSite) -> Option<usize> {
+        site.connecting_atom_ids()
+            .iter()
+            .filter_map(|atom_id| self.positions.get(atom_id))
+            .min()
+            .map(|position| position / self.batch_size)
+    }
+    /// Search the next batch, `None` if all batches are done.
+    fn search_next_batch(&mut self) -> Option<Vec<FoundSite>> {
+        let start = self.next_batch * self.batch_size;
+        if start >= self.to_check_atoms.len() {
+            return None;
+        }
+        let end = (start + self.batch_size).min(self.to_check_atoms.len());
+        let neighbours: BTreeSet<usize> = (start..end)
+            .flat_map(|position| {
+                self.images_kdtree
+                    .within_radius(&self.images.coords()[position], self.halo)
+                    .into_iter()
+                    .map(|(_, image_id)| self.images.images()[*image_id].atom_id())
+            })
+            .collect();
+        let batch_atoms: Vec<Atom> = neighbours
+            .into_iter()
+            .map(|position| self.to_check_atoms[position].clone())
+            .collect();
+        let report =
+            self.mount_checker
+                .search_with(self.strategy, self.lattice_model, &batch_atoms);
+        let this_batch = self.next_batch;
+        self.next_batch += 1;
+        Some(
+            report
+                .into_sites()
+                .filter(|site| self.owner_batch(site) == Some(this_batch))
+                .collect(),
+        )
+    }
+}
+
+impl<'a> Iterator for SiteStream<'a> {
+    type Item = FoundSite;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(site) = self.pending.next() {
+                return Some(site);
+            }
+            self.pending = self.search_next_batch()?.into_iter();
+        }
+    }
+}
+
+impl MountingChecker {
+    /// Stream the sites around `to_check_atoms` by the given strategy, see `SiteStream`.
+    /// Collecting the whole stream into a `FinalReport` gives the same sites as `search_with`.
+    pub fn site_stream<'a>(
+        &'a self,
+        strategy: &'a dyn SearchStrategy,
+        lattice_model: &'a BasicLatticeModel,
+        to_check_atoms: &[Atom],
+    ) -> SiteStream<'a> {
+        SiteStream::new(self, strategy, lattice_model, to_check_atoms)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::read_to_string;
+
+    use castep_periodic_table::{data::ELEMENT_TABLE, element::LookupElement};
+    use chemrust_parser::CellParser;
+
+    use crate::analyzer::{
+        adsorbate::AdsorptionSite, algorithm::FinalReport, mounting_analyze::MountingChecker,
+        search_strategy::IntersectSearch,
+    };
+
+    use super::FoundSite;
+
+    #[test]
+    fn stream_matches_search() {
+        let cell = read_to_string("../chemrust-parser/SAC_GDY_V.cell").unwrap();
+        let lattice = CellParser::new(&cell)
+            .to_lattice_cart()
+            .to_positions()
+            .build_lattice();
+        let mount_checker = MountingChecker::new_builder()
+            .with_element(ELEMENT_TABLE.get_by_symbol("Co").unwrap())
+            .with_bondlength(1.41)
+            .build();
+        let report = mount_checker.mount_search(&lattice, lattice.atoms());
+        let streamed: FinalReport = mount_checker
+            .site_stream(&IntersectSearch, &lattice, lattice.atoms())
+            .with_batch_size(5)
+            .collect();
+        // The same sites of each kind at the same positions, none duplicated or dropped
+        let sorted_sites = |report: FinalReport| -> Vec<(usize, [f64; 3])> {
+            let mut sites: Vec<(usize, [f64; 3])> = report
+                .into_sites()
+                .map(|site| {
+                    let kind = match site {
+                        FoundSite::Sphere(_) => 0,
+                        FoundSite::Circle(_) => 1,
+                        FoundSite::Arc(_) => 2,
+                        FoundSite::CutPoint(_) => 3,
+                        FoundSite::MultiCnPoint(_) => 4,
+                    };
+                    (kind, site.anchor_position().into())
+                })
+                .collect();
+            sites.sort_by(|a, b| {
+                a.0.cmp(&b.0).then_with(|| {
+                    a.1.iter()
+                        .zip(b.1.iter())
+                        .map(|(x, y)| x.total_cmp(y))
+                        .find(|ordering| ordering.is_ne())
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+            });
+            sites
+        };
+        let expected = sorted_sites(report);
+        let found = sorted_sites(streamed);
+        assert!(!expected.is_empty());
+        assert_eq!(found.len(), expected.len());
+        found.iter().zip(expected.iter()).for_each(
+            |((kind, position), (expected_kind, expected_position))| {
+                assert_eq!(kind, expected_kind);
+                assert!(position
+                    .iter()
+                    .zip(expected_position.iter())
+                    .all(|(x, y)| (x - y).abs() < 1e-6));
+            },
+        );
+        // Stop at the first multi point without searching the rest
+        let first = mount_checker
+            .site_stream(&IntersectSearch, &lattice, lattice.atoms())
+            .find(|site| matches!(site, FoundSite::MultiCnPoint(_)));
+        assert!(first.is_some());
+    }
+}
@@ -0,0 +1,256 @@
+//! Detect the space-group operations of the host structure, to collapse the symmetry-equivalent
+//! mounting sites in the `FinalReport`.
+//! The operations are searched as follows:
+//! 1. The lattice point group: integer matrices `W` with entries in `{-1, 0, 1}` which preserve
+//!    the metric tensor `G = L^T * L`, i.e., `W^T * G * W = G`.
+//! 2. For each rotation, the candidate translations are taken from the element with the fewest atoms,
+//!    mapping one reference atom to each atom of the same element.
+//! 3. An operation is accepted when every atom is mapped onto an atom of the same element,
+//!    modulo the lattice translations.
+//!
+//! The operations are represented as affine `Matrix4<f64>` in fractional coordinates,
+//! the same as `SYMMETRY_OPS` written in the `.cell`.
+use std::collections::HashMap;
+
+use chemrust_core::data::BasicLatticeModel;
+use itertools::Itertools;
+use nalgebra::{Matrix3, Matrix4, Point3, Vector3};
+
+mod site_classes;
+
+pub use site_classes::{SiteClass, SymmetryReducedReport};
+
+#[derive(Debug, Clone)]
+pub struct SymmetryFinder<'a> {
+    lattice_model: &'a BasicLatticeModel,
+    /// Tolerance of the atom positions, in angstrom.
+    tolerance: f64,
+}
+
+impl<'a> SymmetryFinder<'a> {
+    pub fn new(lattice_model: &'a BasicLatticeModel) -> Self {
+        Self {
+            lattice_model,
+            tolerance: 0.01,
+        }
+    }
+    pub fn with_tolerance(self, tolerance: f64) -> Self {
+        Self { tolerance, ..self }
+    }
+
+    pub fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+    /// Returns the symmetry operations in fractional coordinates.
+    /// Only the identity is returned when the model has no lattice vectors.
+    pub fn find_operations(&self) -> Vec<Matrix4<f64>> {
+        let lattice = match self.lattice_model.lattice_vectors() {
+            Some(lat) => lat,
+            None => return vec![Matrix4::identity()],
+        };
+        let lattice_matrix = *lattice.data();
+        let to_frac = lattice.mat_cart_to_frac();
+        let atoms = self.lattice_model.atoms();
+        if atoms.is_empty() {
+            return vec![Matrix4::identity()];
+        }
+        let fracs: Vec<Point3<f64>> = atoms
+            .iter()
+            .map(|atom| to_frac * atom.cartesian_coord())
+            .collect();
+        // Atom ids grouped by element
+        let mut element_groups: HashMap<u8, Vec<usize>> = HashMap::new();
+        atoms.iter().enumerate().for_each(|(i, atom)| {
+            element_groups
+                .entry(atom.atomic_number())
+                .or_default()
+                .push(i)
+        });
+        let (_, ref_group) = element_groups
+            .iter()
+            .min_by_key(|(atomic_number, group)| (group.len(), **atomic_number))
+            .unwrap();
+        let ref_atom = ref_group[0];
+        let mut operations: Vec<Matrix4<f64>> = Vec::new();
+        lattice_rotations(&lattice_matrix)
+            .iter()
+            .for_each(|rotation| {
+                let rotated_ref = rotation * fracs[ref_atom].coords;
+                let mut translations: Vec<Vector3<f64>> = Vec::new();
+                ref_group.iter().for_each(|&target| {
+                    let t = wrap_frac(&(fracs[target].coords - rotated_ref));
+                    let is_repeated = translations.iter().any(|existed| {
+                        frac_distance(&lattice_matrix, &(existed - t)) < self.tolerance
+                    });
+                    if is_repeated {
+                        return;
+                    }
+                    let is_symmetric = atoms.iter().enumerate().all(|(i, atom)| {
+                        let mapped = rotation * fracs[i].coords + t;
+                        element_groups[&atom.atomic_number()].iter().any(|&j| {
+                            frac_distance(&lattice_matrix, &(mapped - fracs[j].coords))
+                                < self.tolerance
+                        })
+                    });
+                    if is_symmetric {
+                        translations.push(t);
+                    }
+                });
+                translations.into_iter().for_each(|t| {
+                    let mut op = rotation.to_homogeneous();
+                    op.fixed_view_mut::<3, 1>(0, 3).copy_from(&t);
+                    operations.push(op)
+                })
+            });
+        operations
+    }
+}
+
+/// Integer matrices with entries in `{-1, 0, 1}` preserving the metric tensor of the lattice.
+fn lattice_rotations(lattice_matrix: &Matrix3<f64>) -> Vec<Matrix3<f64>> {
+    let metric = lattice_matrix.transpose() * lattice_matrix;
+    let scale = metric.abs().max();
+    (0..9)
+        .map(|_| [-1.0_f64, 0.0, 1.0])
+        .multi_cartesian_product()
+        .map(|entries| Matrix3::from_row_slice(&entries))
+        .filter(|w| (w.determinant().abs() - 1.0).abs() < 1e-6)
+        .filter(|w| (w.transpose() * metric * w - metric).abs().max() < 1e-3 * scale)
+        .collect()
+}
+
+/// Wrap fractional coordinates into `[0, 1)`
+fn wrap_frac(frac: &Vector3<f64>) -> Vector3<f64> {
+    frac.map(|v| v - v.floor())
+}
+
+/// Cartesian length of the minimum-image of a fractional displacement.
+pub(crate) fn frac_distance(lattice_matrix: &Matrix3<f64>, frac_diff: &Vector3<f64>) -> f64 {
+    let min_image = frac_diff.map(|v| v - v.round());
+    (lattice_matrix * min_image).norm()
+}
+
+/// Whether the symmetry operation maps point `from` onto `to`, modulo lattice translations.
+pub(crate) fn is_mapped_to(
+    lattice_matrix: &Matrix3<f64>,
+    operation: &Matrix4<f64>,
+    from: &Point3<f64>,
+    to: &Point3<f64>,
+    tolerance: f64,
+) -> bool {
+    let mapped = operation.transform_point(from);
+    frac_distance(lattice_matrix, &(mapped - to)) < tolerance
+}
+
+#[cfg(test)]
+mod test {
+    use chemrust_core::data::{Atom, BasicLatticeModel, LatticeVectors};
+    use nalgebra::{Matrix3, Point3, UnitVector3, Vector3};
+
+    use crate::analyzer::{
+        algorithm::{AtomImage, BondingCircle, CoordinationPoint, FinalReport},
+        geometry::Circle,
+    };
+
+    use super::SymmetryFinder;
+
+    #[test]
+    fn cubic_operations() {
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal_element(3.0));
+        let atom = Atom::new_builder()
+            .with_symbol("Cu")
+            .with_index(0)
+            .with_coord(&Point3::origin())
+            .ready()
+            .build();
+        let model = BasicLatticeModel::new(&Some(lattice), &[atom]);
+        let operations = SymmetryFinder::new(&model).find_operations();
+        assert_eq!(operations.len(), 48);
+    }
+
+    #[test]
+    fn equivalent_sites() {
+        // One atom in a 4 x 4 x 20 cell, with the top, two bridges and the hollow on both sides
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal(&Vector3::new(4.0, 4.0, 20.0)));
+        let atom = Atom::new_builder()
+            .with_symbol("Cu")
+            .with_index(0)
+            .with_coord(&Point3::new(0.0, 0.0, 5.0))
+            .ready()
+            .build();
+        let model = BasicLatticeModel::new(&Some(lattice), &[atom]);
+        let point = |x: f64, y: f64, z: f64, cn: u32| {
+            CoordinationPoint::new(Point3::new(x, y, z), vec![AtomImage::home(0)], cn)
+        };
+        let report = FinalReport::new(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![
+                point(0.0, 0.0, 7.0, 1),
+                point(2.0, 0.0, 7.0, 2),
+                point(0.0, 2.0, 7.0, 2),
+                point(0.0, 0.0, 3.0, 1),
+            ],
+            vec![point(2.0, 2.0, 7.0, 4), point(2.0, 2.0, 3.0, 4)],
+        );
+        let reduced = report.reduce_by_symmetry(&model);
+        assert_eq!(reduced.operations_num(), 16);
+        // The tops above and below by the mirror through the atom, the bridges by the 4-fold rotation
+        let multiplicities: Vec<usize> = reduced
+            .cut_points()
+            .iter()
+            .map(|class| class.multiplicity())
+            .collect();
+        assert_eq!(multiplicities, vec![2, 2]);
+        assert_eq!(reduced.cut_points()[0].representative().cn(), 1);
+        assert_eq!(reduced.cut_points()[1].representative().cn(), 2);
+        assert_eq!(reduced.multi_cn_points().len(), 1);
+        assert_eq!(reduced.multi_cn_points()[0].multiplicity(), 2);
+        assert_eq!(reduced.representatives().cut_points().len(), 2);
+    }
+
+    #[test]
+    fn circle_normals() {
+        // One atom in a 4 x 6 x 20 cell: circles of the same center and radius at the hollow
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal(&Vector3::new(4.0, 6.0, 20.0)));
+        let atom = Atom::new_builder()
+            .with_symbol("Cu")
+            .with_index(0)
+            .with_coord(&Point3::new(0.0, 0.0, 5.0))
+            .ready()
+            .build();
+        let model = BasicLatticeModel::new(&Some(lattice), &[atom]);
+        let circle = |normal: Vector3<f64>| {
+            BondingCircle::new(
+                Circle::new(
+                    Point3::new(2.0, 3.0, 5.0),
+                    1.0,
+                    UnitVector3::new_normalize(normal),
+                ),
+                [AtomImage::home(0), AtomImage::new(0, [1, 1, 0])],
+            )
+        };
+        // The diagonals of the rectangle are mapped by the mirrors, but no operation maps `a` to `b`
+        let report = FinalReport::new(
+            Vec::new(),
+            vec![
+                circle(Vector3::new(4.0, 6.0, 0.0)),
+                circle(Vector3::new(-4.0, 6.0, 0.0)),
+                circle(Vector3::x()),
+                circle(-Vector3::x()),
+                circle(Vector3::y()),
+            ],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+        );
+        let reduced = report.reduce_by_symmetry(&model);
+        let multiplicities: Vec<usize> = reduced
+            .circles()
+            .iter()
+            .map(|class| class.multiplicity())
+            .collect();
+        assert_eq!(multiplicities, vec![2, 2, 1]);
+    }
+}
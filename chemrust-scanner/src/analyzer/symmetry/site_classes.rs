@@ -0,0 +1,241 @@
+use chemrust_core::data::BasicLatticeModel;
+use nalgebra::{Matrix3, Matrix4, Point3, Vector3};
+
+use crate::analyzer::algorithm::{
+    BondingArc, BondingCircle, BondingSphere, CoordinationPoint, FinalReport,
+};
+
+use super::{is_mapped_to, SymmetryFinder};
+
+/// A class of symmetry-equivalent sites, represented by the first found site.
+#[derive(Debug, Clone)]
+pub struct SiteClass<T> {
+    representative: T,
+    multiplicity: usize,
+}
+
+impl<T> SiteClass<T> {
+    pub(crate) fn new(representative: T, multiplicity: usize) -> Self {
+        Self {
+            representative,
+            multiplicity,
+        }
+    }
+
+    pub fn representative(&self) -> &T {
+        &self.representative
+    }
+    /// Number of the found sites in this class.
+    pub fn multiplicity(&self) -> usize {
+        self.multiplicity
+    }
+}
+
+/// The `FinalReport` with sites collapsed into symmetry-distinct classes.
+#[derive(Debug, Clone)]
+pub struct SymmetryReducedReport {
+    operations_num: usize,
+    sphere_sites: Vec<SiteClass<BondingSphere>>,
+    circles: Vec<SiteClass<BondingCircle>>,
+    arcs: Vec<SiteClass<BondingArc>>,
+    cut_points: Vec<SiteClass<CoordinationPoint>>,
+    multi_cn_points: Vec<SiteClass<CoordinationPoint>>,
+}
+
+impl SymmetryReducedReport {
+    pub fn new(final_report: &FinalReport, symmetry_finder: &SymmetryFinder) -> Self {
+        let operations = symmetry_finder.find_operations();
+        let lattice_model = symmetry_finder.lattice_model;
+        let tolerance = symmetry_finder.tolerance();
+        let classify_spheres = |sites: &[BondingSphere]| {
+            classify(sites, lattice_model, &operations, tolerance, |s| {
+                (s.sphere().center, s.sphere().radius, None)
+            })
+        };
+        let classify_circles = |sites: &[BondingCircle]| {
+            classify(sites, lattice_model, &operations, tolerance, |c| {
+                let circle = c.circle();
+                (
+                    circle.center,
+                    circle.radius,
+                    Some(circle.normal.into_inner()),
+                )
+            })
+        };
+        let classify_arcs = |sites: &[BondingArc]| {
+            classify(sites, lattice_model, &operations, tolerance, |a| {
+                (a.representative(), a.arc().radius(), None)
+            })
+        };
+        let classify_points = |sites: &[CoordinationPoint]| {
+            classify(sites, lattice_model, &operations, tolerance, |p| {
+                (p.coord(), p.cn() as f64, None)
+            })
+        };
+        Self {
+            operations_num: operations.len(),
+            sphere_sites: classify_spheres(final_report.sphere_sites()),
+            circles: classify_circles(final_report.circles()),
+            arcs: classify_arcs(final_report.arcs()),
+            cut_points: classify_points(final_report.cut_points()),
+            multi_cn_points: classify_points(final_report.multi_cn_points()),
+        }
+    }
+
+    pub fn operations_num(&self) -> usize {
+        self.operations_num
+    }
+
+    pub fn sphere_sites(&self) -> &[SiteClass<BondingSphere>] {
+        &self.sphere_sites
+    }
+
+    pub fn circles(&self) -> &[SiteClass<BondingCircle>] {
+        &self.circles
+    }
+
+    pub fn arcs(&self) -> &[SiteClass<BondingArc>] {
+        &self.arcs
+    }
+
+    pub fn cut_points(&self) -> &[SiteClass<CoordinationPoint>] {
+        &self.cut_points
+    }
+
+    pub fn multi_cn_points(&self) -> &[SiteClass<CoordinationPoint>] {
+        &self.multi_cn_points
+    }
+    /// A `FinalReport` with one representative site per class, for the export.
+    pub fn representatives(&self) -> FinalReport {
+        FinalReport::new(
+            representatives_of(&self.sphere_sites),
+            representatives_of(&self.circles),
+            representatives_of(&self.arcs),
+            representatives_of(&self.cut_points),
+            representatives_of(&self.multi_cn_points),
+        )
+    }
+
+    pub fn report_classes(&self) -> String {
+        let summary = |name: &str, multiplicities: Vec<usize>| {
+            let total: usize = multiplicities.iter().sum();
+            format!(
+                "{name}: {total} sites in {} classes, multiplicities: {:?}",
+                multiplicities.len(),
+                multiplicities
+            )
+        };
+        [
+            format!("Symmetry operations found: {}", self.operations_num),
+            summary("Spheres", multiplicities_of(&self.sphere_sites)),
+            summary("Circles", multiplicities_of(&self.circles)),
+            summary("Arcs", multiplicities_of(&self.arcs)),
+            summary("Cut points", multiplicities_of(&self.cut_points)),
+            summary("Multi points", multiplicities_of(&self.multi_cn_points)),
+        ]
+        .join("\n")
+    }
+}
+
+impl FinalReport {
+    /// Collapse the sites into symmetry-distinct classes by the space-group operations of the host structure.
+    pub fn reduce_by_symmetry(&self, lattice_model: &BasicLatticeModel) -> SymmetryReducedReport {
+        SymmetryReducedReport::new(self, &SymmetryFinder::new(lattice_model))
+    }
+}
+
+fn representatives_of<T: Clone>(classes: &[SiteClass<T>]) -> Vec<T> {
+    classes
+        .iter()
+        .map(|class| class.representative.clone())
+        .collect()
+}
+
+fn multiplicities_of<T>(classes: &[SiteClass<T>]) -> Vec<usize> {
+    classes.iter().map(|class| class.multiplicity).collect()
+}
+
+/// Group the sites by the symmetry operations.
+/// # Args:
+/// - key: returns the position of the site used for mapping, an invariant quantity
+///   (radius or coordination number) that must be equal within a class, and the unit axis
+///   of the site (the normal of a circle) which must be mapped as well, up to sign.
+fn classify<T, F>(
+    sites: &[T],
+    lattice_model: &BasicLatticeModel,
+    operations: &[Matrix4<f64>],
+    tolerance: f64,
+    key: F,
+) -> Vec<SiteClass<T>>
+where
+    T: Clone,
+    F: Fn(&T) -> (Point3<f64>, f64, Option<Vector3<f64>>),
+{
+    let lattice = match lattice_model.lattice_vectors() {
+        Some(lat) => lat,
+        None => {
+            return sites
+                .iter()
+                .map(|site| SiteClass {
+                    representative: site.clone(),
+                    multiplicity: 1,
+                })
+                .collect()
+        }
+    };
+    let lattice_matrix: Matrix3<f64> = *lattice.data();
+    let to_frac = lattice.mat_cart_to_frac();
+    let keys: Vec<(Point3<f64>, f64, Option<Vector3<f64>>)> = sites
+        .iter()
+        .map(|site| {
+            let (coord, invariant, axis) = key(site);
+            (to_frac * coord, invariant, axis)
+        })
+        .collect();
+    // The rotation part of each operation, in cartesian coordinates
+    let rotations: Vec<Matrix3<f64>> = operations
+        .iter()
+        .map(|op| lattice_matrix * op.fixed_view::<3, 3>(0, 0) * to_frac)
+        .collect();
+    let mut assigned = vec![false; sites.len()];
+    let mut classes: Vec<SiteClass<T>> = Vec::new();
+    for i in 0..sites.len() {
+        if assigned[i] {
+            continue;
+        }
+        assigned[i] = true;
+        let (rep_frac, rep_invariant, rep_axis) = keys[i];
+        let mut multiplicity = 1;
+        for j in (i + 1)..sites.len() {
+            if assigned[j] {
+                continue;
+            }
+            let (frac, invariant, axis) = keys[j];
+            if (invariant - rep_invariant).abs() > tolerance {
+                continue;
+            }
+            let is_equivalent = operations
+                .iter()
+                .zip(rotations.iter())
+                .any(|(op, rotation)| {
+                    is_mapped_to(&lattice_matrix, op, &rep_frac, &frac, tolerance)
+                        && match (rep_axis, axis) {
+                            // Parallel or antiparallel unit vectors
+                            (Some(rep_axis), Some(axis)) => {
+                                (rotation * rep_axis).cross(&axis).norm() < tolerance
+                            }
+                            _ => true,
+                        }
+                });
+            if is_equivalent {
+                assigned[j] = true;
+                multiplicity += 1;
+            }
+        }
+        classes.push(SiteClass {
+            representative: sites[i].clone(),
+            multiplicity,
+        });
+    }
+    classes
+}
@@ -1,4 +1,14 @@
 mod analyzer;
 mod result_output;
 
-pub use analyzer::{FinalReport, IntersectChecker, MountingChecker};
+pub use analyzer::{
+    AdatomRelaxer, AdsorptionSite, ArcRepresentative, BondConeFilter, CoverageConfiguration,
+    CoverageEnumerator, ExclusionMask, FinalReport, FingerprintFinder, FingerprintReducedReport,
+    FoundSite, GridSearch, IntersectChecker, IntersectSearch, InterstitialFinder,
+    InterstitialFinderBuilder, InterstitialSites, InterstitialVoid, LBEMountingChecker,
+    LennardJonesPotential, LocalEnvSearch, MolecularAdsorbate, MorsePotential, MountingChecker,
+    PairPotential, RankedReport, RelaxedPosition, ScoredSite, SearchStrategy, SiteClass,
+    SiteFingerprint, SitePlacement, SiteScorer, SiteStream, SurfaceSite, SurfaceSiteEnumerator,
+    SurfaceSiteKind, SurfaceSites, SymmetryFinder, SymmetryReducedReport, Tolerance,
+};
+pub use result_output::{ScanReport, SiteKind, SiteRecord};
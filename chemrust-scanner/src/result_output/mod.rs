@@ -1 +1,342 @@
+//! Machine-readable report of the found sites, in JSON or CSV.
+//! Each site is listed with its kind, position, the connecting atoms and the measured distances,
+//! and the seed folders its models are exported to.
+use chemrust_core::data::BasicLatticeModel;
+use nalgebra::{distance, Point3, Vector3};
+use serde::Serialize;
 
+use crate::analyzer::algorithm::{AtomImage, FinalReport};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SiteKind {
+    Sphere,
+    Circle,
+    Arc,
+    CutPoint,
+    MultiCnPoint,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SiteRecord {
+    kind: SiteKind,
+    /// Model name of the site, e.g., `cn_3_point_atom_0_4_7`
+    name: String,
+    /// Seed folders of the exported models, `{lattice_name}_{name}_opt`.
+    /// The rotations of a molecular adsorbate are exported to `{lattice_name}_{name}_rot_{k}_opt`,
+    /// and the samples of an arc to `{lattice_name}_{name}_sample_{i}_opt`.
+    /// Empty if no model is exported, e.g., the adsorbate clashes in all orientations.
+    seed_folders: Vec<String>,
+    cartesian_coord: [f64; 3],
+    /// `None` for the models without lattice vectors
+    fractional_coord: Option<[f64; 3]>,
+    coordination_number: usize,
+    /// Indices of the connecting atoms in the host model, starting from 0
+    connecting_atom_ids: Vec<usize>,
+    connecting_elements: Vec<String>,
+    /// Distances to the connecting atoms, to the nearest periodic images
+    distances: Vec<f64>,
+}
+
+impl SiteRecord {
+    pub fn kind(&self) -> SiteKind {
+        self.kind
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    pub fn seed_folders(&self) -> &[String] {
+        self.seed_folders.as_ref()
+    }
+
+    pub fn cartesian_coord(&self) -> [f64; 3] {
+        self.cartesian_coord
+    }
+
+    pub fn fractional_coord(&self) -> Option<[f64; 3]> {
+        self.fractional_coord
+    }
+
+    pub fn coordination_number(&self) -> usize {
+        self.coordination_number
+    }
+
+    pub fn connecting_atom_ids(&self) -> &[usize] {
+        self.connecting_atom_ids.as_ref()
+    }
+
+    pub fn connecting_elements(&self) -> &[String] {
+        self.connecting_elements.as_ref()
+    }
+
+    pub fn distances(&self) -> &[f64] {
+        self.distances.as_ref()
+    }
+}
+
+/// Flattened `SiteRecord` for a CSV row, the lists are joined by `;`.
+#[derive(Debug, Serialize)]
+struct CsvRow<'a> {
+    kind: SiteKind,
+    name: &'a str,
+    seed_folders: String,
+    x: f64,
+    y: f64,
+    z: f64,
+    frac_x: Option<f64>,
+    frac_y: Option<f64>,
+    frac_z: Option<f64>,
+    coordination_number: usize,
+    connecting_atom_ids: String,
+    connecting_elements: String,
+    distances: String,
+}
+
+impl<'a> From<&'a SiteRecord> for CsvRow<'a> {
+    fn from(record: &'a SiteRecord) -> Self {
+        let join = |items: Vec<String>| items.join(";");
+        let [x, y, z] = record.cartesian_coord;
+        Self {
+            kind: record.kind,
+            name: &record.name,
+            seed_folders: record.seed_folders.join(";"),
+            x,
+            y,
+            z,
+            frac_x: record.fractional_coord.map(|f| f[0]),
+            frac_y: record.fractional_coord.map(|f| f[1]),
+            frac_z: record.fractional_coord.map(|f| f[2]),
+            coordination_number: record.coordination_number,
+            connecting_atom_ids: join(
+                record
+                    .connecting_atom_ids
+                    .iter()
+                    .map(|i| i.to_string())
+                    .collect(),
+            ),
+            connecting_elements: record.connecting_elements.join(";"),
+            distances: join(record.distances.iter().map(|d| d.to_string()).collect()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanReport {
+    lattice_name: String,
+    sites: Vec<SiteRecord>,
+}
+
+impl ScanReport {
+    /// The sites are listed in the order of `FinalReport::adsorption_sites`:
+    /// spheres, circles, arcs (at the representatives), cut points and multi points.
+    /// The seed folders are left empty until given by `with_exported_names`.
+    pub fn new(
+        final_report: &FinalReport,
+        lattice_model: &BasicLatticeModel,
+        lattice_name: &str,
+    ) -> Self {
+        let connections: Vec<(SiteKind, Vec<AtomImage>)> = [
+            final_report
+                .sphere_sites()
+                .iter()
+                .map(|s| {
+                    (
+                        SiteKind::Sphere,
+                        vec![AtomImage::home(s.locating_atom_id())],
+                    )
+                })
+                .collect::<Vec<(SiteKind, Vec<AtomImage>)>>(),
+            final_report
+                .circles()
+                .iter()
+                .map(|c| (SiteKind::Circle, c.connecting_atoms().to_vec()))
+                .collect(),
+            final_report
+                .arcs()
+                .iter()
+                .map(|a| (SiteKind::Arc, a.connecting_atoms().to_vec()))
+                .collect(),
+            final_report
+                .cut_points()
+                .iter()
+                .map(|p| (SiteKind::CutPoint, p.connecting_atoms().to_vec()))
+                .collect(),
+            final_report
+                .multi_cn_points()
+                .iter()
+                .map(|p| (SiteKind::MultiCnPoint, p.connecting_atoms().to_vec()))
+                .collect(),
+        ]
+        .concat();
+        let sites = final_report
+            .adsorption_sites(lattice_model)
+            .into_iter()
+            .zip(connections)
+            .map(|((name, position, _), (kind, images))| {
+                let atoms = lattice_model.atoms();
+                let distances = images
+                    .iter()
+                    .map(|image| distance(&position, &image_coord(lattice_model, image)))
+                    .collect();
+                let fractional_coord = lattice_model
+                    .lattice_vectors()
+                    .map(|lattice| (lattice.mat_cart_to_frac() * position).into());
+                SiteRecord {
+                    kind,
+                    seed_folders: Vec::new(),
+                    name,
+                    cartesian_coord: position.into(),
+                    fractional_coord,
+                    coordination_number: images.len(),
+                    connecting_atom_ids: images.iter().map(|image| image.atom_id()).collect(),
+                    connecting_elements: images
+                        .iter()
+                        .map(|image| atoms[image.atom_id()].symbol().to_string())
+                        .collect(),
+                    distances,
+                }
+            })
+            .collect();
+        Self {
+            lattice_name: lattice_name.into(),
+            sites,
+        }
+    }
+
+    /// Record the seed folders of the models exported at each site,
+    /// given by `FinalReport::exported_names` in the same order as the sites.
+    pub fn with_exported_names(self, exported_names: &[Vec<String>]) -> Self {
+        let sites = self
+            .sites
+            .into_iter()
+            .zip(exported_names)
+            .map(|(record, names)| SiteRecord {
+                seed_folders: names
+                    .iter()
+                    .map(|name| format!("{}_{name}_opt", self.lattice_name))
+                    .collect(),
+                ..record
+            })
+            .collect();
+        Self { sites, ..self }
+    }
+
+    pub fn lattice_name(&self) -> &str {
+        self.lattice_name.as_ref()
+    }
+
+    pub fn sites(&self) -> &[SiteRecord] {
+        self.sites.as_ref()
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+    /// One site per row, with a header line.
+    pub fn to_csv(&self) -> csv::Result<String> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        self.sites
+            .iter()
+            .try_for_each(|record| writer.serialize(CsvRow::from(record)))?;
+        let bytes = writer
+            .into_inner()
+            .map_err(|e| csv::Error::from(e.into_error()))?;
+        Ok(String::from_utf8(bytes).expect("CSV written from strings and numbers"))
+    }
+}
+
+/// Cartesian coordinate of the periodic image of the atom.
+fn image_coord(lattice_model: &BasicLatticeModel, image: &AtomImage) -> Point3<f64> {
+    let coord = lattice_model.atoms()[image.atom_id()].cartesian_coord();
+    match lattice_model.lattice_vectors() {
+        Some(lattice) => coord + lattice.data() * Vector3::from(image.offset().map(|v| v as f64)),
+        None => coord,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chemrust_core::data::{Atom, BasicLatticeModel, LatticeVectors};
+    use nalgebra::{Matrix3, Point3, Vector3};
+
+    use crate::analyzer::{
+        algorithm::{AtomImage, CoordinationPoint, FinalReport},
+        MolecularAdsorbate,
+    };
+
+    use super::{ScanReport, SiteKind};
+
+    #[test]
+    fn scan_report() {
+        let lattice = LatticeVectors::new(Matrix3::from_diagonal(&Vector3::new(4.0, 4.0, 20.0)));
+        let atoms: Vec<Atom> = [("Cu", 0.0), ("O", 2.0)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(symbol, x))| {
+                Atom::new_builder()
+                    .with_symbol(symbol)
+                    .with_index(i)
+                    .with_coord(&Point3::new(x, 0.0, 5.0))
+                    .ready()
+                    .build()
+            })
+            .collect();
+        let model = BasicLatticeModel::new(&Some(lattice), &atoms);
+        // Bridging the Cu and the O across the cell boundary at x = 3.0
+        let point = CoordinationPoint::new(
+            Point3::new(3.0, 0.0, 5.0),
+            vec![AtomImage::new(0, [1, 0, 0]), AtomImage::home(1)],
+            2,
+        );
+        // Bonding to the Cu only, with the outward normal pointing to the O
+        let towards_o =
+            CoordinationPoint::new(Point3::new(1.0, 0.0, 5.0), vec![AtomImage::home(0)], 1);
+        let final_report = FinalReport::new(
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![point, towards_o],
+            Vec::new(),
+        );
+        let exported_names =
+            final_report.exported_names(&model, &MolecularAdsorbate::single_atom("O"), None);
+        let report =
+            ScanReport::new(&final_report, &model, "CuO").with_exported_names(&exported_names);
+        let site = &report.sites()[0];
+        assert_eq!(site.kind(), SiteKind::CutPoint);
+        assert_eq!(site.seed_folders(), ["CuO_cn_2_point_atom_0_1_opt"]);
+        assert_eq!(site.connecting_elements(), ["Cu", "O"]);
+        assert_eq!(site.fractional_coord(), Some([0.75, 0.0, 0.25]));
+        assert!(site.distances().iter().all(|d| (d - 1.0).abs() < 1e-6));
+        let json = report.to_json().unwrap();
+        assert!(json.contains("\"kind\": \"cut_point\""));
+        let csv = report.to_csv().unwrap();
+        let mut lines = csv.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("kind,name,seed_folders,x,y,z"));
+        assert!(lines.next().unwrap().contains(",2,0;1,Cu;O,1;1"));
+        // The O of a CO lands on the host O at the second site, so nothing is exported there
+        let co: Vec<Atom> = [("C", 0.0), ("O", 1.15)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(symbol, z))| {
+                Atom::new_builder()
+                    .with_symbol(symbol)
+                    .with_index(i)
+                    .with_coord(&Point3::new(0.0, 0.0, z))
+                    .ready()
+                    .build()
+            })
+            .collect();
+        let exported_names =
+            final_report.exported_names(&model, &MolecularAdsorbate::new(&co, 0), None);
+        let report =
+            ScanReport::new(&final_report, &model, "CuO").with_exported_names(&exported_names);
+        assert_eq!(report.sites()[1].name(), "cn_1_point_atom_0");
+        assert!(report.sites()[1].seed_folders().is_empty());
+    }
+}
@@ -1,11 +1,22 @@
-use std::{error::Error, fs, path::Path};
+use std::{collections::HashMap, error::Error, fs, path::Path};
 
 use castep_periodic_table::element::Element;
-use chemrust_core::data::{custom_data_type::FractionalCoordRange, BasicLatticeModel};
-use chemrust_parser::CellParser;
-use chemrust_scanner::{FinalReport, MountingChecker};
+use chemrust_core::data::{lattice::SurfaceSide, Atom, BasicLatticeModel};
+use chemrust_parser::{CellParser, XyzParser};
+use chemrust_scanner::{
+    AdatomRelaxer, CoverageEnumerator, ExclusionMask, FinalReport, FingerprintFinder,
+    FingerprintReducedReport, GridSearch, IntersectSearch, InterstitialFinder,
+    LennardJonesPotential, LocalEnvSearch, MorsePotential, MountingChecker, PairPotential,
+    SearchStrategy, SiteScorer, SurfaceSiteEnumerator,
+};
 
-use crate::{export_res::ExportManager, yaml_parser::TaskTable};
+use crate::{
+    export_res::ExportManager,
+    yaml_parser::{
+        ExclusionTable, FingerprintTable, HostElementTable, InterstitialTable, PotentialKind,
+        RankingTable, RelaxationTable, TaskTable,
+    },
+};
 
 mod run_modes;
 
@@ -15,39 +26,253 @@ pub struct Executor<'a> {
     cell_filepath: &'a Path,
     cell_model: BasicLatticeModel,
     radius: f64,
+    host_bondlengths: HashMap<String, f64>,
+    bondlength_window: Option<(f64, f64)>,
+    host_elements: HostElementTable,
+    /// Half-angle of the bond cones, in degrees
+    bond_cone_angle: Option<f64>,
+    exclusion_mask: Option<ExclusionMask>,
 }
 
 impl<'a> Executor<'a> {
+    /// A `.xyz` file is loaded as a molecule or cluster without lattice vectors.
     pub fn new(new_element: &'a Element, cell_filepath: &'a Path, radius: f64) -> Self {
         let cell_text = fs::read_to_string(cell_filepath).unwrap();
-        let cell_model = CellParser::new(&cell_text)
-            .to_lattice_cart()
-            .to_positions()
-            .build_lattice();
+        let cell_model = if cell_filepath.extension().is_some_and(|ext| ext == "xyz") {
+            let atoms = XyzParser::new(&cell_text).parse_atoms().unwrap();
+            BasicLatticeModel::new(&None, &atoms)
+        } else {
+            CellParser::new(&cell_text)
+                .to_lattice_cart()
+                .to_positions()
+                .build_lattice()
+        };
         Self {
             new_element,
             cell_filepath,
             cell_model,
             radius,
+            host_bondlengths: HashMap::new(),
+            bondlength_window: None,
+            host_elements: HostElementTable::default(),
+            bond_cone_angle: None,
+            exclusion_mask: None,
+        }
+    }
+    /// Use specific bondlengths to the given host elements.
+    pub fn with_host_bondlengths(self, host_bondlengths: &HashMap<String, f64>) -> Self {
+        Self {
+            host_bondlengths: host_bondlengths.clone(),
+            ..self
         }
     }
+    /// Accept the sites with bondlengths within the `(min, max)` window.
+    pub fn with_bondlength_window(self, bondlength_window: Option<(f64, f64)>) -> Self {
+        Self {
+            bondlength_window,
+            ..self
+        }
+    }
+    /// Restrict the host elements bonding to the new element.
+    pub fn with_host_elements(self, host_elements: Option<&HostElementTable>) -> Self {
+        Self {
+            host_elements: host_elements.cloned().unwrap_or_default(),
+            ..self
+        }
+    }
+    /// Discard the sites inside the cones along the existing bonds, with the half-angle in degrees.
+    pub fn with_bond_cone_angle(self, bond_cone_angle: Option<f64>) -> Self {
+        Self {
+            bond_cone_angle,
+            ..self
+        }
+    }
+    /// Discard the sites around the masked atoms.
+    pub fn with_exclusion(
+        self,
+        exclusion: Option<&ExclusionTable>,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            exclusion_mask: exclusion.map(|table| table.exclusion_mask()).transpose()?,
+            ..self
+        })
+    }
 
-    fn search(
-        &self,
-        x_range: FractionalCoordRange,
-        y_range: FractionalCoordRange,
-        z_range: FractionalCoordRange,
-    ) -> Result<FinalReport, Box<dyn Error>> {
+    /// The `MountingChecker` with the bonding settings of the executor.
+    fn mount_checker(&self) -> MountingChecker {
         let mount_checker = MountingChecker::new_builder()
             .with_element(self.new_element)
             .with_bondlength(self.radius)
-            .build();
-        let filtered_atoms = self.cell_model.xyz_range_filter(x_range, y_range, z_range);
-        if !filtered_atoms.is_empty() {
-            Ok(mount_checker.mount_search(self.cell_model.atoms(), &filtered_atoms))
-        } else {
+            .with_host_bondlengths(&self.host_bondlengths)
+            .with_forbidden_elements(&self.host_elements.forbidden)
+            .with_bonded_pairs_only(self.host_elements.bonded_pairs_only);
+        let mount_checker = match &self.host_elements.allowed {
+            Some(allowed) => mount_checker.with_allowed_elements(allowed),
+            None => mount_checker,
+        };
+        let mount_checker = match self.bond_cone_angle {
+            Some(angle) => mount_checker.with_bond_cone_filter(angle.to_radians()),
+            None => mount_checker,
+        };
+        let mount_checker = match &self.exclusion_mask {
+            Some(mask) => mount_checker.with_exclusion_mask(mask.clone()),
+            None => mount_checker,
+        };
+        match self.bondlength_window {
+            Some((min, max)) => mount_checker.with_bondlength_window(min, max),
+            None => mount_checker,
+        }
+        .build()
+    }
+    /// The atoms within the cartesian ranges if given, otherwise within the fractional ranges.
+    /// With `region`, only the atoms inside the region are kept.
+    fn range_filter(&self, config_table: &TaskTable) -> Result<Vec<Atom>, Box<dyn Error>> {
+        let filtered_atoms = match config_table.cartesian_range() {
+            Some(table) => self.cell_model.cartesian_range_filter(
+                table.x_range()?,
+                table.y_range()?,
+                table.z_range()?,
+            ),
+            None => self.cell_model.xyz_range_filter(
+                config_table.x_range(),
+                config_table.y_range(),
+                config_table.z_range(),
+            ),
+        };
+        let filtered_atoms = match config_table.region()? {
+            Some(region) => {
+                let in_region = self.cell_model.region_filter(&region);
+                filtered_atoms
+                    .into_iter()
+                    .filter(|atom| in_region.iter().any(|a| a.index() == atom.index()))
+                    .collect()
+            }
+            None => filtered_atoms,
+        };
+        Ok(filtered_atoms)
+    }
+    /// Search around the atoms selected by the ranges.
+    /// With `surface`, only the surface atoms on the given side are searched.
+    /// The sites are found by the given `strategy`.
+    fn search(
+        &self,
+        surface: Option<SurfaceSide>,
+        mut filtered_atoms: Vec<Atom>,
+        strategy: &dyn SearchStrategy,
+    ) -> Result<FinalReport, Box<dyn Error>> {
+        let mount_checker = self.mount_checker();
+        if let Some(side) = surface {
+            let surface_atoms = self.cell_model.surface_filter(side);
+            filtered_atoms.retain(|atom| {
+                surface_atoms
+                    .iter()
+                    .any(|surface_atom| surface_atom.index() == atom.index())
+            });
+        }
+        if filtered_atoms.is_empty() {
             panic!("No atoms found in this range")
+        } else {
+            Ok(mount_checker.search_with(strategy, &self.cell_model, &filtered_atoms))
+        }
+    }
+    /// Enumerate the top, bridge and hollow sites on the given side, `Top` by default.
+    /// The new atom is placed at `adsorption_height` above the sites if given, otherwise at the target bondlength.
+    fn enumerate_sites(
+        &self,
+        surface: Option<SurfaceSide>,
+        adsorption_height: Option<f64>,
+    ) -> Result<FinalReport, Box<dyn Error>> {
+        let enumerator = SurfaceSiteEnumerator::new_builder()
+            .with_element(self.new_element)
+            .with_side(surface.unwrap_or(SurfaceSide::Top));
+        let enumerator = match adsorption_height {
+            Some(height) => enumerator.with_height(height),
+            None => enumerator.with_bondlength(self.radius),
+        }
+        .build();
+        let sites = enumerator.enumerate(&self.cell_model);
+        println!("{}", sites.report_sites());
+        Ok(sites.report())
+    }
+    /// Find the interstitial voids of the bulk model, at least `min_radius` large.
+    fn find_interstitials(&self, table: &InterstitialTable) -> Result<FinalReport, Box<dyn Error>> {
+        let finder = InterstitialFinder::new_builder().with_min_radius(table.min_radius);
+        let finder = match table.cutoff {
+            Some(cutoff) => finder.with_cutoff(cutoff),
+            None => finder,
         }
+        .build();
+        let sites = finder.find(&self.cell_model);
+        println!("{}", sites.report_voids());
+        Ok(sites.report())
+    }
+    /// Group the sites with near-identical local environments, and keep one site per group.
+    fn reduce_by_fingerprint(
+        &self,
+        final_stage: &FinalReport,
+        table: &FingerprintTable,
+    ) -> FinalReport {
+        let finder = FingerprintFinder::new(&self.cell_model);
+        let finder = match table.shell {
+            Some(shell) => finder.with_shell(shell),
+            None => finder,
+        };
+        let finder = match table.tolerance {
+            Some(tolerance) => finder.with_tolerance(tolerance),
+            None => finder,
+        };
+        let reduced = FingerprintReducedReport::new(final_stage, &finder);
+        println!("{}", reduced.report_groups());
+        reduced.representatives()
+    }
+    /// Score the sites by the pair potential, and keep the `top_n` best ones and those below `max_score`.
+    /// All the sites are kept if neither is set.
+    fn rank_sites(&self, final_stage: &FinalReport, table: &RankingTable) -> FinalReport {
+        let scorer = SiteScorer::new(self.new_element);
+        let scorer = match table.coordination_bonus {
+            Some(bonus) => scorer.with_coordination_bonus(bonus),
+            None => scorer,
+        };
+        let ranked = scorer.rank(final_stage, &self.cell_model);
+        println!("{}", ranked.report_scores());
+        ranked.select(table.top_n, table.max_score)
+    }
+    /// Relax the new atom at the sites with the host frozen, and merge the sites converged together.
+    /// The bonds are found by the target bondlengths of the search.
+    fn relax_sites(&self, final_stage: &FinalReport, table: &RelaxationTable) -> FinalReport {
+        let bondlengths = self.mount_checker().bondlengths_in(&self.cell_model);
+        match table.potential {
+            PotentialKind::Morse => {
+                let potential = MorsePotential::new(&bondlengths);
+                let potential = match table.well_depth {
+                    Some(well_depth) => potential.with_well_depth(well_depth),
+                    None => potential,
+                };
+                self.relax_with(potential, &bondlengths, final_stage, table)
+            }
+            PotentialKind::LennardJones => {
+                let potential = LennardJonesPotential::new(self.new_element, &self.cell_model);
+                self.relax_with(potential, &bondlengths, final_stage, table)
+            }
+        }
+    }
+    fn relax_with<P: PairPotential>(
+        &self,
+        potential: P,
+        bondlengths: &[f64],
+        final_stage: &FinalReport,
+        table: &RelaxationTable,
+    ) -> FinalReport {
+        let relaxer = AdatomRelaxer::new(potential, bondlengths);
+        let relaxer = match table.force_tolerance {
+            Some(force_tolerance) => relaxer.with_force_tolerance(force_tolerance),
+            None => relaxer,
+        };
+        let relaxer = match table.max_steps {
+            Some(max_steps) => relaxer.with_max_steps(max_steps),
+            None => relaxer,
+        };
+        relaxer.relax(final_stage, &self.cell_model)
     }
     fn export_manager(&self, export_loc: &str, potential_loc: &str, edft: bool) -> ExportManager {
         let lattice_name = self.cell_filepath.file_stem().unwrap().to_str().unwrap();
@@ -66,37 +291,87 @@ impl<'a> Executor<'a> {
             edft,
         )
     }
+    /// Export the models of the sites with the settings of the task table.
     fn export(
         &self,
-        export_loc: &str,
-        potential_loc: &str,
-        edft: bool,
+        config_table: &TaskTable,
         final_stage: &FinalReport,
     ) -> Result<(), Box<dyn Error>> {
-        let manager = self.export_manager(export_loc, potential_loc, edft);
+        let cwd = env!("CARGO_MANIFEST_DIR");
+        let manager = self.export_manager(
+            config_table.export_dir(),
+            config_table
+                .potential_dir()
+                .unwrap_or(&format!("{}/../Potentials", cwd)),
+            config_table.edft(),
+        );
+        let manager = match config_table.adsorbate() {
+            Some(table) => manager.with_adsorbate(table.load_adsorbate()?),
+            None => manager,
+        };
+        let manager = match config_table.vacuum() {
+            Some(vacuum) => manager.with_vacuum(vacuum),
+            None => manager,
+        };
         manager.export_points_model(final_stage, &self.cell_model)?;
         manager.export_circles_model(final_stage, &self.cell_model)?;
+        manager.export_arcs_model(final_stage, &self.cell_model, config_table.arc_samples())?;
         manager.export_sphere_model(final_stage, &self.cell_model)?;
         manager.overall_in_one(final_stage, &self.cell_model)?;
+        manager.export_report(final_stage, &self.cell_model, config_table.arc_samples())?;
+        if let Some(coverage) = config_table.coverage() {
+            let enumerator = CoverageEnumerator::new(
+                &self.cell_model,
+                coverage.max_adsorbates,
+                coverage.min_spacing,
+            );
+            let enumerator = match coverage.max_configurations {
+                Some(max) => enumerator.with_max_configurations(max),
+                None => enumerator,
+            };
+            manager.export_coverage_models(final_stage, &self.cell_model, &enumerator)?;
+        }
         Ok(())
     }
 }
 
 impl<'a> Executor<'a> {
     pub fn run(&self, config_table: &TaskTable) -> Result<(), Box<dyn Error>> {
-        let final_stage = self.search(
-            config_table.x_range(),
-            config_table.y_range(),
-            config_table.z_range(),
-        )?;
-        let cwd = env!("CARGO_MANIFEST_DIR");
-        self.export(
-            config_table.export_dir(),
-            config_table
-                .potential_dir()
-                .unwrap_or(&format!("{}/../Potentials", cwd)),
-            config_table.edft(),
-            &final_stage,
-        )
+        let final_stage = if config_table.site_enumeration() {
+            self.enumerate_sites(config_table.surface(), config_table.adsorption_height())?
+        } else if let Some(table) = config_table.interstitial() {
+            self.find_interstitials(table)?
+        } else {
+            let strategy: Box<dyn SearchStrategy> = match config_table.grid_spacing() {
+                Some(spacing) => Box::new(GridSearch::new(spacing)),
+                None if config_table.local_bonding_env() => Box::new(LocalEnvSearch),
+                None => Box::new(IntersectSearch),
+            };
+            self.search(
+                config_table.surface(),
+                self.range_filter(config_table)?,
+                strategy.as_ref(),
+            )?
+        };
+        let final_stage = match config_table.relaxation() {
+            Some(table) => self.relax_sites(&final_stage, table),
+            None => final_stage,
+        };
+        let final_stage = if config_table.symmetry_reduce() {
+            let reduced = final_stage.reduce_by_symmetry(&self.cell_model);
+            println!("{}", reduced.report_classes());
+            reduced.representatives()
+        } else {
+            final_stage
+        };
+        let final_stage = match config_table.fingerprint_reduce() {
+            Some(table) => self.reduce_by_fingerprint(&final_stage, table),
+            None => final_stage,
+        };
+        let final_stage = match config_table.ranking() {
+            Some(table) => self.rank_sites(&final_stage, table),
+            None => final_stage,
+        };
+        self.export(config_table, &final_stage)
     }
 }
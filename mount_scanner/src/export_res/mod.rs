@@ -13,7 +13,7 @@ use chemrust_formats::{
 };
 use chemrust_parser::CellParser;
 
-use chemrust_scanner::FinalReport;
+use chemrust_scanner::{CoverageEnumerator, FinalReport, MolecularAdsorbate, ScanReport};
 use glob::glob;
 use rayon::prelude::*;
 
@@ -22,10 +22,14 @@ const CWD: &str = env!("CARGO_MANIFEST_DIR");
 #[derive(Debug, Clone)]
 pub struct ExportManager {
     new_element_symbol: String,
+    /// What to mount at the sites, the single new element by default
+    adsorbate: MolecularAdsorbate,
     export_loc_str: String,
     potential_loc_str: String,
     lattice_name: String,
     edft: bool,
+    /// Vacuum padding on each side of the box for the models without lattice vectors, in angstrom
+    vacuum: f64,
 }
 
 impl ExportManager {
@@ -38,12 +42,35 @@ impl ExportManager {
     ) -> Self {
         Self {
             new_element_symbol: new_element_symbol.into(),
+            adsorbate: MolecularAdsorbate::single_atom(new_element_symbol),
             export_loc_str: export_loc_str.into(),
             potential_loc_str: potential_loc_str.into(),
             lattice_name: lattice_name.into(),
             edft,
+            vacuum: 10.0,
         }
     }
+    /// Mount a molecule instead of the single new element.
+    pub fn with_adsorbate(self, adsorbate: MolecularAdsorbate) -> Self {
+        Self { adsorbate, ..self }
+    }
+    /// Vacuum padding of the box for the models without lattice vectors, 10 Å by default.
+    pub fn with_vacuum(self, vacuum: f64) -> Self {
+        Self { vacuum, ..self }
+    }
+    /// Put the model of a molecule or cluster into the vacuum box of the original model,
+    /// so all exported models of the same host share the same box.
+    fn boxed(
+        &self,
+        model: BasicLatticeModel,
+        original_lattice_model: &BasicLatticeModel,
+    ) -> BasicLatticeModel {
+        if model.lattice_vectors().is_some() {
+            return model;
+        }
+        let (lattice_vectors, translation) = original_lattice_model.vacuum_box(self.vacuum);
+        model.placed_in_box(lattice_vectors, &translation)
+    }
     fn generate_seed_file(
         &self,
         cell_file: StructureFile<Cell>,
@@ -67,6 +94,7 @@ impl ExportManager {
     ) -> Result<(), Box<dyn Error>> {
         let spheres = final_report.visualize_specific_sites(final_report.sphere_sites());
         let circles = final_report.visualize_specific_sites(final_report.circles());
+        let arcs = final_report.visualize_specific_sites(final_report.arcs());
         let points = final_report.visualize_specific_sites(
             &[final_report.cut_points(), final_report.multi_cn_points()].concat(),
         );
@@ -78,6 +106,10 @@ impl ExportManager {
             self.export_per_sites(&circles, original_lattice_model, "double_circles")?;
             println!("Export all double circles")
         }
+        if !arcs.is_empty() {
+            self.export_per_sites(&arcs, original_lattice_model, "double_arcs")?;
+            println!("Export all double arcs")
+        }
         if !points.is_empty() {
             self.export_per_sites(&points, original_lattice_model, "multi_points")?;
             println!("Export all multi points")
@@ -90,9 +122,11 @@ impl ExportManager {
         original_lattice_model: &BasicLatticeModel,
     ) -> StructureFile<Cell> {
         let new_atoms = [original_lattice_model.atoms(), new_atoms].concat();
-        let new_lattice_vectors = original_lattice_model.lattice_vectors().unwrap().clone();
-        let new_lattice = BasicLatticeModel::new(&Some(new_lattice_vectors), &new_atoms);
-        StructureFile::<Cell>::new(new_lattice)
+        let new_lattice = BasicLatticeModel::new(
+            &original_lattice_model.lattice_vectors().cloned(),
+            &new_atoms,
+        );
+        StructureFile::<Cell>::new(self.boxed(new_lattice, original_lattice_model))
     }
     fn export_per_sites(
         &self,
@@ -116,10 +150,11 @@ impl ExportManager {
         original_lattice_model: &BasicLatticeModel,
     ) -> Result<(), Box<dyn Error>> {
         if let Some(spheres_res) =
-            final_report.generate_sphere_models(original_lattice_model, &self.new_element_symbol)
+            final_report.generate_sphere_models(original_lattice_model, &self.adsorbate)
         {
             spheres_res.into_iter().try_for_each(|(name, model)| {
-                let cell_output = StructureFile::<Cell>::new(model);
+                let cell_output =
+                    StructureFile::<Cell>::new(self.boxed(model, original_lattice_model));
                 let export_name = format!("{}_{}", &self.lattice_name, name);
                 self.generate_seed_file(cell_output, &export_name)
             })?
@@ -132,10 +167,30 @@ impl ExportManager {
         original_lattice_model: &BasicLatticeModel,
     ) -> Result<(), Box<dyn Error>> {
         if let Some(circle_res) =
-            final_report.generate_circle_models(original_lattice_model, &self.new_element_symbol)
+            final_report.generate_circle_models(original_lattice_model, &self.adsorbate)
         {
             circle_res.into_iter().try_for_each(|(name, model)| {
-                let cell_output = StructureFile::<Cell>::new(model);
+                let cell_output =
+                    StructureFile::<Cell>::new(self.boxed(model, original_lattice_model));
+                let export_name = format!("{}_{}", &self.lattice_name, name);
+                self.generate_seed_file(cell_output, &export_name)
+            })?
+        }
+        Ok(())
+    }
+    /// Export the arcs at the representative positions, or `samples` positions per arc.
+    pub fn export_arcs_model(
+        &self,
+        final_report: &FinalReport,
+        original_lattice_model: &BasicLatticeModel,
+        samples: Option<usize>,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(arc_res) =
+            final_report.generate_arc_models(original_lattice_model, &self.adsorbate, samples)
+        {
+            arc_res.into_iter().try_for_each(|(name, model)| {
+                let cell_output =
+                    StructureFile::<Cell>::new(self.boxed(model, original_lattice_model));
                 let export_name = format!("{}_{}", &self.lattice_name, name);
                 self.generate_seed_file(cell_output, &export_name)
             })?
@@ -148,25 +203,61 @@ impl ExportManager {
         original_lattice_model: &BasicLatticeModel,
     ) -> Result<(), io::Error> {
         if let Some(cut_point_res) =
-            final_report.generate_cut_point_models(original_lattice_model, &self.new_element_symbol)
+            final_report.generate_cut_point_models(original_lattice_model, &self.adsorbate)
         {
             cut_point_res.into_iter().try_for_each(|(name, model)| {
-                let cell_output = StructureFile::<Cell>::new(model);
+                let cell_output =
+                    StructureFile::<Cell>::new(self.boxed(model, original_lattice_model));
                 let export_name = format!("{}_{}", &self.lattice_name, name);
                 self.generate_seed_file(cell_output, &export_name)
             })?
         }
-        if let Some(multi_point_res) = final_report
-            .generate_multi_point_models(original_lattice_model, &self.new_element_symbol)
+        if let Some(multi_point_res) =
+            final_report.generate_multi_point_models(original_lattice_model, &self.adsorbate)
         {
             multi_point_res.into_iter().try_for_each(|(name, model)| {
-                let cell_output = StructureFile::<Cell>::new(model);
+                let cell_output =
+                    StructureFile::<Cell>::new(self.boxed(model, original_lattice_model));
                 let export_name = format!("{}_{}", &self.lattice_name, name);
                 self.generate_seed_file(cell_output, &export_name)
             })?
         }
         Ok(())
     }
+    /// Write the report of all sites as `{lattice_name}_sites.json` and `{lattice_name}_sites.csv`
+    /// in the export directory, with the seed folders exported by the `export_*_model`.
+    pub fn export_report(
+        &self,
+        final_report: &FinalReport,
+        original_lattice_model: &BasicLatticeModel,
+        arc_samples: Option<usize>,
+    ) -> Result<(), Box<dyn Error>> {
+        let exported_names =
+            final_report.exported_names(original_lattice_model, &self.adsorbate, arc_samples);
+        let report = ScanReport::new(final_report, original_lattice_model, &self.lattice_name)
+            .with_exported_names(&exported_names);
+        let export_name = format!("{}/{}_sites", self.export_loc_str, self.lattice_name);
+        fs::write(format!("{export_name}.json"), report.to_json()?)?;
+        fs::write(format!("{export_name}.csv"), report.to_csv()?)?;
+        Ok(())
+    }
+    /// Export the symmetry-distinct configurations of multiple adsorbates.
+    pub fn export_coverage_models(
+        &self,
+        final_report: &FinalReport,
+        original_lattice_model: &BasicLatticeModel,
+        enumerator: &CoverageEnumerator,
+    ) -> Result<(), io::Error> {
+        enumerator
+            .generate_models(final_report, &self.adsorbate)
+            .into_iter()
+            .try_for_each(|(name, model)| {
+                let cell_output =
+                    StructureFile::<Cell>::new(self.boxed(model, original_lattice_model));
+                let export_name = format!("{}_{}", &self.lattice_name, name);
+                self.generate_seed_file(cell_output, &export_name)
+            })
+    }
 }
 
 /// Copy the extension and rename to the model name.
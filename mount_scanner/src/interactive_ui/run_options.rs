@@ -1,8 +1,8 @@
-use std::path::Path;
+use std::{collections::HashMap, path::Path};
 
 use castep_periodic_table::element::Element;
 use chemrust_core::data::custom_data_type::FractionalCoordRange;
-use inquire::{required, validator::Validation, CustomType, InquireError, Text};
+use inquire::{required, validator::Validation, Confirm, CustomType, InquireError, Text};
 
 use crate::yaml_parser::TaskTable;
 
@@ -16,6 +16,7 @@ pub struct RunOptions {
     x_range: FractionalCoordRange,
     y_range: FractionalCoordRange,
     z_range: FractionalCoordRange,
+    symmetry_reduce: bool,
 }
 
 impl RunOptions {
@@ -62,6 +63,12 @@ impl RunOptions {
             .prompt()?;
         Ok(FractionalCoordRange::new(min, max))
     }
+    fn ask_symmetry_reduce() -> Result<bool, InquireError> {
+        Confirm::new("Export only the symmetry-distinct sites?(y/n or yes/no)")
+            .with_help_message("Sites equivalent by the space-group operations of the model are collapsed into one")
+            .with_default(true)
+            .prompt()
+    }
     pub fn new() -> Result<RunOptions, InquireError> {
         let filename = Self::ask_filename()?;
         let new_element = Self::ask_element()?;
@@ -69,6 +76,7 @@ impl RunOptions {
         let x_range = Self::ask_frac_range("x-axis")?;
         let y_range = Self::ask_frac_range("y-axis")?;
         let z_range = Self::ask_frac_range("z-axis")?;
+        let symmetry_reduce = Self::ask_symmetry_reduce()?;
         Ok(RunOptions {
             filepath: filename,
             new_element: new_element.clone(),
@@ -76,6 +84,7 @@ impl RunOptions {
             x_range,
             y_range,
             z_range,
+            symmetry_reduce,
         })
     }
 
@@ -94,13 +103,34 @@ impl RunOptions {
             model_path,
             new_element,
             target_bondlength,
+            host_bondlengths: HashMap::new(),
+            bondlength_window: None,
+            host_elements: None,
+            bond_cone_angle: None,
+            exclusion: None,
+            surface: None,
             x_range: (self.x_range.min(), self.x_range.max()),
             y_range: (self.y_range.min(), self.y_range.max()),
             z_range: (self.z_range.min(), self.z_range.max()),
+            cartesian_range: None,
+            region: None,
+            vacuum: None,
             export_dir: export_options.export_dir().to_string(),
             potential_dir: Some(export_options.potential_dir().into()),
             kpoint_quality: export_options.kpoint_quality().clone(),
             edft: export_options.edft(),
+            symmetry_reduce: self.symmetry_reduce,
+            arc_samples: None,
+            site_enumeration: false,
+            local_bonding_env: false,
+            grid_spacing: None,
+            interstitial: None,
+            fingerprint_reduce: None,
+            relaxation: None,
+            ranking: None,
+            adsorption_height: None,
+            adsorbate: None,
+            coverage: None,
         })
     }
 
@@ -127,4 +157,8 @@ impl RunOptions {
     pub fn z_range(&self) -> FractionalCoordRange {
         self.z_range
     }
+
+    pub fn symmetry_reduce(&self) -> bool {
+        self.symmetry_reduce
+    }
 }
@@ -33,7 +33,12 @@ fn run_by_config(yaml_config_path: Option<String>) -> Result<(), Box<dyn Error>>
         yaml_table.new_element(),
         Path::new(yaml_table.model_path()),
         yaml_table.target_bondlength(),
-    );
+    )
+    .with_host_bondlengths(yaml_table.host_bondlengths())
+    .with_bondlength_window(yaml_table.bondlength_window())
+    .with_host_elements(yaml_table.host_elements())
+    .with_bond_cone_angle(yaml_table.bond_cone_angle())
+    .with_exclusion(yaml_table.exclusion())?;
     executor.run(&yaml_table)
 }
 
@@ -45,7 +50,12 @@ fn interactive_cli() -> Result<(), Box<dyn Error>> {
         yaml_table.new_element(),
         Path::new(yaml_table.model_path()),
         yaml_table.target_bondlength(),
-    );
+    )
+    .with_host_bondlengths(yaml_table.host_bondlengths())
+    .with_bondlength_window(yaml_table.bondlength_window())
+    .with_host_elements(yaml_table.host_elements())
+    .with_bond_cone_angle(yaml_table.bond_cone_angle())
+    .with_exclusion(yaml_table.exclusion())?;
     executor.run(&yaml_table)?;
     let export_table_filename = format!(
         "{}/{}.yaml",
@@ -1,11 +1,16 @@
 #![allow(dead_code)]
-use std::{error::Error, path::Path};
+use std::{collections::HashMap, error::Error, path::Path};
 
 use castep_periodic_table::{
     data::ELEMENT_TABLE,
     element::{Element, LookupElement},
 };
-use chemrust_core::data::custom_data_type::FractionalCoordRange;
+use chemrust_core::data::{
+    custom_data_type::{CartesianCoordRange, FractionalCoordRange, Region},
+    lattice::SurfaceSide,
+};
+use chemrust_scanner::{ExclusionMask, MolecularAdsorbate};
+use nalgebra::{Point3, Vector3};
 use serde::{Deserialize, Serialize};
 
 use crate::interactive_ui::KPointQuality;
@@ -15,13 +20,353 @@ pub struct TaskTable {
     pub(crate) model_path: String,
     pub(crate) new_element: String,
     pub(crate) target_bondlength: f64,
+    /// Bondlengths to specific host elements, overriding `target_bondlength`
+    #[serde(default)]
+    pub(crate) host_bondlengths: HashMap<String, f64>,
+    /// Accepted `(min, max)` bondlength window around `target_bondlength`
+    #[serde(default)]
+    pub(crate) bondlength_window: Option<(f64, f64)>,
+    /// Host elements allowed or forbidden to bond to `new_element`
+    #[serde(default)]
+    pub(crate) host_elements: Option<HostElementTable>,
+    /// Half-angle in degrees of the cones along the existing host bonds;
+    /// the sites inside the cones, collinear with or behind the bonds, are discarded
+    #[serde(default)]
+    pub(crate) bond_cone_angle: Option<f64>,
+    /// Exclude the sites around the existing adsorbates or defects
+    #[serde(default)]
+    pub(crate) exclusion: Option<ExclusionTable>,
+    /// Select the atoms accessible from the vacuum, instead of guessing the `z_range`.
+    /// The fractional ranges still apply, and default to the whole cell.
+    #[serde(default)]
+    pub(crate) surface: Option<SurfaceSelection>,
+    #[serde(default = "full_range")]
     pub(crate) x_range: (f64, f64),
+    #[serde(default = "full_range")]
     pub(crate) y_range: (f64, f64),
+    #[serde(default = "full_range")]
     pub(crate) z_range: (f64, f64),
+    /// Select the atoms by the cartesian ranges in angstrom instead of the fractional ranges,
+    /// e.g. for the molecules and clusters without lattice vectors
+    #[serde(default)]
+    pub(crate) cartesian_range: Option<CartesianRangeTable>,
+    /// Only the atoms inside the region, in addition to the ranges
+    #[serde(default)]
+    pub(crate) region: Option<RegionTable>,
+    /// Vacuum padding in angstrom on each side of the box exported for the models
+    /// without lattice vectors, 10 Å by default
+    #[serde(default)]
+    pub(crate) vacuum: Option<f64>,
     pub(crate) export_dir: String,
     pub(crate) potential_dir: Option<String>,
     pub(crate) kpoint_quality: KPointQuality,
     pub(crate) edft: bool,
+    /// Export one model per symmetry-distinct site
+    #[serde(default)]
+    pub(crate) symmetry_reduce: bool,
+    /// Export one model per group of sites with near-identical local environments
+    #[serde(default)]
+    pub(crate) fingerprint_reduce: Option<FingerprintTable>,
+    /// Export evenly spaced samples on each arc, instead of the representative position only
+    #[serde(default)]
+    pub(crate) arc_samples: Option<usize>,
+    /// Enumerate the top, bridge and hollow sites of the `surface` (default `Top`),
+    /// instead of the intersection-based search
+    #[serde(default)]
+    pub(crate) site_enumeration: bool,
+    /// Search only within the local bonding environment of each atom, avoiding the sites
+    /// on top of the existing bonds, instead of the intersection-based search over the whole model
+    #[serde(default)]
+    pub(crate) local_bonding_env: bool,
+    /// Scan the region on a grid with this spacing in angstrom, and cluster the grid points
+    /// within the bondlength windows into sites, instead of the intersection-based search
+    #[serde(default)]
+    pub(crate) grid_spacing: Option<f64>,
+    /// Find the interstitial voids of the bulk model to dope the new atom inside,
+    /// instead of the intersection-based search
+    #[serde(default)]
+    pub(crate) interstitial: Option<InterstitialTable>,
+    /// Height of the new atom above the enumerated sites, overriding `target_bondlength`
+    #[serde(default)]
+    pub(crate) adsorption_height: Option<f64>,
+    /// Relax the new atom at each site with the host frozen before the export
+    #[serde(default)]
+    pub(crate) relaxation: Option<RelaxationTable>,
+    /// Score the sites by the UFF Lennard-Jones potential, and export only the best ones
+    #[serde(default)]
+    pub(crate) ranking: Option<RankingTable>,
+    /// Mount a molecule at the sites, anchored by the atom of `new_element`
+    #[serde(default)]
+    pub(crate) adsorbate: Option<AdsorbateTable>,
+    /// Also export the configurations of multiple adsorbates
+    #[serde(default)]
+    pub(crate) coverage: Option<CoverageTable>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HostElementTable {
+    /// Only the atoms of these elements can bond, if given
+    #[serde(default)]
+    pub(crate) allowed: Option<Vec<String>>,
+    /// The atoms of these elements never bond
+    #[serde(default)]
+    pub(crate) forbidden: Vec<String>,
+    /// Exclude the element pairs not bonded by the `is_bonded` criteria at the target bondlength
+    #[serde(default)]
+    pub(crate) bonded_pairs_only: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CartesianRangeTable {
+    /// `(min, max)` of x in angstrom, unbounded if not given
+    #[serde(default)]
+    pub(crate) x: Option<(f64, f64)>,
+    #[serde(default)]
+    pub(crate) y: Option<(f64, f64)>,
+    #[serde(default)]
+    pub(crate) z: Option<(f64, f64)>,
+}
+
+impl CartesianRangeTable {
+    pub fn x_range(&self) -> Result<CartesianCoordRange, Box<dyn Error>> {
+        cartesian_range("x", self.x)
+    }
+    pub fn y_range(&self) -> Result<CartesianCoordRange, Box<dyn Error>> {
+        cartesian_range("y", self.y)
+    }
+    pub fn z_range(&self) -> Result<CartesianCoordRange, Box<dyn Error>> {
+        cartesian_range("z", self.z)
+    }
+}
+
+fn cartesian_range(
+    name: &str,
+    range: Option<(f64, f64)>,
+) -> Result<CartesianCoordRange, Box<dyn Error>> {
+    match range {
+        Some((min, max)) if min > max => {
+            Err(format!("Invalid cartesian range of {name}: min {min} > max {max}").into())
+        }
+        Some((min, max)) => Ok(CartesianCoordRange::new(min, max)),
+        None => Ok(CartesianCoordRange::unbounded()),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExclusionTable {
+    /// The sites within this distance of the masked atoms are discarded, in angstrom
+    pub(crate) radius: f64,
+    /// Masked atoms by index, starting from 0
+    #[serde(default)]
+    pub(crate) atoms: Vec<usize>,
+    /// The atoms inside any of the regions are masked
+    #[serde(default)]
+    pub(crate) regions: Vec<RegionTable>,
+}
+
+impl ExclusionTable {
+    pub fn exclusion_mask(&self) -> Result<ExclusionMask, Box<dyn Error>> {
+        self.regions.iter().try_fold(
+            ExclusionMask::new(self.radius).with_atoms(&self.atoms),
+            |mask, region| Ok(mask.with_region(Region::try_from(region)?)),
+        )
+    }
+}
+
+/// The `Region` of the atoms to scan, in angstrom and by atom index from 0,
+/// e.g. `{shape: AroundAtom, atom: 3, radius: 4.0}`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "shape")]
+pub enum RegionTable {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+    },
+    AroundAtom {
+        atom: usize,
+        radius: f64,
+    },
+    Cylinder {
+        origin: [f64; 3],
+        axis: [f64; 3],
+        radius: f64,
+    },
+    /// The side of the plane that `normal` points to
+    HalfSpace {
+        point: [f64; 3],
+        normal: [f64; 3],
+    },
+    /// Wraps around the cell when `lower > upper`
+    FractionalInterval {
+        axis: usize,
+        lower: f64,
+        upper: f64,
+    },
+    All {
+        regions: Vec<RegionTable>,
+    },
+    Any {
+        regions: Vec<RegionTable>,
+    },
+    Not {
+        region: Box<RegionTable>,
+    },
+}
+
+impl TryFrom<&RegionTable> for Region {
+    type Error = Box<dyn Error>;
+
+    fn try_from(value: &RegionTable) -> Result<Self, Self::Error> {
+        let region = match value {
+            RegionTable::Sphere { center, radius } => {
+                Region::sphere(Point3::from(*center), *radius)
+            }
+            RegionTable::AroundAtom { atom, radius } => Region::around_atom(*atom, *radius),
+            RegionTable::Cylinder {
+                origin,
+                axis,
+                radius,
+            } => Region::cylinder(Point3::from(*origin), Vector3::from(*axis), *radius),
+            RegionTable::HalfSpace { point, normal } => {
+                Region::half_space(Point3::from(*point), Vector3::from(*normal))
+            }
+            RegionTable::FractionalInterval { axis, lower, upper } => {
+                if *axis > 2 {
+                    return Err(format!(
+                        "Invalid fractional axis: {axis}, expected 0, 1 or 2 for a, b, c"
+                    )
+                    .into());
+                }
+                Region::fractional_interval(*axis, *lower, *upper)
+            }
+            RegionTable::All { regions } => Region::All(
+                regions
+                    .iter()
+                    .map(Region::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            RegionTable::Any { regions } => Region::Any(
+                regions
+                    .iter()
+                    .map(Region::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            RegionTable::Not { region } => Region::try_from(region.as_ref())?.complement(),
+        };
+        Ok(region)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InterstitialTable {
+    /// Smallest radius of the empty sphere in the voids, in angstrom
+    #[serde(default)]
+    pub(crate) min_radius: f64,
+    /// Range of the periodic images around the cell, in angstrom
+    #[serde(default)]
+    pub(crate) cutoff: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FingerprintTable {
+    /// Radius of the local environment, in angstrom
+    #[serde(default)]
+    pub(crate) shell: Option<f64>,
+    /// Largest difference of the matching distances, in angstrom
+    #[serde(default)]
+    pub(crate) tolerance: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RelaxationTable {
+    /// Pair potential between the new atom and the host atoms, `Morse` by default
+    #[serde(default)]
+    pub(crate) potential: PotentialKind,
+    /// Depth of the Morse wells at the target bondlengths, in eV
+    #[serde(default)]
+    pub(crate) well_depth: Option<f64>,
+    /// Stop when the force is below this value, in eV/Å
+    #[serde(default)]
+    pub(crate) force_tolerance: Option<f64>,
+    #[serde(default)]
+    pub(crate) max_steps: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub enum PotentialKind {
+    /// Wells at the target bondlengths
+    #[default]
+    Morse,
+    /// UFF van der Waals interactions
+    LennardJones,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RankingTable {
+    /// Export the sites with the `top_n` lowest scores
+    #[serde(default)]
+    pub(crate) top_n: Option<usize>,
+    /// Export the sites scored below this energy, in eV
+    #[serde(default)]
+    pub(crate) max_score: Option<f64>,
+    /// Energy subtracted per bond of the site, in eV
+    #[serde(default)]
+    pub(crate) coordination_bonus: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CoverageTable {
+    /// Up to this number of adsorbates in a model, from 2
+    pub(crate) max_adsorbates: usize,
+    /// Minimum distance between two adsorbates under PBC, in angstrom
+    pub(crate) min_spacing: f64,
+    /// Stop after this number of configurations
+    #[serde(default)]
+    pub(crate) max_configurations: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdsorbateTable {
+    /// Path to the `.xyz` or `.cell` fragment of the molecule
+    pub(crate) path: String,
+    /// Index of the atom bonding to the site in the fragment, starting from 0
+    pub(crate) anchor: usize,
+    /// Number of evenly spaced rotations about the outward normal of the site
+    #[serde(default)]
+    pub(crate) rotations: Option<usize>,
+}
+
+impl AdsorbateTable {
+    pub fn load_adsorbate(&self) -> Result<MolecularAdsorbate, Box<dyn Error>> {
+        let text = std::fs::read_to_string(&self.path)?;
+        let adsorbate = if self.path.ends_with(".xyz") {
+            MolecularAdsorbate::from_xyz(&text, self.anchor)?
+        } else {
+            MolecularAdsorbate::from_cell(&text, self.anchor)
+        };
+        Ok(adsorbate.with_rotations(self.rotations.unwrap_or(1)))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub enum SurfaceSelection {
+    Top,
+    Bottom,
+    Both,
+}
+
+impl From<SurfaceSelection> for SurfaceSide {
+    fn from(value: SurfaceSelection) -> Self {
+        match value {
+            SurfaceSelection::Top => SurfaceSide::Top,
+            SurfaceSelection::Bottom => SurfaceSide::Bottom,
+            SurfaceSelection::Both => SurfaceSide::Both,
+        }
+    }
+}
+
+fn full_range() -> (f64, f64) {
+    (0.0, 1.0)
 }
 
 impl TaskTable {
@@ -43,6 +388,26 @@ impl TaskTable {
         self.target_bondlength
     }
 
+    pub fn host_bondlengths(&self) -> &HashMap<String, f64> {
+        &self.host_bondlengths
+    }
+
+    pub fn bondlength_window(&self) -> Option<(f64, f64)> {
+        self.bondlength_window
+    }
+
+    pub fn host_elements(&self) -> Option<&HostElementTable> {
+        self.host_elements.as_ref()
+    }
+
+    pub fn bond_cone_angle(&self) -> Option<f64> {
+        self.bond_cone_angle
+    }
+
+    pub fn exclusion(&self) -> Option<&ExclusionTable> {
+        self.exclusion.as_ref()
+    }
+
     pub fn export_dir(&self) -> &str {
         self.export_dir.as_ref()
     }
@@ -55,6 +420,70 @@ impl TaskTable {
         self.edft
     }
 
+    pub fn symmetry_reduce(&self) -> bool {
+        self.symmetry_reduce
+    }
+
+    pub fn arc_samples(&self) -> Option<usize> {
+        self.arc_samples
+    }
+
+    pub fn site_enumeration(&self) -> bool {
+        self.site_enumeration
+    }
+
+    pub fn local_bonding_env(&self) -> bool {
+        self.local_bonding_env
+    }
+
+    pub fn grid_spacing(&self) -> Option<f64> {
+        self.grid_spacing
+    }
+
+    pub fn interstitial(&self) -> Option<&InterstitialTable> {
+        self.interstitial.as_ref()
+    }
+
+    pub fn fingerprint_reduce(&self) -> Option<&FingerprintTable> {
+        self.fingerprint_reduce.as_ref()
+    }
+
+    pub fn relaxation(&self) -> Option<&RelaxationTable> {
+        self.relaxation.as_ref()
+    }
+
+    pub fn ranking(&self) -> Option<&RankingTable> {
+        self.ranking.as_ref()
+    }
+
+    pub fn adsorption_height(&self) -> Option<f64> {
+        self.adsorption_height
+    }
+
+    pub fn adsorbate(&self) -> Option<&AdsorbateTable> {
+        self.adsorbate.as_ref()
+    }
+
+    pub fn coverage(&self) -> Option<&CoverageTable> {
+        self.coverage.as_ref()
+    }
+
+    pub fn surface(&self) -> Option<SurfaceSide> {
+        self.surface.map(SurfaceSide::from)
+    }
+
+    pub fn cartesian_range(&self) -> Option<&CartesianRangeTable> {
+        self.cartesian_range.as_ref()
+    }
+
+    pub fn region(&self) -> Result<Option<Region>, Box<dyn Error>> {
+        self.region.as_ref().map(Region::try_from).transpose()
+    }
+
+    pub fn vacuum(&self) -> Option<f64> {
+        self.vacuum
+    }
+
     pub fn potential_dir(&self) -> Option<&String> {
         self.potential_dir.as_ref()
     }
@@ -71,7 +500,10 @@ impl TaskTable {
 
 #[cfg(test)]
 mod test {
-    use super::TaskTable;
+    use chemrust_core::data::{custom_data_type::Region, BasicLatticeModel};
+    use nalgebra::Point3;
+
+    use super::{CartesianRangeTable, RegionTable, TaskTable};
 
     #[test]
     fn test_task_table() {
@@ -82,4 +514,40 @@ mod test {
         println!("{:#?}", task_table.x_range());
         println!("{}", serde_yaml::to_string(&task_table).unwrap());
     }
+
+    #[test]
+    fn region_table() {
+        let text = "
+shape: Any
+regions:
+  - {shape: AroundAtom, atom: 3, radius: 4.0}
+  - shape: Not
+    region: {shape: HalfSpace, point: [0.0, 0.0, 10.0], normal: [0.0, 0.0, -1.0]}
+";
+        let table: RegionTable = serde_yaml::from_str(text).unwrap();
+        let region = Region::try_from(&table).unwrap();
+        assert!(matches!(region, Region::Any(ref regions) if regions.len() == 2));
+        let model = BasicLatticeModel::new(&None, &[]);
+        assert!(region.contains(&Point3::new(0.0, 0.0, 12.0), &model));
+        assert!(!region.contains(&Point3::new(0.0, 0.0, 8.0), &model));
+    }
+
+    #[test]
+    fn cartesian_range_table() {
+        let table: CartesianRangeTable =
+            serde_yaml::from_str("{x: [2.0, 5.0], z: [8.0, 3.0]}").unwrap();
+        assert!(table.x_range().is_ok());
+        assert!(table.y_range().is_ok());
+        assert!(table.z_range().is_err());
+    }
+
+    #[test]
+    fn invalid_region_axis() {
+        let text = "
+shape: Not
+region: {shape: FractionalInterval, axis: 3, lower: 0.0, upper: 0.5}
+";
+        let table: RegionTable = serde_yaml::from_str(text).unwrap();
+        assert!(Region::try_from(&table).is_err());
+    }
 }